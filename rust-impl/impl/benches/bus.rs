@@ -0,0 +1,299 @@
+//! Throughput and latency of BUS running in process. Run with `cargo bench --bench bus`,
+//! pass `--quick` after `--` for fewer messages.
+//!
+//! Threaded BUS, which the `zmq::poll` event loop replaced, is measured by the first
+//! version of this file on the parent of commit 89d4628 "Run BUS on a single-threaded
+//! zmq::poll event loop". From root of repository:
+//!
+//! ```text
+//! git worktree add --detach ../bus-baseline 89d4628^
+//! mkdir ../bus-baseline/rust-impl/impl/benches
+//! git show 89d4628:rust-impl/impl/benches/bus.rs \
+//!     > ../bus-baseline/rust-impl/impl/benches/bus.rs
+//! printf '\n[[bench]]\nname = "bus"\nharness = false\n' \
+//!     >> ../bus-baseline/rust-impl/impl/Cargo.toml
+//! cd ../bus-baseline/rust-impl && cargo bench --bench bus
+//! ```
+//!
+//! Messages per second of two full runs on one machine, threaded BUS on 89d4628^ ->
+//! event loop on 89d4628:
+//!
+//! ```text
+//! publish (inproc)               351k, 345k -> 595k, 459k
+//! publish (tcp)                  95k, 100k (lost messages) -> 303k, 392k
+//! sequential requests (inproc)   18.3k, 19.3k -> 17.4k, 15.4k
+//! sequential requests (tcp)      6.5k, 10.6k -> 9.2k, 10.1k
+//! pipelined requests (inproc)    48.9k, 57.8k -> 59.7k, 70.4k
+//! pipelined requests (tcp)       36.8k, 46.5k -> 51.0k, 55.9k
+//! ```
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::spawn_bus;
+use common::spawn_service;
+use rust_impl::kind_topic;
+use rust_impl::BusClient;
+use rust_impl::BusConfig;
+use rust_impl::LatencyHistogram;
+use rust_impl::Shutdown;
+use rust_impl::Transport;
+use std::env;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use uuid::Uuid;
+use zeromq_messages::codec::encode_message;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::ValueMultiplicationRequest;
+use zeromq_messages::messages::ValueMultiplicationResponse;
+use zmq::Context;
+use zmq::SocketType;
+
+const TIMEOUT: Duration = Duration::from_secs(10_u64);
+/// How long subscriptions and connections take to settle before measuring.
+const SETTLE_DURATION: Duration = Duration::from_millis(300_u64);
+/// Messages which publishing benchmark lets in flight, so slow subscriber drops nothing.
+const PUBLISH_WINDOW: usize = 500;
+const MAX_OUTSTANDING_REQUESTS: usize = 100;
+
+fn main() {
+    let is_quick = env::args().any(|arg| arg == "--quick");
+    let scale = if is_quick { 10 } else { 1 };
+
+    println!(
+        "{:<32} {:>10} {:>12} {:>14}  latency",
+        "benchmark", "messages", "elapsed", "messages/sec"
+    );
+
+    let mut port = 57_731;
+    for transport in &[Transport::Inproc, Transport::Tcp] {
+        publish(&config(*transport, "publish", port), 200_000 / scale);
+        port += 2;
+        sequential_requests(&config(*transport, "sequential", port), 10_000 / scale);
+        port += 2;
+        pipelined_requests(&config(*transport, "pipelined", port), 100_000 / scale);
+        port += 2;
+    }
+}
+
+fn config(transport: Transport, name: &str, port: u16) -> BusConfig {
+    let (router_endpoint, publisher_endpoint) = match transport {
+        Transport::Tcp => (
+            format!("tcp://127.0.0.1:{port}"),
+            format!("tcp://127.0.0.1:{}", port + 1),
+        ),
+        _ => (
+            format!("inproc://bench-{name}-router"),
+            format!("inproc://bench-{name}-publisher"),
+        ),
+    };
+
+    BusConfig {
+        transport,
+        router_endpoint: Some(router_endpoint.parse().unwrap()),
+        publisher_endpoints: Some(vec![publisher_endpoint.parse().unwrap()]),
+        log_level: String::from("error"),
+        ..BusConfig::default()
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn report(name: &str, config: &BusConfig, count: usize, elapsed: Duration, latency: &str) {
+    println!(
+        "{:<32} {:>10} {:>12.3?} {:>14.0}  {}",
+        format!("{name} ({})", config.transport.as_str()),
+        count,
+        elapsed,
+        count as f64 / elapsed.as_secs_f64(),
+        latency
+    );
+}
+
+/// Raw sender pushes requests through BUS to raw subscriber without acknowledgements.
+fn publish(config: &BusConfig, count: usize) {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = BusConfig {
+        acknowledge_messages: false,
+        ..config.clone()
+    };
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    let subscriber = context.socket(SocketType::SUB).unwrap();
+    subscriber.set_rcvhwm(0).unwrap();
+    subscriber.set_rcvtimeo(1000).unwrap();
+    for endpoint in config.publisher_connect_endpoints() {
+        endpoint.connect(&subscriber).unwrap();
+    }
+    subscriber
+        .set_subscribe(&kind_topic(ZeromqMessageKind::ValueMultiplicationRequest))
+        .unwrap();
+
+    let received_count = Arc::new(AtomicUsize::new(0));
+    let receiver_count = Arc::clone(&received_count);
+    let receiver_join_handle = thread::spawn(move || {
+        while receiver_count.load(Ordering::Relaxed) < count {
+            // Topic and message frames.
+            if subscriber.recv_bytes(0).is_err() || subscriber.recv_bytes(0).is_err() {
+                break;
+            }
+            let _ = receiver_count.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    let sender = context.socket(SocketType::DEALER).unwrap();
+    config.router_connect_endpoint().connect(&sender).unwrap();
+    let message_bytes = encode_message(
+        Uuid::new_v4(),
+        ValueMultiplicationRequest {
+            value: 6,
+            multiplier: 7,
+        },
+    )
+    .unwrap();
+
+    thread::sleep(SETTLE_DURATION);
+
+    let start_time = Instant::now();
+    for sent_count in 0..count {
+        while sent_count - received_count.load(Ordering::Relaxed) >= PUBLISH_WINDOW {
+            thread::yield_now();
+        }
+        sender.send(&message_bytes, 0).unwrap();
+    }
+    receiver_join_handle.join().unwrap();
+    let elapsed = start_time.elapsed();
+
+    let received_count = received_count.load(Ordering::Relaxed);
+    report("publish", &config, received_count, elapsed, "-");
+    if received_count < count {
+        println!("    lost {} messages", count - received_count);
+    }
+
+    shutdown.request();
+    let _ = bus_join_handle.join().unwrap();
+}
+
+/// Client waits for response to every request before sending the next one.
+fn sequential_requests(config: &BusConfig, count: usize) {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let bus_join_handle = spawn_bus(&context, config, &shutdown);
+    let service_join_handle = spawn_service(&context, config, &shutdown);
+    let client = BusClient::connect(
+        &context,
+        config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(SETTLE_DURATION);
+
+    let mut latency = LatencyHistogram::new();
+    let start_time = Instant::now();
+    for _ in 0..count {
+        let request_start_time = Instant::now();
+        let _ = client
+            .request::<_, ValueMultiplicationResponse>(
+                ValueMultiplicationRequest {
+                    value: 6,
+                    multiplier: 7,
+                },
+                TIMEOUT,
+            )
+            .unwrap();
+        latency.record(request_start_time.elapsed());
+    }
+    let elapsed = start_time.elapsed();
+
+    report(
+        "sequential requests",
+        config,
+        count,
+        elapsed,
+        &describe_latency(&latency),
+    );
+
+    drop(client);
+    shutdown.request();
+    service_join_handle.join().unwrap();
+    let _ = bus_join_handle.join().unwrap();
+}
+
+/// Client keeps up to `MAX_OUTSTANDING_REQUESTS` requests waiting for response.
+fn pipelined_requests(config: &BusConfig, count: usize) {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let bus_join_handle = spawn_bus(&context, config, &shutdown);
+    let service_join_handle = spawn_service(&context, config, &shutdown);
+    let client = BusClient::connect(
+        &context,
+        config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(SETTLE_DURATION);
+
+    let (latency_sender, latency_receiver) = mpsc::channel();
+    let start_time = Instant::now();
+    for _ in 0..count {
+        while client.awaiting_requests_count() >= MAX_OUTSTANDING_REQUESTS {
+            thread::yield_now();
+        }
+        let latency_sender = latency_sender.clone();
+        let _ = client
+            .request_with_callback(
+                ValueMultiplicationRequest {
+                    value: 6,
+                    multiplier: 7,
+                },
+                move |result: Result<ValueMultiplicationResponse, _>, latency| {
+                    let _ = latency_sender.send(result.map(|_| latency).ok());
+                },
+            )
+            .unwrap();
+    }
+
+    let mut latency = LatencyHistogram::new();
+    let mut received_count = 0;
+    for _ in 0..count {
+        match latency_receiver.recv_timeout(TIMEOUT) {
+            Ok(Some(request_latency)) => {
+                latency.record(request_latency);
+                received_count += 1;
+            }
+            Ok(None) => {}
+            // The rest of requests is lost.
+            Err(_) => break,
+        }
+    }
+    let elapsed = start_time.elapsed();
+
+    report(
+        "pipelined requests",
+        config,
+        received_count,
+        elapsed,
+        &describe_latency(&latency),
+    );
+
+    drop(client);
+    shutdown.request();
+    service_join_handle.join().unwrap();
+    let _ = bus_join_handle.join().unwrap();
+}
+
+fn describe_latency(latency: &LatencyHistogram) -> String {
+    format!(
+        "p50 {:.1?}, p99 {:.1?}, max {:.1?}",
+        latency.value_at_quantile(0.5),
+        latency.value_at_quantile(0.99),
+        latency.max()
+    )
+}
@@ -0,0 +1,381 @@
+use crate::client::decode_response;
+use crate::client::BusClient;
+use crate::client::BusClientError;
+use crate::config::BusConfig;
+use crate::helpers::DeadLockSafeMutex;
+use crate::PAYLOAD_FORMAT;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context as TaskContext;
+use std::task::Poll;
+use std::task::Waker;
+use uuid::Uuid;
+use zeromq_messages::codec::encode_message_with_format;
+use zeromq_messages::codec::MessageDecodeError;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::template::ZeromqMessageTrait;
+use zeromq_messages::view::MessageView;
+use zmq::Context;
+use zmq::Message;
+
+//-----------------------------------------------------------------------------------------
+// Mailbox
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug)]
+struct MailboxState<T> {
+    items: VecDeque<T>,
+    waker: Option<Waker>,
+    /// Set once either side is dropped.
+    is_closed: bool,
+}
+
+/// Items handed over from client input/output thread to async task, which is woken up
+/// when they arrive. Futures and streams built on it run on any executor.
+fn mailbox<T: Send + 'static>() -> (MailboxSender<T>, MailboxReceiver<T>) {
+    let state = DeadLockSafeMutex::new(MailboxState {
+        items: VecDeque::new(),
+        waker: None,
+        is_closed: false,
+    });
+
+    (MailboxSender(state.clone()), MailboxReceiver(state))
+}
+
+struct MailboxSender<T: Send + 'static>(DeadLockSafeMutex<MailboxState<T>>);
+
+impl<T: Send + 'static> MailboxSender<T> {
+    /// Puts item into mailbox, returns `false` if receiver is dropped.
+    fn send(&self, item: T) -> bool {
+        let waker = self.0.lock(move |state| {
+            if state.is_closed {
+                return None;
+            }
+            state.items.push_back(item);
+            Some(state.waker.take())
+        });
+
+        match waker {
+            Some(waker) => {
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T: Send + 'static> Drop for MailboxSender<T> {
+    fn drop(&mut self) {
+        let waker = self.0.lock(|state| {
+            state.is_closed = true;
+            state.waker.take()
+        });
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+struct MailboxReceiver<T: Send + 'static>(DeadLockSafeMutex<MailboxState<T>>);
+
+impl<T: Send + 'static> MailboxReceiver<T> {
+    /// Takes the next item, `None` means that sender is dropped and all items are taken.
+    fn poll_recv(&self, cx: &mut TaskContext<'_>) -> Poll<Option<T>> {
+        let waker = cx.waker().clone();
+        self.0.lock(move |state| {
+            if let Some(item) = state.items.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            if state.is_closed {
+                return Poll::Ready(None);
+            }
+            state.waker = Some(waker);
+            Poll::Pending
+        })
+    }
+}
+
+impl<T: Send + 'static> Drop for MailboxReceiver<T> {
+    fn drop(&mut self) {
+        self.0.lock(|state| {
+            state.is_closed = true;
+            state.items.clear();
+        });
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// IncomingMessage
+//-----------------------------------------------------------------------------------------
+
+/// Message of subscribed kind which was published by BUS, with its payload still encoded.
+pub struct IncomingMessage {
+    kind: ZeromqMessageKind,
+    uuid: Uuid,
+    message: Message,
+}
+
+impl IncomingMessage {
+    fn new(message: &Message) -> Option<Self> {
+        let message_view = MessageView::new(message).ok()?;
+
+        Some(Self {
+            kind: message_view.kind(),
+            uuid: message_view.uuid(),
+            message: Message::from(&**message),
+        })
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> ZeromqMessageKind {
+        self.kind
+    }
+
+    /// Uuid of message, response to request is sent with the same uuid.
+    #[must_use]
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    /// Decodes payload of message, which has to be of `P` kind.
+    pub fn decode<P>(&self) -> Result<P, MessageDecodeError>
+    where
+        P: for<'de> ZeromqMessageTrait<'de>,
+    {
+        MessageView::new(&self.message)?.decode_payload::<P>()
+    }
+}
+
+impl fmt::Debug for IncomingMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncomingMessage")
+            .field("kind", &self.kind)
+            .field("uuid", &self.uuid)
+            .finish_non_exhaustive()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// MessageStream
+//-----------------------------------------------------------------------------------------
+
+/// Stream of received messages of chosen kinds which are not responses to requests of
+/// this client. It ends once client is dropped.
+#[must_use]
+pub struct MessageStream {
+    receiver: MailboxReceiver<IncomingMessage>,
+}
+
+impl MessageStream {
+    /// Waits for the next message, returns `None` once client is dropped.
+    pub async fn next(&mut self) -> Option<IncomingMessage> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for MessageStream {
+    type Item = IncomingMessage;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<IncomingMessage>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl fmt::Debug for MessageStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageStream").finish_non_exhaustive()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// ResponseFuture
+//-----------------------------------------------------------------------------------------
+
+/// Future of response to request with given uuid. Dropping it forgets the request, so it
+/// will not be resent anymore.
+struct ResponseFuture<'a, Resp> {
+    client: &'a BusClient,
+    uuid: Uuid,
+    receiver: MailboxReceiver<Message>,
+    response_type: PhantomData<fn() -> Resp>,
+}
+
+impl<Resp> Future for ResponseFuture<'_, Resp>
+where
+    Resp: for<'de> ZeromqMessageTrait<'de>,
+{
+    type Output = Result<Resp, BusClientError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        self.receiver
+            .poll_recv(cx)
+            .map(|maybe_message| match maybe_message {
+                Some(message) => decode_response(&message),
+                // Client dropped request without response, so it is stopped.
+                None => Err(BusClientError::Disconnected),
+            })
+    }
+}
+
+impl<Resp> Drop for ResponseFuture<'_, Resp> {
+    fn drop(&mut self) {
+        self.client.forget_request(self.uuid);
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// AsyncBusClient
+//-----------------------------------------------------------------------------------------
+
+/// Async flavor of `BusClient` for services built on tokio.
+///
+/// Sockets are still owned by input/output thread of the client: sending hands message
+/// over to that thread, and responses and messages wake up tasks waiting for them. Async
+/// service takes requests from `messages` stream and answers them with `respond`.
+#[derive(Debug)]
+pub struct AsyncBusClient {
+    client: Arc<BusClient>,
+}
+
+impl AsyncBusClient {
+    /// Connects to BUS and subscribes to messages of given kinds, which are responses to
+    /// requests of the client or messages taken from `messages` stream.
+    pub fn connect(
+        context: &Context,
+        config: &BusConfig,
+        kinds: &[ZeromqMessageKind],
+    ) -> Result<Self, BusClientError> {
+        Ok(Self {
+            client: Arc::new(BusClient::connect(context, config, kinds)?),
+        })
+    }
+
+    /// Sends message which needs no response and returns its uuid.
+    pub async fn send<P>(&self, payload: P) -> Result<Uuid, BusClientError>
+    where
+        P: for<'de> ZeromqMessageTrait<'de>,
+    {
+        let uuid = Uuid::new_v4();
+        self.respond(uuid, payload).await?;
+        Ok(uuid)
+    }
+
+    /// Sends response to request with given uuid, which was taken from `messages` stream.
+    ///
+    /// While input/output thread is behind, message is handed over to it from blocking
+    /// thread pool of tokio, so executor is never blocked.
+    pub async fn respond<P>(&self, uuid: Uuid, payload: P) -> Result<(), BusClientError>
+    where
+        P: for<'de> ZeromqMessageTrait<'de>,
+    {
+        let message_bytes = encode_message_with_format(uuid, payload, PAYLOAD_FORMAT)?;
+        self.send_message_bytes(uuid, message_bytes).await
+    }
+
+    /// Same as `respond`, but message is already encoded.
+    pub(crate) async fn send_message_bytes(
+        &self,
+        uuid: Uuid,
+        message_bytes: Vec<u8>,
+    ) -> Result<(), BusClientError> {
+        match self.client.try_send_to_io_thread(&message_bytes) {
+            Err(zmq::Error::EAGAIN) => {
+                let client = Arc::clone(&self.client);
+                tokio::task::spawn_blocking(move || client.send_to_io_thread(message_bytes))
+                    .await
+                    .map_err(|_| BusClientError::Disconnected)??;
+            }
+            result => result?,
+        }
+
+        log::trace!("[CLIENT] message {} sent", uuid);
+
+        Ok(())
+    }
+
+    /// Sends request and waits for response with the same uuid. Request is resent
+    /// periodically while waiting, timeout is left to the caller, e.g. `tokio::time::timeout`.
+    pub async fn request<Req, Resp>(&self, payload: Req) -> Result<Resp, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        self.start_request(None, payload)?.await
+    }
+
+    /// Same as `request`, but request is delivered to one instance of `destination`
+    /// service.
+    pub async fn request_to<Req, Resp>(
+        &self,
+        destination: &str,
+        payload: Req,
+    ) -> Result<Resp, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        self.start_request(Some(destination), payload)?.await
+    }
+
+    /// Returns stream of received messages of given kinds, which client has to be
+    /// subscribed to. Every stream gets its own copy of message.
+    pub fn messages(
+        &self,
+        kinds: &[ZeromqMessageKind],
+    ) -> Result<MessageStream, BusClientError> {
+        let (sender, receiver) = mailbox();
+        self.client
+            .listen(kinds, move |message| match IncomingMessage::new(message) {
+                Some(incoming_message) => sender.send(incoming_message),
+                None => true,
+            })?;
+
+        Ok(MessageStream { receiver })
+    }
+
+    /// Blocking client which shares connection with this one.
+    #[must_use]
+    pub fn blocking(&self) -> &BusClient {
+        &self.client
+    }
+
+    fn start_request<Req, Resp>(
+        &self,
+        destination: Option<&str>,
+        payload: Req,
+    ) -> Result<ResponseFuture<'_, Resp>, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        let (sender, receiver) = mailbox();
+        let uuid = self.client.send_request::<Req, Resp>(
+            destination,
+            payload,
+            Box::new(move |message, _| {
+                // Receiver may be already dropped if nobody waits for response anymore.
+                let _ = sender.send(message);
+            }),
+        )?;
+
+        Ok(ResponseFuture {
+            client: &self.client,
+            uuid,
+            receiver,
+            response_type: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,176 @@
+use crate::async_client::AsyncBusClient;
+use crate::async_client::IncomingMessage;
+use crate::config::BusConfig;
+use crate::metrics::Metrics;
+use crate::service::count_processed;
+use crate::service::BusServiceError;
+use crate::service::ServiceMetrics;
+use crate::PAYLOAD_FORMAT;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+use zeromq_messages::codec::encode_message_with_format;
+use zeromq_messages::codec::MessageEncodeError;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::template::ZeromqMessageTrait;
+use zmq::Context;
+
+type ResponseFuture =
+    Pin<Box<dyn Future<Output = Result<Vec<u8>, MessageEncodeError>> + Send>>;
+type AsyncHandler =
+    Box<dyn Fn(&IncomingMessage) -> Result<ResponseFuture, BusServiceError> + Send + Sync>;
+
+//-----------------------------------------------------------------------------------------
+// AsyncBusService
+//-----------------------------------------------------------------------------------------
+
+/// Async flavor of `BusService` for applications built on tokio. Requests are taken from
+/// `AsyncBusClient::messages` stream, handled by async handlers one after another and
+/// answered with `AsyncBusClient::respond`.
+///
+/// Service does not register on BUS, so it handles published requests only. Directed and
+/// queued requests need `BusService`.
+#[derive(Default)]
+pub struct AsyncBusService {
+    handlers: HashMap<ZeromqMessageKind, AsyncHandler>,
+    metrics: Metrics,
+}
+
+impl AsyncBusService {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers async handler for requests of `Req` kind.
+    #[must_use]
+    pub fn on<Req, Resp, Fut>(
+        mut self,
+        handler: impl Fn(Req) -> Fut + Send + Sync + 'static,
+    ) -> Self
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+        Fut: Future<Output = Resp> + Send + 'static,
+    {
+        let kind = <Req as ZeromqMessageTrait<'_>>::kind();
+        let previous_handler = self.handlers.insert(
+            kind,
+            Box::new(move |message| {
+                let request = message.decode::<Req>()?;
+                let uuid = message.uuid();
+                let response = handler(request);
+                Ok(Box::pin(async move {
+                    encode_message_with_format(uuid, response.await, PAYLOAD_FORMAT)
+                }))
+            }),
+        );
+
+        assert!(
+            previous_handler.is_none(),
+            "handler for {:?} is already registered",
+            kind
+        );
+
+        self
+    }
+
+    /// Records metrics of handled requests in given registry instead of a private one.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: &Metrics) -> Self {
+        self.metrics = metrics.clone();
+        self
+    }
+
+    /// Returns kinds of requests for which handlers are registered.
+    #[must_use]
+    pub fn kinds(&self) -> Vec<ZeromqMessageKind> {
+        self.handlers.keys().copied().collect()
+    }
+
+    /// Connects to BUS and processes requests until the returned future is dropped, which
+    /// disconnects service from BUS.
+    pub async fn run(
+        self,
+        context: &Context,
+        config: &BusConfig,
+    ) -> Result<(), BusServiceError> {
+        let kinds = self.kinds();
+        let client = AsyncBusClient::connect(context, config, &kinds)?;
+        let mut requests = client.messages(&kinds)?;
+
+        let metrics = ServiceMetrics::new(&self.metrics);
+        let mut total_processed_messages_count: usize = 0;
+
+        while let Some(request) = requests.next().await {
+            if self.process(&client, &request, &metrics).await {
+                count_processed(&mut total_processed_messages_count, config);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles request and sends response to BUS, returns `false` if it failed.
+    async fn process(
+        &self,
+        client: &AsyncBusClient,
+        request: &IncomingMessage,
+        metrics: &ServiceMetrics,
+    ) -> bool {
+        log::trace!("< {:?}", request);
+
+        let Some(handler) = self.handlers.get(&request.kind()) else {
+            return false;
+        };
+
+        let start_time = Instant::now();
+        let response_message_bytes = match handler(request) {
+            Ok(response_future) => response_future.await.map_err(BusServiceError::from),
+            Err(error) => Err(error),
+        };
+        let response_message_bytes = match response_message_bytes {
+            Ok(response_message_bytes) => response_message_bytes,
+            Err(error) => {
+                log::error!(
+                    "failed to handle {:?} message because of: {}",
+                    request.kind(),
+                    error
+                );
+                if let BusServiceError::CantDecodeRequest(error) = &error {
+                    metrics.count_decode_error(error);
+                }
+                metrics.failed.inc();
+                return false;
+            }
+        };
+
+        log::trace!("> {:?}", response_message_bytes);
+
+        if let Err(error) = client
+            .send_message_bytes(request.uuid(), response_message_bytes)
+            .await
+        {
+            log::error!("failed to send message because of: {}", error);
+            metrics.failed.inc();
+            return false;
+        }
+
+        metrics.processed.inc();
+        metrics
+            .handling_duration
+            .observe_duration(start_time.elapsed());
+
+        true
+    }
+}
+
+impl fmt::Debug for AsyncBusService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncBusService")
+            .field("kinds", &self.kinds())
+            .finish_non_exhaustive()
+    }
+}
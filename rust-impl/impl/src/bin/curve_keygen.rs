@@ -0,0 +1,54 @@
+// Rust flags
+#![warn(nonstandard_style)]
+#![warn(future_incompatible)]
+#![warn(rust_2018_compatibility)]
+#![warn(rust_2018_idioms)]
+#![warn(unused)]
+#![warn(missing_debug_implementations)]
+#![warn(missing_copy_implementations)]
+#![warn(trivial_casts)]
+#![warn(trivial_numeric_casts)]
+#![warn(unsafe_code)]
+#![warn(unused_extern_crates)]
+#![warn(unused_import_braces)]
+#![warn(unused_qualifications)]
+#![warn(unused_results)]
+#![warn(variant_size_differences)]
+#![recursion_limit = "1024"]
+// Clippy flags
+#![warn(clippy::all)]
+#![warn(clippy::pedantic)]
+#![allow(clippy::module_name_repetitions)]
+#![allow(clippy::missing_panics_doc)]
+#![allow(clippy::missing_errors_doc)]
+
+use rust_impl::CurveKeyPair;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Generates CURVE keypair for BUS or for service")]
+struct Args {
+    /// Name of key files: `<name>.key` has public key, which is given to the other side,
+    /// and `<name>.key_secret` has both keys.
+    name: String,
+
+    /// Directory in which key files are written, existing files are never overwritten.
+    #[structopt(long, default_value = ".", parse(from_os_str))]
+    output_directory: PathBuf,
+}
+
+fn main() {
+    let args = Args::from_args();
+
+    let key_pair = CurveKeyPair::generate()
+        .unwrap_or_else(|error| panic!("failed to generate keypair: {}", error));
+
+    let (public_key_path, secret_key_path) = key_pair
+        .write(&args.output_directory, &args.name)
+        .unwrap_or_else(|error| panic!("failed to write key files: {}", error));
+
+    println!("public key: {}", key_pair.public_key());
+    println!("public key file: {}", public_key_path.display());
+    println!("secret key file: {}", secret_key_path.display());
+}
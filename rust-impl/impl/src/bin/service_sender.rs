@@ -24,339 +24,467 @@
 
 use rand::thread_rng;
 use rand::Rng;
-use rust_impl::DeadLockSafeRwLock;
-use rust_impl::BUS_PUBLISHERS_SOCKET_ADDRS;
-use rust_impl::BUS_ROUTER_SOCKET_ADDR;
-use rust_impl::LOG_LEVEL;
-use rust_impl::REQUESTS_COUNT_INSIDE_ONE_GROUP;
+use rust_impl::value_multiplication;
+use rust_impl::Bus;
+use rust_impl::BusClient;
+use rust_impl::BusClientError;
+use rust_impl::BusConfig;
+use rust_impl::BusConfigArgs;
+use rust_impl::BusService;
+use rust_impl::BusStats;
+use rust_impl::Counter;
+use rust_impl::DeadLockSafeMutex;
+use rust_impl::Histogram;
+use rust_impl::LatencyHistogram;
+use rust_impl::LoadArgs;
+use rust_impl::LoadGenerator;
+use rust_impl::Metrics;
+use rust_impl::Pace;
+use rust_impl::Shutdown;
+use rust_impl::Transport;
+use rust_impl::DEFAULT_LATENCY_BUCKETS;
 use rust_impl::RUST_LOG_ENVIRONMENT_VARIABLE_NAME;
-use rust_impl::ZEROMQ_ZERO_FLAG;
+use rust_impl::VALUE_MULTIPLICATION_SERVICE_NAME;
 use std::cmp::Ord;
 use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::collections::VecDeque;
-use std::convert::From;
+use std::convert::TryFrom;
 use std::env;
-use std::iter::Iterator;
-use std::rc::Rc;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use std::time::Instant;
 use std::time::SystemTime;
+use structopt::StructOpt;
 use uuid::Uuid;
-use zeromq_messages::codec::decode_message_kind;
-use zeromq_messages::codec::decode_message_payload;
-use zeromq_messages::codec::decode_message_uuid;
-use zeromq_messages::codec::encode_message;
 use zeromq_messages::kind::ZeromqMessageKind;
 use zeromq_messages::messages::ValueMultiplicationRequest;
 use zeromq_messages::messages::ValueMultiplicationResponse;
 use zmq::Context as ZmqContext;
-use zmq::Message;
-use zmq::SocketType;
 
-const RESEND_REQUESTS_EVERY_DURATION: Duration = Duration::from_secs(5_u64);
-
-type AwaitingRequestsStorage = DeadLockSafeRwLock<HashMap<Uuid, RequestData>>;
-
-#[derive(Debug, Clone)]
-struct RequestData {
-    value: i64,
-    multiplier: i64,
-    expected_result: i64,
-    last_send_attempt_time: Instant,
+/// How long to wait for responses to already sent requests on shutdown. Covers one
+/// resend of lost requests.
+const RESPONSES_DRAIN_TIMEOUT: Duration = Duration::from_secs(10_u64);
+const RESPONSES_DRAIN_CHECK_INTERVAL: Duration = Duration::from_millis(100_u64);
+/// Longest sleep while waiting for the next request to be due, so shutdown is noticed.
+const MAX_PACE_SLEEP: Duration = Duration::from_millis(100_u64);
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Sends value multiplication requests to BUS and checks responses")]
+struct Args {
+    /// Run BUS and responder service inside this process, connected over inproc transport.
+    #[structopt(long)]
+    in_process: bool,
+
+    /// Deliver requests to one instance of named service instead of publishing them to
+    /// every responder.
+    #[structopt(long)]
+    destination: Option<String>,
+
+    /// Address of HTTP endpoint serving Prometheus metrics of sender, overrides
+    /// `sender_metrics_address` of config file.
+    #[structopt(long, env = "SENDER_METRICS_ADDRESS")]
+    metrics_address: Option<SocketAddr>,
+
+    #[structopt(flatten)]
+    load: LoadArgs,
+
+    #[structopt(flatten)]
+    bus_config: BusConfigArgs,
 }
 
-impl RequestData {
-    fn new(value: i64, multiplier: i64) -> Self {
-        Self {
-            value,
-            multiplier,
-            expected_result: value * multiplier,
-            last_send_attempt_time: Instant::now(),
-        }
-    }
+fn main() {
+    let args = Args::from_args();
+    let mut config = BusConfig::load(args.bus_config)
+        .unwrap_or_else(|error| panic!("[SYSTEM] failed to load config: {}", error));
 
-    fn should_resend_request(&self) -> bool {
-        Instant::now().duration_since(self.last_send_attempt_time)
-            > RESEND_REQUESTS_EVERY_DURATION
+    if args.in_process {
+        config.transport = Transport::Inproc;
     }
 
-    fn update_last_send_attempt_time(&mut self) {
-        self.last_send_attempt_time = Instant::now();
-    }
-}
+    let load_generator = args
+        .load
+        .generator(u64::try_from(config.group_size).unwrap_or(u64::MAX))
+        .unwrap_or_else(|error| panic!("[SYSTEM] invalid load arguments: {}", error));
 
-#[allow(clippy::too_many_lines)]
-fn main() {
     if env::var(RUST_LOG_ENVIRONMENT_VARIABLE_NAME).is_err() {
-        env::set_var(RUST_LOG_ENVIRONMENT_VARIABLE_NAME, LOG_LEVEL);
+        env::set_var(RUST_LOG_ENVIRONMENT_VARIABLE_NAME, &config.log_level);
     }
 
     env_logger::init();
 
+    let shutdown = Shutdown::on_signals().unwrap_or_else(|error| {
+        panic!("[SYSTEM] failed to handle shutdown signals: {}", error)
+    });
+
     let context = ZmqContext::new();
-    let awaiting_requests_storage: AwaitingRequestsStorage = DeadLockSafeRwLock::default();
 
-    let sender = context
-        .socket(SocketType::DEALER)
-        .expect("[SYSTEM] failed to initialize sender socket");
+    // In process BUS and responder service record their metrics in the same registry.
+    let metrics = Metrics::new();
+    let metrics_address = args.metrics_address.or(config.sender_metrics_address);
+    let metrics_server = metrics_address.map(|metrics_address| {
+        metrics
+            .serve(metrics_address, &shutdown)
+            .unwrap_or_else(|error| panic!("[SYSTEM] failed to serve metrics: {}", error))
+    });
+    let sender_metrics = SenderMetrics::new(&metrics);
+
+    let in_process_bus = if args.in_process {
+        Some(InProcessBus::start(&context, &config, &metrics))
+    } else {
+        None
+    };
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap_or_else(|error| panic!("[SYSTEM] failed to connect to BUS: {}", error));
+
+    log::debug!("[SYSTEM] client has connected to BUS");
+
+    let group_size = config.group_size;
+    log::debug!("[SYSTEM] load profile {:?}", load_generator.profile());
+
+    let latency_report = DeadLockSafeMutex::new(LatencyReport::new(group_size));
+    let latency_report_clone = latency_report.clone();
+
+    log::debug!("[SYSTEM] running messages sending loop");
+
+    let sender_shutdown = shutdown.clone();
+    let destination = args.destination;
+    let sender_loop_join_handle = thread::spawn(move || {
+        send_requests(
+            client,
+            destination.as_deref(),
+            &latency_report_clone,
+            &sender_metrics,
+            load_generator,
+            group_size,
+            &sender_shutdown,
+        )
+    });
+
+    let (client, total_sended_messages_count) = sender_loop_join_handle
+        .join()
+        .expect("[SYSTEM] failed to wait sender thread to finish");
 
-    log::debug!("[SYSTEM] initialized sender socket");
+    // Sending stops early once total count of requests is sent, then the whole process
+    // stops after responses are drained.
+    shutdown.request();
 
-    sender
-        .connect(BUS_ROUTER_SOCKET_ADDR.as_str())
-        .expect("[SYSTEM] failed to connect to BUS router socket.");
+    log::debug!(
+        "[SYSTEM] waiting for {} responses",
+        client.awaiting_requests_count()
+    );
 
-    log::debug!("[SYSTEM] sender has connected to BUS router socket");
+    let drain_start_time = Instant::now();
+    while client.awaiting_requests_count() > 0
+        && drain_start_time.elapsed() < RESPONSES_DRAIN_TIMEOUT
+    {
+        thread::sleep(RESPONSES_DRAIN_CHECK_INTERVAL);
+    }
 
-    let receiver = context
-        .socket(SocketType::SUB)
-        .expect("[SYSTEM] failed to initialize receiver socket");
+    let total_lost_messages_count = client.awaiting_requests_count();
+    drop(client);
 
-    log::debug!("[SYSTEM] initialized receiver socket");
+    if let Some(in_process_bus) = in_process_bus {
+        in_process_bus.stop();
+    }
 
-    for publisher_address in BUS_PUBLISHERS_SOCKET_ADDRS.iter() {
-        receiver
-            .connect(publisher_address.as_str())
-            .unwrap_or_else(|error| {
-                panic!(
-                    "[SYSTEM] connection to BUS publisher socket '{}' failed with: {}",
-                    publisher_address, error
-                )
-            });
+    if let Some(metrics_server) = metrics_server {
+        metrics_server.join();
+    }
 
-        receiver.set_subscribe(b"").unwrap_or_else(|error| {
-            panic!(
-                "[SYSTEM] subscription to BUS publisher socket '{}' failed with: {}",
-                publisher_address, error
+    let (total_received_messages_count, final_report) =
+        latency_report.lock(|latency_report| {
+            (
+                latency_report.received_count(),
+                latency_report.final_report(),
             )
         });
-    }
 
-    log::debug!(
-        "[SYSTEM] receiver has connected to all BUS publishers: {}",
-        BUS_PUBLISHERS_SOCKET_ADDRS.join(", ")
+    log::info!(
+        "[SYSTEM] stopped: total sended {} messages, total received {} messages, lost {} messages",
+        total_sended_messages_count,
+        total_received_messages_count,
+        total_lost_messages_count
     );
+    log::info!("[SYSTEM] {}", final_report);
+}
+
+/// Round-trip latencies of requests which got expected response, for the current group of
+/// responses and for the whole run.
+#[derive(Debug)]
+struct LatencyReport {
+    group_size: u64,
+    start_time: Instant,
+    group_start_time: Instant,
+    last_response_time: Instant,
+    group: LatencyHistogram,
+    total: LatencyHistogram,
+}
 
-    let mut total_received_messages_count = 0;
-    let awaiting_requests_storage_clone = awaiting_requests_storage.clone();
+impl LatencyReport {
+    fn new(group_size: usize) -> Self {
+        let now = Instant::now();
 
-    log::debug!("[SYSTEM] running messages receiving loop");
+        Self {
+            group_size: u64::try_from(group_size).unwrap_or(u64::MAX),
+            start_time: now,
+            group_start_time: now,
+            last_response_time: now,
+            group: LatencyHistogram::new(),
+            total: LatencyHistogram::new(),
+        }
+    }
 
-    drop(thread::spawn(move || 'receive_messages: loop {
-        let message_bytes = match receiver.recv_bytes(ZEROMQ_ZERO_FLAG) {
-            Ok(message_bytes) => message_bytes,
-            Err(error) => {
-                log::error!("[RECEIVER] failed to receive message because of: {}", error);
-                continue 'receive_messages;
-            }
-        };
-
-        log::trace!("< {:?}", message_bytes);
-
-        let (message_kind, message_bytes_without_kind) =
-            match decode_message_kind(message_bytes) {
-                Ok(message_kind_and_left_bytes) => message_kind_and_left_bytes,
-                Err(error) => {
-                    log::error!(
-                        "[RECEIVER] failed to decode message kind because of: {}",
-                        error
-                    );
-                    continue 'receive_messages;
-                }
-            };
-
-        if !(matches!(message_kind, ZeromqMessageKind::ValueMultiplicationResponse)) {
-            log::trace!(
-                "[RECEIVER] ignored message with unexpected kind {:?}",
-                message_kind
+    /// Records latency of response, logs report of the group once it is complete and
+    /// starts the next group.
+    fn record(&mut self, latency: Duration) {
+        self.last_response_time = Instant::now();
+        self.group.record(latency);
+        self.total.record(latency);
+
+        if self.group.len() >= self.group_size {
+            log::debug!(
+                "[RECEIVER] {:?} - total received {} messages, last group: {}",
+                SystemTime::now(),
+                self.total.len(),
+                describe_latency(&self.group, self.last_response_time - self.group_start_time)
             );
-            continue 'receive_messages;
+
+            self.group.reset();
+            self.group_start_time = self.last_response_time;
         }
+    }
 
-        let (uuid, message_payload_bytes) = decode_message_uuid(message_bytes_without_kind);
-
-        match awaiting_requests_storage_clone.read(move |awaiting_requests_storage| {
-            awaiting_requests_storage.get(&uuid).cloned()
-        }) {
-            Some(RequestData {
-                expected_result, ..
-            }) => {
-                log::trace!("[RECEIVER] attempt to decode payload");
-
-                let payload = match decode_message_payload::<'_, ValueMultiplicationResponse>(
-                    message_payload_bytes.as_slice(),
-                ) {
-                    Ok(payload) => payload,
-                    Err(error) => {
-                        log::error!(
-                            "[RECEIVER] failed to decode message payload because of: {}",
-                            error
-                        );
-                        continue 'receive_messages;
-                    }
-                };
-
-                log::trace!("[RECEIVER] compare expected and received values");
-
-                match expected_result.cmp(&payload.result) {
-                    Ordering::Greater | Ordering::Less => {
-                        log::error!("[RECEIVER] received message with unexpected payload");
-                    }
-                    Ordering::Equal => {
-                        total_received_messages_count += 1;
-                    }
-                }
-
-                log::trace!("[RECEIVER] request completed, removing from storage");
-
-                // Drop copy allowed because dropped value is not written in any variable.
-                #[allow(clippy::drop_copy)]
-                drop(awaiting_requests_storage_clone.write(
-                    move |awaiting_requests_storage| awaiting_requests_storage.remove(&uuid),
-                ));
-
-                log::trace!(
-                    "[RECEIVER] request {} completed and removed from storage",
-                    uuid
-                );
+    fn received_count(&self) -> u64 {
+        self.total.len()
+    }
 
-                if total_received_messages_count % REQUESTS_COUNT_INSIDE_ONE_GROUP == 0 {
-                    log::debug!(
-                        "[RECEIVER] {:?} - total received {} messages",
-                        SystemTime::now(),
-                        total_received_messages_count
-                    );
-                }
-            }
-            None => {
-                log::error!("[RECEIVER] received message with unexpected uuid: {}", uuid);
-            }
+    /// Describes throughput and latency from start until the last response.
+    fn final_report(&self) -> String {
+        format!(
+            "final report: {}",
+            describe_latency(&self.total, self.last_response_time - self.start_time)
+        )
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn describe_latency(histogram: &LatencyHistogram, elapsed: Duration) -> String {
+    let throughput = if elapsed.is_zero() {
+        0.0
+    } else {
+        histogram.len() as f64 / elapsed.as_secs_f64()
+    };
+
+    format!("throughput {throughput:.0} messages/sec over {elapsed:.3?}, latency {histogram}")
+}
+
+/// Metrics of requests sent by this binary.
+#[derive(Debug, Clone)]
+struct SenderMetrics {
+    sent: Arc<Counter>,
+    received: Arc<Counter>,
+    failed: Arc<Counter>,
+    request_duration: Arc<Histogram>,
+}
+
+impl SenderMetrics {
+    fn new(metrics: &Metrics) -> Self {
+        Self {
+            sent: metrics.counter("sender_sent_requests_total", "Requests sent to BUS.", &[]),
+            received: metrics.counter(
+                "sender_received_responses_total",
+                "Responses with expected result.",
+                &[],
+            ),
+            failed: metrics.counter(
+                "sender_failed_requests_total",
+                "Requests which failed or got response with unexpected result.",
+                &[],
+            ),
+            request_duration: metrics.histogram(
+                "sender_request_duration_seconds",
+                "Time from sending request until its response or failure.",
+                &[],
+                DEFAULT_LATENCY_BUCKETS,
+            ),
         }
-    }));
+    }
+}
 
-    log::debug!("[SYSTEM] running messages sending loop");
+/// BUS and responder service running on background threads and sharing context with
+/// the client.
+struct InProcessBus {
+    shutdown: Shutdown,
+    bus_join_handle: JoinHandle<BusStats>,
+    service_join_handle: JoinHandle<()>,
+}
+
+impl InProcessBus {
+    fn start(context: &ZmqContext, config: &BusConfig, metrics: &Metrics) -> Self {
+        // Separate flag is used, so BUS keeps running while client waits for responses.
+        let shutdown = Shutdown::new();
+
+        let bus = Bus::bind(context, config)
+            .unwrap_or_else(|error| panic!("[SYSTEM] failed to initialize BUS: {}", error))
+            .with_metrics(metrics);
+        let bus_shutdown = shutdown.clone();
+        let bus_join_handle = thread::spawn(move || bus.run(&bus_shutdown));
+
+        let service_context = context.clone();
+        let service_config = config.clone();
+        let service_metrics = metrics.clone();
+        let service_shutdown = shutdown.clone();
+        let service_join_handle = thread::spawn(move || {
+            BusService::new()
+                .named(VALUE_MULTIPLICATION_SERVICE_NAME)
+                .with_metrics(&service_metrics)
+                .on(value_multiplication)
+                .run(&service_context, &service_config, &service_shutdown)
+                .unwrap_or_else(|error| panic!("[SYSTEM] service failed with: {}", error));
+        });
+
+        log::debug!("[SYSTEM] BUS and responder service are running in process");
+
+        Self {
+            shutdown,
+            bus_join_handle,
+            service_join_handle,
+        }
+    }
+
+    fn stop(self) {
+        self.shutdown.request();
+
+        self.service_join_handle
+            .join()
+            .expect("[SYSTEM] failed to wait service thread to finish");
+        let _ = self
+            .bus_join_handle
+            .join()
+            .expect("[SYSTEM] failed to wait BUS thread to finish");
+    }
+}
 
+/// Sends requests with random values as load generator allows until shutdown is requested
+/// or total count of requests is sent, returns the client, which still waits for
+/// responses, and count of sent requests.
+fn send_requests(
+    client: BusClient,
+    destination: Option<&str>,
+    latency_report: &DeadLockSafeMutex<LatencyReport>,
+    sender_metrics: &SenderMetrics,
+    mut load_generator: LoadGenerator,
+    group_size: usize,
+    shutdown: &Shutdown,
+) -> (BusClient, usize) {
+    let mut rng = thread_rng();
     let mut total_sended_messages_count = 0;
-    let sender_loop_join_handle = thread::spawn(move || {
-        let mut rng = thread_rng();
-        let mut last_resend_check = Instant::now();
-
-        #[allow(unused_labels)]
-        'send_messages: loop {
-            let should_resend_requests = Instant::now().duration_since(last_resend_check)
-                > RESEND_REQUESTS_EVERY_DURATION;
-            let resend_requests: Rc<VecDeque<(Uuid, RequestData)>> =
-                Rc::new(VecDeque::default());
-            let mut resend_requests_clone = Rc::clone(&resend_requests);
-
-            if should_resend_requests {
-                last_resend_check = Instant::now();
-                awaiting_requests_storage.write(move |awaiting_requests_storage| {
-                    let resend_requests_iter = awaiting_requests_storage
-                        .iter_mut()
-                        .filter_map(|(uuid, request_data)| {
-                            if request_data.should_resend_request() {
-                                request_data.update_last_send_attempt_time();
-                                Some((*uuid, request_data.clone()))
-                            } else {
-                                None
-                            }
-                        });
-                    Rc::make_mut(&mut resend_requests_clone).extend(resend_requests_iter);
-                });
-
-                log::debug!("[SENDER] resend {} requests", resend_requests.len());
+    let start_time = Instant::now();
+
+    while !shutdown.is_requested() {
+        match load_generator.pace(start_time.elapsed(), || client.awaiting_requests_count()) {
+            Pace::Send => {}
+            Pace::Wait(delay) => {
+                thread::sleep(delay.min(MAX_PACE_SLEEP));
+                continue;
             }
-
-            let mut total_messages_sent_inside_current_group = 0;
-
-            'send_messages_group: while total_messages_sent_inside_current_group
-                < REQUESTS_COUNT_INSIDE_ONE_GROUP
-            {
-                let mut is_resend = false;
-                let mut cloned_resend_requests = Rc::clone(&resend_requests);
-                let current_resend_requests = Rc::make_mut(&mut cloned_resend_requests);
-                let (current_value, current_multiplier, current_request, current_uuid) =
-                    if let Some((
-                        uuid,
-                        RequestData {
-                            value, multiplier, ..
-                        },
-                    )) = current_resend_requests.pop_front()
-                    {
-                        is_resend = true;
-
-                        (
-                            value,
-                            multiplier,
-                            ValueMultiplicationRequest { multiplier, value },
-                            uuid,
-                        )
-                    } else {
-                        let current_value = i64::from(rng.gen::<u8>());
-                        let current_multiplier = i64::from(rng.gen::<u8>());
-
-                        (
-                            current_value,
-                            current_multiplier,
-                            ValueMultiplicationRequest {
-                                value: current_value,
-                                multiplier: current_multiplier,
-                            },
-                            Uuid::new_v4(),
-                        )
-                    };
-
-                let message_bytes = match encode_message(current_uuid, current_request) {
-                    Ok(message_bytes) => message_bytes,
-                    Err(error) => {
-                        log::error!("[SENDER] failed to encode message because of: {}", error);
-                        continue 'send_messages_group;
-                    }
-                };
-
-                if let Err(error) =
-                    sender.send(Message::from(message_bytes.clone()), ZEROMQ_ZERO_FLAG)
-                {
-                    log::error!("[SENDER] failed to send message because of: {}", error);
-                    continue 'send_messages_group;
-                }
-
-                total_messages_sent_inside_current_group += 1;
-                log::trace!("> {:?}", message_bytes);
-
-                // If we resend the request, then it has already been written to the storage.
-                if !is_resend {
-                    // Drop copy allowed because dropped value is not written in any variable.
-                    #[allow(clippy::drop_copy)]
-                    drop(
-                        awaiting_requests_storage.write(move |awaiting_requests_storage| {
-                            awaiting_requests_storage.insert(
-                                current_uuid,
-                                RequestData::new(current_value, current_multiplier),
-                            )
-                        }),
-                    );
-                }
-
-                total_sended_messages_count += 1;
+            Pace::Done => {
+                log::debug!("[SENDER] total count of messages is sent");
+                break;
             }
+        }
 
-            if total_sended_messages_count % REQUESTS_COUNT_INSIDE_ONE_GROUP == 0 {
-                log::debug!(
-                    "[SENDER] {:?} - total sended {} messages",
-                    SystemTime::now(),
-                    total_sended_messages_count
+        let value = i64::from(rng.gen::<u8>());
+        let multiplier = i64::from(rng.gen::<u8>());
+        let latency_report = latency_report.clone();
+        let callback_metrics = sender_metrics.clone();
+
+        if let Err(error) = send_request(
+            &client,
+            destination,
+            ValueMultiplicationRequest { value, multiplier },
+            move |result, latency| {
+                callback_metrics.request_duration.observe_duration(latency);
+                check_response(
+                    result,
+                    value * multiplier,
+                    latency,
+                    &latency_report,
+                    &callback_metrics,
                 );
-            }
+            },
+        ) {
+            log::error!("[SENDER] failed to send message because of: {}", error);
+            continue;
         }
-    });
 
-    sender_loop_join_handle
-        .join()
-        .expect("[SYSTEM] failed to wait sender thread to finish");
+        load_generator.record_sent();
+        sender_metrics.sent.inc();
+        total_sended_messages_count += 1;
+
+        if total_sended_messages_count % group_size == 0 {
+            log::debug!(
+                "[SENDER] {:?} - total sended {} messages",
+                SystemTime::now(),
+                total_sended_messages_count
+            );
+        }
+    }
+
+    (client, total_sended_messages_count)
+}
+
+/// Publishes request or directs it to `destination` service, if it is given.
+fn send_request(
+    client: &BusClient,
+    destination: Option<&str>,
+    payload: ValueMultiplicationRequest,
+    callback: impl FnOnce(Result<ValueMultiplicationResponse, BusClientError>, Duration)
+        + Send
+        + 'static,
+) -> Result<Uuid, BusClientError> {
+    match destination {
+        Some(destination) => client.request_with_callback_to(destination, payload, callback),
+        None => client.request_with_callback(payload, callback),
+    }
+}
+
+fn check_response(
+    result: Result<ValueMultiplicationResponse, BusClientError>,
+    expected_result: i64,
+    latency: Duration,
+    latency_report: &DeadLockSafeMutex<LatencyReport>,
+    metrics: &SenderMetrics,
+) {
+    let payload = match result {
+        Ok(payload) => payload,
+        Err(error) => {
+            log::error!(
+                "[RECEIVER] failed to receive response because of: {}",
+                error
+            );
+            metrics.failed.inc();
+            return;
+        }
+    };
+
+    log::trace!("[RECEIVER] compare expected and received values");
 
-    unreachable!("[SYSTEM] somethink gone wrong");
+    match expected_result.cmp(&payload.result) {
+        Ordering::Greater | Ordering::Less => {
+            log::error!("[RECEIVER] received message with unexpected payload");
+            metrics.failed.inc();
+        }
+        Ordering::Equal => {
+            metrics.received.inc();
+            latency_report.lock(move |latency_report| latency_report.record(latency));
+        }
+    }
 }
@@ -0,0 +1,1536 @@
+use crate::config::BusConfig;
+use crate::curve::CurveError;
+use crate::curve::CurveServer;
+use crate::curve::ZapHandler;
+use crate::curve::ZAP_ENDPOINT_NAME;
+use crate::endpoint::join_endpoints;
+use crate::endpoint::Endpoint;
+use crate::helpers::BusPublisherData;
+use crate::journal::Journal;
+use crate::journal::JournalError;
+use crate::journal::JournalId;
+use crate::journal::PendingMessages;
+use crate::metrics::Counter;
+use crate::metrics::Gauge;
+use crate::metrics::Metrics;
+use crate::queue::BoundedQueue;
+use crate::queue::OverflowPolicy;
+use crate::registry::ServiceRegistry;
+use crate::selector::PublisherLoad;
+use crate::selector::PublisherSelection;
+use crate::selector::PublisherSelector;
+use crate::shutdown::set_linger;
+use crate::shutdown::Shutdown;
+use crate::shutdown::SHUTDOWN_CHECK_INTERVAL;
+use crate::subscriptions::SubscriptionTable;
+use crate::topic::send_published_message;
+use crate::work_queue::QueuedRequest;
+use crate::work_queue::WorkQueues;
+use crate::PAYLOAD_FORMAT;
+use crate::ZEROMQ_ZERO_FLAG;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::vec;
+use uuid::Uuid;
+use zeromq_messages::codec::encode_message_with_format;
+use zeromq_messages::codec::MessageDecodeError;
+use zeromq_messages::codec::MessageEncodeError;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::BusAcknowledgement;
+use zeromq_messages::messages::BusRegistry;
+use zeromq_messages::messages::BusRegistryItemInstances;
+use zeromq_messages::messages::BusRegistryQuery;
+use zeromq_messages::messages::BusRejection;
+use zeromq_messages::messages::BusServiceRegistration;
+use zeromq_messages::messages::BusSubscriptions;
+use zeromq_messages::messages::BusSubscriptionsItemPublishers;
+use zeromq_messages::messages::BusSubscriptionsQuery;
+use zeromq_messages::messages::BusWorkerReady;
+use zeromq_messages::template::ZeromqMessageTrait;
+use zeromq_messages::view::MessageView;
+use zmq::Context;
+use zmq::Message;
+use zmq::PollEvents;
+use zmq::PollItem;
+use zmq::Socket;
+use zmq::SocketType;
+
+/// Messages which BUS loop receives from router socket, and publishes from queue, on
+/// every iteration.
+const MAX_BATCH_SIZE: usize = 256;
+/// How often messages which publisher sockets failed to send are retried.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10_u64);
+/// How often expired instances are forgotten and queued requests are dispatched again.
+const HOUSEKEEPING_INTERVAL: Duration = SHUTDOWN_CHECK_INTERVAL;
+/// How often BUS logs its counters, if they changed.
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(10_u64);
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum BusError {
+    #[error("Failed to initialize BUS socket")]
+    CantCreateSocket(#[from] zmq::Error),
+
+    #[error("Binding BUS socket on {endpoint} failed")]
+    CantBind {
+        endpoint: Endpoint,
+        #[source]
+        source: zmq::Error,
+    },
+
+    #[error("Failed to create directory {0} for ipc sockets")]
+    CantCreateIpcDirectory(PathBuf, #[source] io::Error),
+
+    #[error("Failed to encode reply to sender")]
+    CantEncodeReply(#[from] MessageEncodeError),
+
+    #[error("Failed to send reply to sender")]
+    CantSendReply(#[source] zmq::Error),
+
+    #[error("Failed to open journal")]
+    CantOpenJournal(#[from] JournalError),
+
+    #[error("Failed to set up CURVE security")]
+    Curve(#[from] CurveError),
+}
+
+//-----------------------------------------------------------------------------------------
+// BusStats
+//-----------------------------------------------------------------------------------------
+
+/// Counters of messages which passed through BUS during its run.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct BusStats {
+    /// Messages received on router socket, except heartbeats.
+    pub received: usize,
+    /// Messages left unpublished by previous run and taken from journal.
+    pub replayed: usize,
+    pub published: usize,
+    /// Directed messages delivered to one instance of destination service and responses
+    /// delivered to senders of queued requests.
+    pub routed: usize,
+    /// Queued requests delivered to one worker.
+    pub dispatched: usize,
+    /// Messages lost because of full queue, failed publishing on shutdown or queued
+    /// requests left unanswered on shutdown.
+    pub dropped: usize,
+    /// Messages answered with `BusRejection` because of full queue or unavailable
+    /// destination.
+    pub rejected: usize,
+    /// Messages answered with `BusAcknowledgement`.
+    pub acknowledged: usize,
+    /// Messages not published because nobody was subscribed to their kind.
+    pub skipped: usize,
+}
+
+impl fmt::Display for BusStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "received {} messages, replayed {} messages, published {} messages, routed {} messages, dispatched {} messages, dropped {} messages, rejected {} messages, acknowledged {} messages, skipped {} messages",
+            self.received,
+            self.replayed,
+            self.published,
+            self.routed,
+            self.dispatched,
+            self.dropped,
+            self.rejected,
+            self.acknowledged,
+            self.skipped
+        )
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// BusMetrics
+//-----------------------------------------------------------------------------------------
+
+/// Counters behind `BusStats` together with the rest of BUS metrics. Clones share the
+/// same counters, so they are served by metrics server while BUS loop updates them.
+#[derive(Debug, Clone)]
+struct BusMetrics {
+    registry: Metrics,
+    received: Arc<Counter>,
+    replayed: Arc<Counter>,
+    published: Arc<Counter>,
+    routed: Arc<Counter>,
+    dispatched: Arc<Counter>,
+    dropped: Arc<Counter>,
+    rejected: Arc<Counter>,
+    acknowledged: Arc<Counter>,
+    skipped: Arc<Counter>,
+    retry_buffer_depth: Arc<Gauge>,
+    parked_messages: Arc<Gauge>,
+}
+
+impl BusMetrics {
+    fn new(registry: Metrics) -> Self {
+        let counter = |name, help| registry.counter(name, help, &[]);
+
+        Self {
+            received: counter(
+                "bus_received_messages_total",
+                "Messages received on router socket, except heartbeats.",
+            ),
+            replayed: counter(
+                "bus_replayed_messages_total",
+                "Messages taken from journal left by previous run.",
+            ),
+            published: counter(
+                "bus_published_messages_total",
+                "Messages published to subscribers.",
+            ),
+            routed: counter(
+                "bus_routed_messages_total",
+                "Directed messages and responses to queued requests delivered to one socket.",
+            ),
+            dispatched: counter(
+                "bus_dispatched_messages_total",
+                "Queued requests delivered to one worker.",
+            ),
+            dropped: counter("bus_dropped_messages_total", "Messages lost by BUS."),
+            rejected: counter(
+                "bus_rejected_messages_total",
+                "Messages answered with rejection.",
+            ),
+            acknowledged: counter(
+                "bus_acknowledged_messages_total",
+                "Messages answered with acknowledgement.",
+            ),
+            skipped: counter(
+                "bus_skipped_messages_total",
+                "Messages not published because nobody was subscribed to their kind.",
+            ),
+            retry_buffer_depth: registry.gauge(
+                "bus_retry_buffer_messages",
+                "Messages which failed to be published and wait for retry.",
+                &[],
+            ),
+            parked_messages: registry.gauge(
+                "bus_parked_messages",
+                "Messages which wait until somebody subscribes to their kind.",
+                &[],
+            ),
+            registry,
+        }
+    }
+
+    /// Counter of messages sent through publisher socket with given index.
+    fn publisher_sent(&self, index: usize) -> Arc<Counter> {
+        self.registry.counter(
+            "bus_publisher_sent_messages_total",
+            "Messages sent through publisher socket.",
+            &[("publisher", &index.to_string())],
+        )
+    }
+
+    fn count_decode_error(&self, error: &MessageDecodeError) {
+        self.registry
+            .counter(
+                "bus_decode_errors_total",
+                "Messages which BUS failed to decode.",
+                &[("error", error.variant_name())],
+            )
+            .inc();
+    }
+
+    fn stats(&self) -> BusStats {
+        let value = |counter: &Counter| usize::try_from(counter.get()).unwrap_or(usize::MAX);
+
+        BusStats {
+            received: value(&self.received),
+            replayed: value(&self.replayed),
+            published: value(&self.published),
+            routed: value(&self.routed),
+            dispatched: value(&self.dispatched),
+            dropped: value(&self.dropped),
+            rejected: value(&self.rejected),
+            acknowledged: value(&self.acknowledged),
+            skipped: value(&self.skipped),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// ReceivedMessage
+//-----------------------------------------------------------------------------------------
+
+/// Message waiting to be published together with its kind and journal record.
+struct ReceivedMessage {
+    journal_id: Option<JournalId>,
+    uuid: Uuid,
+    kind: ZeromqMessageKind,
+    message: Message,
+}
+
+//-----------------------------------------------------------------------------------------
+// Bus
+//-----------------------------------------------------------------------------------------
+
+/// BUS which receives messages from services via router socket and publishes them to
+/// subscribers of their kind via publisher sockets.
+pub struct Bus {
+    router_socket: Socket,
+    publishers: Vec<BusPublisherData>,
+    zap_handler: Option<ZapHandler>,
+    group_size: usize,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    publisher_selection: PublisherSelection,
+    subscriptions: SubscriptionTable,
+    park_unsubscribed_messages: bool,
+    acknowledge_messages: bool,
+    queue_kinds: Vec<ZeromqMessageKind>,
+    heartbeat_timeout: Duration,
+    journal: Option<Journal>,
+    replayed_messages: PendingMessages,
+    metrics: BusMetrics,
+}
+
+impl Bus {
+    /// Opens journal, if it is enabled, and binds BUS sockets, so services are able to
+    /// connect right after this call.
+    pub fn bind(context: &Context, config: &BusConfig) -> Result<Self, BusError> {
+        let (journal, replayed_messages) = match &config.journal_directory {
+            Some(journal_directory) => {
+                let (journal, replayed_messages) = Journal::open(
+                    journal_directory,
+                    config.journal_segment_size,
+                    config.journal_compaction_percent,
+                    config.journal_sync,
+                )?;
+
+                log::debug!(
+                    "opened journal in {}, {} messages will be replayed",
+                    journal_directory.display(),
+                    replayed_messages.len()
+                );
+
+                (Some(journal), replayed_messages)
+            }
+            None => (None, Vec::new()),
+        };
+
+        let router_endpoint = config.router_bind_endpoint();
+        let publisher_endpoints = config.publisher_bind_endpoints();
+
+        // ZAP handler has to be bound before sockets, which ask it about every client.
+        let curve_server = CurveServer::from_config(config)?;
+        let zap_handler = match &curve_server {
+            Some(curve_server) => curve_server.zap_handler(context)?,
+            None => None,
+        };
+        if let Some(zap_handler) = &zap_handler {
+            let zap_endpoint = Endpoint::Inproc(String::from(ZAP_ENDPOINT_NAME));
+            bind(zap_handler.socket(), &zap_endpoint)?;
+        }
+        if let Some(curve_server) = &curve_server {
+            log::info!(
+                "BUS sockets use CURVE security with public key {}, {}",
+                curve_server.public_key(),
+                if zap_handler.is_some() {
+                    "clients are checked against allowlist"
+                } else {
+                    "every client is allowed"
+                }
+            );
+        }
+
+        let router_socket = context.socket(SocketType::ROUTER)?;
+        set_linger(&router_socket)?;
+        router_socket.set_router_mandatory(true)?;
+        if let Some(curve_server) = &curve_server {
+            curve_server.apply(&router_socket)?;
+        }
+
+        log::debug!("initialized BUS router socket");
+
+        bind(&router_socket, &router_endpoint)?;
+
+        log::debug!("BUS router socket binded on {}", router_endpoint);
+
+        let mut publishers: Vec<BusPublisherData> =
+            Vec::with_capacity(publisher_endpoints.len());
+
+        for publisher_endpoint in &publisher_endpoints {
+            let publisher = context.socket(SocketType::XPUB)?;
+            set_linger(&publisher)?;
+            if let Some(curve_server) = &curve_server {
+                curve_server.apply(&publisher)?;
+            }
+            bind(&publisher, publisher_endpoint)?;
+            publishers.push(BusPublisherData::new(publisher));
+        }
+
+        let metrics = BusMetrics::new(Metrics::new());
+        for index in 0..publishers.len() {
+            let _ = metrics.publisher_sent(index);
+        }
+
+        log::debug!(
+            "initialized BUS publisher sockets and binded on {}",
+            join_endpoints(&publisher_endpoints)
+        );
+
+        let subscriptions =
+            SubscriptionTable::new(publisher_endpoints.iter().map(ToString::to_string));
+
+        Ok(Self {
+            router_socket,
+            publishers,
+            zap_handler,
+            group_size: config.group_size,
+            queue_capacity: config.queue_capacity,
+            overflow_policy: config.overflow_policy,
+            publisher_selection: config.publisher_selection,
+            subscriptions,
+            park_unsubscribed_messages: config.park_unsubscribed_messages,
+            acknowledge_messages: config.acknowledge_messages,
+            queue_kinds: config.queue_kinds.clone(),
+            heartbeat_timeout: config.heartbeat_timeout(),
+            journal,
+            replayed_messages,
+            metrics,
+        })
+    }
+
+    /// Records BUS metrics in given registry instead of a private one, e.g. to serve them
+    /// together with metrics of services running in the same process.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: &Metrics) -> Self {
+        self.metrics = BusMetrics::new(metrics.clone());
+        for index in 0..self.publishers.len() {
+            let _ = self.metrics.publisher_sent(index);
+        }
+        self
+    }
+
+    /// Registry of BUS metrics, which is updated while BUS runs.
+    #[must_use]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics.registry
+    }
+
+    /// Runs event loop on current thread until shutdown is requested. Messages which were
+    /// already received are published before returning.
+    #[must_use]
+    pub fn run(self, shutdown: &Shutdown) -> BusStats {
+        let Self {
+            router_socket,
+            publishers,
+            zap_handler,
+            group_size,
+            queue_capacity,
+            overflow_policy,
+            publisher_selection,
+            subscriptions,
+            park_unsubscribed_messages,
+            acknowledge_messages,
+            queue_kinds,
+            heartbeat_timeout,
+            journal,
+            replayed_messages,
+            metrics,
+        } = self;
+
+        log::debug!("running BUS loop");
+        let now = Instant::now();
+        BusLoop {
+            router_socket: &router_socket,
+            publishers: &publishers,
+            zap_handler: zap_handler.as_ref(),
+            journal: journal.as_ref(),
+            received_messages: BoundedQueue::new(queue_capacity, overflow_policy),
+            replayed_messages: replayed_messages.into_iter(),
+            unsynced_acknowledgements: Vec::new(),
+            overflow_policy,
+            acknowledge_messages,
+            service_registry: ServiceRegistry::default(),
+            heartbeat_timeout,
+            work_queues: WorkQueues::new(&queue_kinds, queue_capacity),
+            selector: publisher_selection.selector(),
+            publisher_loads: vec![PublisherLoad::default(); publishers.len()],
+            publisher_sent_counters: (0..publishers.len())
+                .map(|index| metrics.publisher_sent(index))
+                .collect(),
+            errored_messages: VecDeque::new(),
+            buffer_capacity: queue_capacity,
+            subscriptions,
+            park_unsubscribed_messages,
+            parked_messages: HashMap::new(),
+            parked_count: 0,
+            group_size: u64::try_from(group_size).unwrap_or(u64::MAX),
+            retry_timer: Timer::new(RETRY_INTERVAL, now),
+            housekeeping_timer: Timer::new(HOUSEKEEPING_INTERVAL, now),
+            stats_timer: Timer::new(STATS_LOG_INTERVAL, now),
+            logged_stats: BusStats::default(),
+            is_stopping: false,
+            metrics: metrics.clone(),
+        }
+        .run(shutdown);
+
+        let stats = metrics.stats();
+        log::info!("BUS stopped: {}", stats);
+        stats
+    }
+}
+
+impl fmt::Debug for Bus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bus")
+            .field("publishers", &self.publishers)
+            .field("zap_handler", &self.zap_handler)
+            .field("group_size", &self.group_size)
+            .field("queue_capacity", &self.queue_capacity)
+            .field("overflow_policy", &self.overflow_policy)
+            .field("publisher_selection", &self.publisher_selection)
+            .field(
+                "park_unsubscribed_messages",
+                &self.park_unsubscribed_messages,
+            )
+            .field("acknowledge_messages", &self.acknowledge_messages)
+            .field("queue_kinds", &self.queue_kinds)
+            .field("heartbeat_timeout", &self.heartbeat_timeout)
+            .field("journal", &self.journal)
+            .finish_non_exhaustive()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Timer
+//-----------------------------------------------------------------------------------------
+
+/// Periodic action of BUS loop.
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    interval: Duration,
+    next_time: Instant,
+}
+
+impl Timer {
+    fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            next_time: now + interval,
+        }
+    }
+
+    /// Returns `true` at most once per interval and schedules the next run.
+    fn is_due(&mut self, now: Instant) -> bool {
+        if now < self.next_time {
+            return false;
+        }
+        self.next_time = now + self.interval;
+        true
+    }
+
+    fn remaining(&self, now: Instant) -> Duration {
+        self.next_time.saturating_duration_since(now)
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// BusLoop
+//-----------------------------------------------------------------------------------------
+
+/// Event loop, which owns router and publisher sockets and polls all of them at once.
+/// Directed messages, queued requests with their responses, registrations and heartbeats
+/// of services and queries are handled right away, the rest of messages are put into
+/// queue and published to subscribers. Subscription events of publishers are read as
+/// they arrive, so messages of kinds nobody is subscribed to are skipped, or parked until
+/// somebody subscribes if parking is enabled.
+struct BusLoop<'a> {
+    router_socket: &'a Socket,
+    publishers: &'a [BusPublisherData],
+    /// Authenticates clients of sockets, which use CURVE security with allowlist.
+    zap_handler: Option<&'a ZapHandler>,
+    journal: Option<&'a Journal>,
+    received_messages: BoundedQueue<ReceivedMessage>,
+    /// Messages left by previous run, they are queued before router socket is read.
+    replayed_messages: vec::IntoIter<(JournalId, Vec<u8>)>,
+    /// Identities and uuids of journaled messages, which are acknowledged once journal
+    /// is flushed after the batch they were received in.
+    unsynced_acknowledgements: Vec<(Message, Uuid)>,
+    overflow_policy: OverflowPolicy,
+    acknowledge_messages: bool,
+    service_registry: ServiceRegistry,
+    heartbeat_timeout: Duration,
+    work_queues: WorkQueues,
+    selector: Box<dyn PublisherSelector>,
+    publisher_loads: Vec<PublisherLoad>,
+    publisher_sent_counters: Vec<Arc<Counter>>,
+    /// Failed messages with index of publisher which failed to send them.
+    errored_messages: VecDeque<(usize, ReceivedMessage)>,
+    /// Capacity of retry buffer and of parked messages.
+    buffer_capacity: usize,
+    subscriptions: SubscriptionTable,
+    park_unsubscribed_messages: bool,
+    parked_messages: HashMap<ZeromqMessageKind, VecDeque<ReceivedMessage>>,
+    parked_count: usize,
+    group_size: u64,
+    retry_timer: Timer,
+    housekeeping_timer: Timer,
+    stats_timer: Timer,
+    logged_stats: BusStats,
+    /// Set once shutdown is requested and remaining messages are published.
+    is_stopping: bool,
+    metrics: BusMetrics,
+}
+
+impl<'a> BusLoop<'a> {
+    /// Handles sockets and timers until shutdown is requested.
+    fn run(mut self, shutdown: &Shutdown) {
+        let router_socket = self.router_socket;
+        let publishers = self.publishers;
+        let zap_handler = self.zap_handler;
+
+        // Router socket goes first, publisher sockets follow in order of their indexes,
+        // ZAP handler socket is the last one.
+        let mut poll_items: Vec<PollItem<'a>> = iter::once(router_socket)
+            .chain(publishers.iter().map(|publisher| &**publisher))
+            .chain(zap_handler.map(ZapHandler::socket))
+            .map(|socket| socket.as_poll_item(zmq::POLLIN))
+            .collect();
+
+        while !shutdown.is_requested() {
+            self.replay_messages();
+
+            poll_items[0].set_events(if self.is_receiving() {
+                zmq::POLLIN
+            } else {
+                PollEvents::empty()
+            });
+
+            match zmq::poll(&mut poll_items, self.poll_timeout_millis()) {
+                Ok(_) => {}
+                Err(zmq::Error::EINTR) => continue,
+                Err(error) => {
+                    log::error!("failed to poll BUS sockets because of: {}", error);
+                    continue;
+                }
+            }
+
+            if poll_items[0].is_readable() {
+                self.receive_messages();
+                self.acknowledge_synced();
+            }
+            if poll_items[1..=publishers.len()]
+                .iter()
+                .any(PollItem::is_readable)
+            {
+                self.receive_subscriptions();
+            }
+            if let Some(zap_handler) = zap_handler {
+                if poll_items[publishers.len() + 1].is_readable() {
+                    zap_handler.handle_requests();
+                }
+            }
+
+            self.publish_received();
+            self.run_timers();
+        }
+
+        self.stop();
+    }
+
+    /// Publishes messages which are already received and retries failed ones for the last
+    /// time. Queued requests which are not answered by then are dropped, their senders
+    /// resend them. Messages which are parked, fail again or still wait for replay are
+    /// left in journal, if it is enabled.
+    fn stop(mut self) {
+        self.is_stopping = true;
+
+        while let Some(received_message) = self.received_messages.pop() {
+            self.publish_or_hold(received_message);
+        }
+        self.retry_errored();
+
+        self.metrics
+            .dropped
+            .add(u64::try_from(self.work_queues.len()).unwrap_or(u64::MAX));
+
+        if self.parked_count > 0 {
+            log::warn!(
+                "{} parked messages are left unpublished because nobody subscribed to them",
+                self.parked_count
+            );
+        }
+
+        if self.replayed_messages.len() > 0 {
+            log::debug!(
+                "{} messages are left in journal for the next run",
+                self.replayed_messages.len()
+            );
+        }
+
+        log::debug!("BUS loop stopped");
+    }
+
+    /// Router socket is read once journal is replayed, and with `Block` policy only while
+    /// there is free space in queue, so senders are slowed down by high water marks of
+    /// their sockets.
+    fn is_receiving(&self) -> bool {
+        self.replayed_messages.as_slice().is_empty()
+            && !(self.overflow_policy == OverflowPolicy::Block
+                && self.received_messages.is_full())
+    }
+
+    /// Sockets are only checked while there are messages to publish, otherwise they are
+    /// waited for until the next timer is due or shutdown is checked.
+    #[allow(clippy::cast_possible_truncation)]
+    fn poll_timeout_millis(&self) -> i64 {
+        if !self.received_messages.is_empty() || !self.replayed_messages.as_slice().is_empty()
+        {
+            return 0;
+        }
+
+        let now = Instant::now();
+        let mut timeout = SHUTDOWN_CHECK_INTERVAL
+            .min(self.housekeeping_timer.remaining(now))
+            .min(self.stats_timer.remaining(now));
+        if !self.errored_messages.is_empty() {
+            timeout = timeout.min(self.retry_timer.remaining(now));
+        }
+
+        timeout.as_micros().div_ceil(1000) as i64
+    }
+
+    fn run_timers(&mut self) {
+        let now = Instant::now();
+
+        if self.retry_timer.is_due(now) && !self.errored_messages.is_empty() {
+            self.retry_errored();
+        }
+
+        // Requests which workers were too busy to take are dispatched again. Journal is
+        // compacted here rather than on every done message.
+        if self.housekeeping_timer.is_due(now) {
+            self.expire_instances(now);
+            self.dispatch_work();
+            compact(self.journal);
+        }
+
+        if self.stats_timer.is_due(now) {
+            self.log_stats();
+        }
+    }
+
+    /// Logs counters if anything passed through BUS since they were logged the last time.
+    fn log_stats(&mut self) {
+        let stats = self.metrics.stats();
+        if stats != self.logged_stats {
+            log::debug!("BUS stats: {}", stats);
+            self.logged_stats = stats;
+        }
+    }
+
+    //-------------------------------------------------------------------------------------
+    // Receiving
+    //-------------------------------------------------------------------------------------
+
+    /// Receives at most `MAX_BATCH_SIZE` messages which are waiting in router socket, so
+    /// busy senders do not hold back publishing and timers.
+    fn receive_messages(&mut self) {
+        let mut identity = Message::new();
+
+        for _ in 0..MAX_BATCH_SIZE {
+            if !self.is_receiving() {
+                return;
+            }
+
+            // Firstly receive first message frame which is the sender identity.
+            match self.router_socket.recv(&mut identity, zmq::DONTWAIT) {
+                Ok(()) => {}
+                Err(zmq::Error::EAGAIN) => return,
+                Err(error) => {
+                    log::error!("failed to receive sender identity because of: {}", error);
+                    return;
+                }
+            }
+
+            log::trace!("< [IDENTITY] {:?}", &*identity);
+
+            // Frames of message arrive together, so the next frame with message content is
+            // already there. Received message is queued as is, without copying its bytes.
+            let mut message = Message::new();
+            if let Err(error) = self.router_socket.recv(&mut message, ZEROMQ_ZERO_FLAG) {
+                log::error!("failed to receive message because of: {}", &error);
+                continue;
+            }
+
+            log::trace!("< {:?}", &*message);
+
+            self.receive(&identity, message);
+        }
+    }
+
+    fn receive(&mut self, identity: &Message, message: Message) {
+        // Message kind is required to build topic which subscribers filter on.
+        let message_view = match MessageView::new(&message) {
+            Ok(message_view) => message_view,
+            Err(error) => {
+                log::error!("failed to decode message header because of: {}", error);
+                self.metrics.count_decode_error(&error);
+                return;
+            }
+        };
+
+        let (message_kind, message_uuid) = (message_view.kind(), message_view.uuid());
+
+        if message_kind != ZeromqMessageKind::BusHeartbeat {
+            self.metrics.received.inc();
+        }
+
+        // Any message from registered instance proves that it is alive.
+        let is_registered = self.service_registry.touch(identity, Instant::now());
+
+        if self.receive_control_message(identity, message_view, is_registered) {
+            return;
+        }
+
+        // Response to queued request has the same uuid and comes from its worker.
+        if let Some(requester) = self.work_queues.complete(message_uuid, identity) {
+            self.respond(&requester, message_uuid, &message);
+            return;
+        }
+
+        if let Some(destination) = message_view.destination() {
+            self.route(identity, message_uuid, destination, &message);
+            return;
+        }
+
+        if self.work_queues.is_queued(message_kind) {
+            self.enqueue_work(identity, message_uuid, message_kind, message);
+            return;
+        }
+
+        self.enqueue(identity, message_uuid, message_kind, message);
+    }
+
+    /// Handles messages addressed to BUS itself, returns `false` for the rest of messages.
+    fn receive_control_message(
+        &mut self,
+        identity: &Message,
+        message_view: MessageView<'_>,
+        is_registered: bool,
+    ) -> bool {
+        let (kind, uuid) = (message_view.kind(), message_view.uuid());
+
+        let result = match kind {
+            ZeromqMessageKind::BusServiceRegistration => message_view
+                .decode_payload::<BusServiceRegistration>()
+                .map(|registration| self.register_service(identity, uuid, &registration)),
+            ZeromqMessageKind::BusWorkerReady => message_view
+                .decode_payload::<BusWorkerReady>()
+                .map(|worker_ready| self.add_worker(identity, uuid, &worker_ready)),
+            ZeromqMessageKind::BusRegistryQuery => message_view
+                .decode_payload::<BusRegistryQuery>()
+                .map(|query| self.answer_registry_query(identity, uuid, &query)),
+            ZeromqMessageKind::BusSubscriptionsQuery => message_view
+                .decode_payload::<BusSubscriptionsQuery>()
+                .map(|_| self.answer_subscriptions_query(identity, uuid)),
+            // Heartbeats are not acknowledged, instance which BUS does not know is asked
+            // to register again by rejection.
+            ZeromqMessageKind::BusHeartbeat => {
+                if !is_registered {
+                    self.reject(
+                        identity,
+                        uuid,
+                        String::from("Service instance is not registered"),
+                    );
+                }
+                Ok(())
+            }
+            _ => return false,
+        };
+
+        if let Err(error) = result {
+            log::error!("failed to decode {:?} message because of: {}", kind, error);
+            self.metrics.count_decode_error(&error);
+        }
+
+        true
+    }
+
+    fn register_service(
+        &mut self,
+        identity: &Message,
+        uuid: Uuid,
+        registration: &BusServiceRegistration,
+    ) {
+        let kinds = registration
+            .kinds
+            .iter()
+            .filter_map(|kind| ZeromqMessageKind::from_number(*kind))
+            .collect();
+
+        if self.service_registry.register(
+            identity,
+            &registration.name,
+            &registration.instance_id,
+            kinds,
+            Instant::now(),
+        ) {
+            log::debug!(
+                "registered instance {} of service {}",
+                registration.instance_id,
+                registration.name
+            );
+        }
+
+        self.acknowledge(identity, uuid, false);
+    }
+
+    fn answer_registry_query(
+        &mut self,
+        identity: &Message,
+        uuid: Uuid,
+        query: &BusRegistryQuery,
+    ) {
+        let kind = query.kind.and_then(ZeromqMessageKind::from_number);
+        let instances = self
+            .service_registry
+            .instances(kind)
+            .into_iter()
+            .map(|instance| BusRegistryItemInstances {
+                name: instance.name.clone(),
+                instance_id: instance.instance_id.clone(),
+                kinds: instance.kinds.iter().map(|kind| kind.number()).collect(),
+            })
+            .collect();
+
+        let result = reply(
+            self.router_socket,
+            identity,
+            uuid,
+            BusRegistry { instances },
+        );
+        log_reply_error("registry", result);
+    }
+
+    /// Answers with kinds to which subscribers of every publisher socket are subscribed.
+    fn answer_subscriptions_query(&mut self, identity: &Message, uuid: Uuid) {
+        self.receive_subscriptions();
+
+        let publishers = self
+            .subscriptions
+            .publishers()
+            .iter()
+            .map(|publisher| BusSubscriptionsItemPublishers {
+                endpoint: publisher.endpoint.clone(),
+                kinds: publisher
+                    .kinds()
+                    .into_iter()
+                    .map(ZeromqMessageKind::number)
+                    .collect(),
+            })
+            .collect();
+
+        let result = reply(
+            self.router_socket,
+            identity,
+            uuid,
+            BusSubscriptions { publishers },
+        );
+        log_reply_error("subscriptions", result);
+    }
+
+    /// Forgets instances which did not send anything within heartbeat timeout. Requests
+    /// dispatched to expired workers are given to other workers.
+    fn expire_instances(&mut self, now: Instant) {
+        let expired_identities = self.service_registry.expire(now, self.heartbeat_timeout);
+        if expired_identities.is_empty() {
+            return;
+        }
+
+        for identity in &expired_identities {
+            log::debug!("instance {:?} expired", identity);
+            self.work_queues.remove_worker(identity);
+        }
+
+        self.dispatch_work();
+    }
+
+    /// Forgets instance which is not connected to router socket anymore.
+    fn forget_instance(&mut self, identity: &[u8]) {
+        let _ = self.service_registry.unregister(identity);
+        self.work_queues.remove_worker(identity);
+    }
+
+    fn add_worker(&mut self, identity: &Message, uuid: Uuid, worker_ready: &BusWorkerReady) {
+        let kinds: Vec<ZeromqMessageKind> = worker_ready
+            .kinds
+            .iter()
+            .filter_map(|kind| ZeromqMessageKind::from_number(*kind))
+            .collect();
+        let credit = usize::try_from(worker_ready.credit).unwrap_or(0);
+
+        log::debug!(
+            "worker {:?} is ready to take {} requests of {:?}",
+            &**identity,
+            credit,
+            kinds
+        );
+
+        self.work_queues.add_worker(identity, &kinds, credit);
+        self.acknowledge(identity, uuid, false);
+        self.dispatch_work();
+    }
+
+    /// Puts request into work queue of its kind and dispatches it if there is free worker.
+    fn enqueue_work(
+        &mut self,
+        identity: &Message,
+        uuid: Uuid,
+        kind: ZeromqMessageKind,
+        message: Message,
+    ) {
+        // Client resends request until it is answered, the copy must not take another
+        // credit of worker.
+        if self.work_queues.contains(uuid) {
+            log::trace!("acknowledged already queued message {}", uuid);
+            self.acknowledge_client(identity, uuid, false);
+            return;
+        }
+
+        let request = QueuedRequest {
+            requester: identity.to_vec(),
+            uuid,
+            kind,
+            message,
+        };
+
+        if self.work_queues.push(request).is_err() {
+            log::trace!("rejected message {} because work queue is full", uuid);
+            self.reject(identity, uuid, String::from("BUS queue is full"));
+            return;
+        }
+
+        self.acknowledge_client(identity, uuid, false);
+        self.dispatch_work();
+    }
+
+    /// Sends queued requests to workers while there are workers with free credit. Request
+    /// of worker which is gone is given to another worker, request of busy worker waits.
+    fn dispatch_work(&mut self) {
+        while let Some((worker, request)) = self.work_queues.next_dispatch() {
+            match send_directed_message(self.router_socket, &worker, &request.message) {
+                Ok(()) => {
+                    log::trace!("> [WORKER] {:?}", &*request.message);
+                    self.metrics.dispatched.inc();
+                    self.work_queues.mark_in_flight(worker, request);
+                }
+                Err(zmq::Error::EHOSTUNREACH) => {
+                    log::debug!("worker {:?} is gone", worker);
+                    self.work_queues.requeue(request);
+                    self.forget_instance(&worker);
+                }
+                Err(error) => {
+                    if error != zmq::Error::EAGAIN {
+                        log::error!("failed to dispatch message because of: {}", error);
+                    }
+                    self.work_queues.requeue(request);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Delivers response of worker to sender of queued request and gives the next request
+    /// to the worker. Response is not acknowledged, worker does not wait for it.
+    fn respond(&mut self, requester: &[u8], uuid: Uuid, message: &Message) {
+        match send_directed_message(self.router_socket, requester, message) {
+            Ok(()) => {
+                log::trace!("> [REQUESTER] {:?}", &**message);
+                self.metrics.routed.inc();
+            }
+            Err(error) => {
+                log::trace!("dropped response {} because of: {}", uuid, error);
+                self.metrics.dropped.inc();
+            }
+        }
+
+        self.dispatch_work();
+    }
+
+    /// Delivers directed message to one instance of destination service, or to instance
+    /// with destination id. Instance which is gone is forgotten and the next one is tried,
+    /// busy instance is skipped.
+    fn route(&mut self, identity: &Message, uuid: Uuid, destination: &str, message: &Message) {
+        let mut is_any_instance_busy = false;
+
+        for instance in self.service_registry.instances_in_turn(destination) {
+            match send_directed_message(self.router_socket, &instance, message) {
+                Ok(()) => {
+                    log::trace!("> [{}] {:?}", destination, &**message);
+                    self.metrics.routed.inc();
+                    self.acknowledge_client(identity, uuid, false);
+                    return;
+                }
+                Err(zmq::Error::EHOSTUNREACH) => {
+                    log::debug!("instance {:?} of service {} is gone", instance, destination);
+                    self.forget_instance(&instance);
+                }
+                Err(zmq::Error::EAGAIN) => {
+                    log::trace!("instance {:?} of service {} is busy", instance, destination);
+                    is_any_instance_busy = true;
+                }
+                Err(error) => {
+                    log::error!("failed to route message because of: {}", error);
+                }
+            }
+        }
+
+        log::trace!(
+            "rejected message {} because {} is unavailable",
+            uuid,
+            destination
+        );
+        let reason = if is_any_instance_busy {
+            format!("All instances of service {destination} are busy")
+        } else {
+            format!("No available instance of service {destination}")
+        };
+        self.reject(identity, uuid, reason);
+    }
+
+    fn enqueue(
+        &mut self,
+        identity: &Message,
+        uuid: Uuid,
+        kind: ZeromqMessageKind,
+        message: Message,
+    ) {
+        // Full queue is handled before message is journaled, so message which is not
+        // accepted is not written to journal at all.
+        if self.received_messages.is_full() {
+            match self.overflow_policy {
+                OverflowPolicy::Reject => {
+                    log::trace!("rejected message {} because queue is full", uuid);
+                    self.reject(identity, uuid, String::from("BUS queue is full"));
+                    return;
+                }
+                // Router socket is not read while queue is full with `Block` policy, so
+                // only message which is already received ends up here.
+                OverflowPolicy::Block | OverflowPolicy::DropNewest => {
+                    log::trace!("dropped message {} because queue is full", uuid);
+                    self.metrics.dropped.inc();
+                    self.reject(
+                        identity,
+                        uuid,
+                        String::from("BUS queue is full, message is dropped"),
+                    );
+                    return;
+                }
+                OverflowPolicy::DropOldest => {}
+            }
+        }
+
+        // Message is written to journal before BUS takes responsibility for it. If journal
+        // fails message is still published, but it won't survive BUS restart.
+        let journal_id = self
+            .journal
+            .and_then(|journal| match journal.append(&message) {
+                Ok(journal_id) => Some(journal_id),
+                Err(error) => {
+                    log::error!("failed to write message to journal because of: {}", error);
+                    None
+                }
+            });
+
+        // Only `DropOldest` pushes into full queue, new message is accepted in place of the
+        // oldest one, which was already acknowledged to its sender, so it is lost.
+        if let Some(overflowed_message) = self.received_messages.push(ReceivedMessage {
+            journal_id,
+            uuid,
+            kind,
+            message,
+        }) {
+            log::trace!(
+                "dropped the oldest message {} because queue is full",
+                overflowed_message.uuid
+            );
+            mark_done(self.journal, overflowed_message.journal_id);
+            self.metrics.dropped.inc();
+        }
+
+        self.acknowledge_journaled(identity, uuid, journal_id);
+    }
+
+    /// Journaled message is acknowledged only once its record is flushed to disk, which
+    /// is deferred until the end of batch unless journal flushes every message. Same as
+    /// `acknowledge_client`, messages of service instances are not acknowledged.
+    fn acknowledge_journaled(
+        &mut self,
+        identity: &Message,
+        uuid: Uuid,
+        journal_id: Option<JournalId>,
+    ) {
+        if self.service_registry.contains(identity) {
+            return;
+        }
+
+        match self.journal {
+            Some(journal) if journal_id.is_some() && !journal.syncs_every_message() => {
+                if self.acknowledge_messages {
+                    self.unsynced_acknowledgements
+                        .push((Message::from(&identity[..]), uuid));
+                }
+            }
+            _ => self.acknowledge(identity, uuid, journal_id.is_some()),
+        }
+    }
+
+    /// Acknowledges message unless it comes from registered service instance. Services
+    /// don't resend their responses and events, so acknowledging them would only double
+    /// traffic of router socket.
+    fn acknowledge_client(&mut self, identity: &Message, uuid: Uuid, journaled: bool) {
+        if !self.service_registry.contains(identity) {
+            self.acknowledge(identity, uuid, journaled);
+        }
+    }
+
+    /// Flushes journal and acknowledges messages of the batch which waited for it. If
+    /// flush fails, messages are still published, but they are not acknowledged as
+    /// journaled.
+    fn acknowledge_synced(&mut self) {
+        let Some(journal) = self.journal else {
+            return;
+        };
+
+        let journaled = match journal.sync() {
+            Ok(()) => true,
+            Err(error) => {
+                log::error!("failed to flush journal because of: {}", error);
+                false
+            }
+        };
+
+        for (identity, uuid) in mem::take(&mut self.unsynced_acknowledgements) {
+            self.acknowledge(&identity, uuid, journaled);
+        }
+    }
+
+    fn acknowledge(&mut self, identity: &Message, uuid: Uuid, journaled: bool) {
+        if !self.acknowledge_messages {
+            return;
+        }
+
+        self.metrics.acknowledged.inc();
+        let result = reply(
+            self.router_socket,
+            identity,
+            uuid,
+            BusAcknowledgement { journaled },
+        );
+        log_reply_error("acknowledgement", result);
+    }
+
+    fn reject(&mut self, identity: &Message, uuid: Uuid, reason: String) {
+        self.metrics.rejected.inc();
+        let result = reply(self.router_socket, identity, uuid, BusRejection { reason });
+        log_reply_error("rejection", result);
+    }
+
+    //-------------------------------------------------------------------------------------
+    // Publishing
+    //-------------------------------------------------------------------------------------
+
+    /// Moves messages left by previous run into queue while there is free space in it, so
+    /// they are published before any new message is received.
+    fn replay_messages(&mut self) {
+        while !self.received_messages.is_full() {
+            let Some((journal_id, message_bytes)) = self.replayed_messages.next() else {
+                return;
+            };
+
+            let message = Message::from(message_bytes);
+            let (message_uuid, message_kind) = match MessageView::new(&message) {
+                Ok(message_view) => (message_view.uuid(), message_view.kind()),
+                Err(error) => {
+                    log::error!("failed to decode journal message because of: {}", error);
+                    self.metrics.count_decode_error(&error);
+                    mark_done(self.journal, Some(journal_id));
+                    continue;
+                }
+            };
+
+            self.metrics.replayed.inc();
+
+            // Queue has free space, so nothing overflows.
+            let _ = self.received_messages.push(ReceivedMessage {
+                journal_id: Some(journal_id),
+                uuid: message_uuid,
+                kind: message_kind,
+                message,
+            });
+        }
+    }
+
+    /// Publishes at most `MAX_BATCH_SIZE` queued messages, so publishing does not hold back
+    /// receiving and timers.
+    fn publish_received(&mut self) {
+        for _ in 0..MAX_BATCH_SIZE {
+            let Some(received_message) = self.received_messages.pop() else {
+                return;
+            };
+            self.publish_or_hold(received_message);
+        }
+    }
+
+    fn publish_or_hold(&mut self, received_message: ReceivedMessage) {
+        // Subscription may have arrived since sockets were polled, so it is checked again
+        // before giving up on message.
+        if !self.subscriptions.is_subscribed(received_message.kind) {
+            self.receive_subscriptions();
+        }
+
+        if self.subscriptions.is_subscribed(received_message.kind) {
+            self.publish(received_message);
+        } else {
+            self.hold_unsubscribed(received_message);
+        }
+    }
+
+    /// Publishes again messages which publisher sockets failed to send. Their subscribers
+    /// may be gone since then.
+    fn retry_errored(&mut self) {
+        for (failed_index, errored_message) in mem::take(&mut self.errored_messages) {
+            self.publisher_loads[failed_index].queued_count -= 1;
+            self.publish_or_hold(errored_message);
+        }
+    }
+
+    /// Sends message through one of publishers which have subscribers of its kind, so it
+    /// is not lost on publisher whose subscription has not arrived yet.
+    fn publish(&mut self, received_message: ReceivedMessage) {
+        let subscribed_publishers = self
+            .subscriptions
+            .subscribed_publishers(received_message.kind);
+        if subscribed_publishers.is_empty() {
+            return self.hold_unsubscribed(received_message);
+        }
+
+        let index_of_publisher_that_will_be_used = self.selector.select(
+            received_message.uuid,
+            &self.publisher_loads,
+            &subscribed_publishers,
+        );
+
+        match send_published_message(
+            &self.publishers[index_of_publisher_that_will_be_used],
+            received_message.kind,
+            &received_message.message,
+        ) {
+            Ok(()) => {
+                log::trace!("> {:?}", &*received_message.message);
+                mark_done(self.journal, received_message.journal_id);
+                self.metrics.published.inc();
+                self.publisher_sent_counters[index_of_publisher_that_will_be_used].inc();
+                self.publisher_loads[index_of_publisher_that_will_be_used]
+                    .record_sent(Instant::now());
+
+                let published_count = self.metrics.published.get();
+                if published_count.is_multiple_of(self.group_size) {
+                    log::debug!(
+                        "{:?} | total processed {} messages",
+                        SystemTime::now(),
+                        published_count
+                    );
+                }
+            }
+            // Retrying forever would block shutdown, so message is lost instead. It is
+            // kept in journal and will be replayed on the next run.
+            Err(error) if self.is_stopping => {
+                log::error!("dropped message on shutdown because of: {}", error);
+                self.metrics.dropped.inc();
+            }
+            Err(error) if self.errored_messages.len() >= self.buffer_capacity => {
+                log::error!("dropped message because retry buffer is full: {}", error);
+                mark_done(self.journal, received_message.journal_id);
+                self.metrics.dropped.inc();
+            }
+            Err(error) => {
+                log::error!("failed to send message because of: {}", error);
+                self.publisher_loads[index_of_publisher_that_will_be_used].queued_count += 1;
+                self.errored_messages
+                    .push_back((index_of_publisher_that_will_be_used, received_message));
+            }
+        }
+
+        self.metrics
+            .retry_buffer_depth
+            .set(u64::try_from(self.errored_messages.len()).unwrap_or(u64::MAX));
+    }
+
+    /// Parks message of kind which nobody is subscribed to, if parking is enabled and
+    /// there is space for it, otherwise skips it.
+    fn hold_unsubscribed(&mut self, received_message: ReceivedMessage) {
+        if !self.park_unsubscribed_messages {
+            log::trace!(
+                "skipped {:?} message because nobody is subscribed to it",
+                received_message.kind
+            );
+            mark_done(self.journal, received_message.journal_id);
+            self.metrics.skipped.inc();
+            return;
+        }
+
+        if self.parked_count >= self.buffer_capacity {
+            log::error!("dropped unsubscribed message because parking is full");
+            mark_done(self.journal, received_message.journal_id);
+            self.metrics.dropped.inc();
+            return;
+        }
+
+        self.parked_messages
+            .entry(received_message.kind)
+            .or_default()
+            .push_back(received_message);
+        self.parked_count += 1;
+        self.update_parked_messages_gauge();
+    }
+
+    /// Applies subscription events which publishers received. Messages parked until their
+    /// kind got subscriber are published right away.
+    fn receive_subscriptions(&mut self) {
+        let publishers = self.publishers;
+
+        let mut is_changed = false;
+        for (index, publisher) in publishers.iter().enumerate() {
+            loop {
+                match publisher.recv_bytes(zmq::DONTWAIT) {
+                    Ok(event) => {
+                        log::trace!("publisher {} received subscription {:?}", index, event);
+                        is_changed |= self.subscriptions.apply(index, &event);
+                    }
+                    Err(zmq::Error::EAGAIN) => break,
+                    Err(error) => {
+                        log::error!("failed to receive subscription because of: {}", error);
+                        break;
+                    }
+                }
+            }
+        }
+
+        if !is_changed {
+            return;
+        }
+
+        let subscribed_kinds: Vec<ZeromqMessageKind> = self
+            .parked_messages
+            .keys()
+            .copied()
+            .filter(|kind| self.subscriptions.is_subscribed(*kind))
+            .collect();
+        for kind in subscribed_kinds {
+            if let Some(parked_messages) = self.parked_messages.remove(&kind) {
+                log::debug!(
+                    "publishing {} parked {:?} messages",
+                    parked_messages.len(),
+                    kind
+                );
+                self.parked_count -= parked_messages.len();
+                self.update_parked_messages_gauge();
+                for parked_message in parked_messages {
+                    self.publish(parked_message);
+                }
+            }
+        }
+    }
+
+    fn update_parked_messages_gauge(&self) {
+        self.metrics
+            .parked_messages
+            .set(u64::try_from(self.parked_count).unwrap_or(u64::MAX));
+    }
+}
+
+/// Answers sender of message with given uuid directly through router socket without
+/// blocking BUS loop.
+fn reply<'de, P: ZeromqMessageTrait<'de>>(
+    router_socket: &Socket,
+    identity: &Message,
+    uuid: Uuid,
+    payload: P,
+) -> Result<(), BusError> {
+    let reply_bytes = encode_message_with_format(uuid, payload, PAYLOAD_FORMAT)?;
+
+    router_socket
+        .send(&**identity, zmq::SNDMORE | zmq::DONTWAIT)
+        .and_then(|()| router_socket.send(reply_bytes, zmq::DONTWAIT))
+        .map_err(BusError::CantSendReply)
+}
+
+/// Sender which is gone or does not read its socket fast enough is not an error of BUS,
+/// it resends message if it still needs reply.
+fn log_reply_error(reply_name: &str, result: Result<(), BusError>) {
+    match result {
+        Ok(()) => {}
+        Err(BusError::CantSendReply(zmq::Error::EAGAIN | zmq::Error::EHOSTUNREACH)) => {
+            log::trace!("dropped {} because sender is busy or gone", reply_name);
+        }
+        Err(error) => {
+            log::error!("failed to send {} because of: {}", reply_name, error);
+        }
+    }
+}
+
+/// Sends message to instance of service without blocking. Router socket is mandatory, so
+/// gone instance is reported with `EHOSTUNREACH` instead of silently losing message.
+fn send_directed_message(
+    router_socket: &Socket,
+    instance: &[u8],
+    message: &Message,
+) -> zmq::Result<()> {
+    router_socket.send(instance, zmq::SNDMORE | zmq::DONTWAIT)?;
+    router_socket.send(&**message, zmq::DONTWAIT)
+}
+
+fn compact(journal: Option<&Journal>) {
+    if let Some(journal) = journal {
+        if let Err(error) = journal.compact() {
+            log::error!("failed to compact journal because of: {}", error);
+        }
+    }
+}
+
+fn mark_done(journal: Option<&Journal>, journal_id: Option<JournalId>) {
+    if let (Some(journal), Some(journal_id)) = (journal, journal_id) {
+        if let Err(error) = journal.mark_done(journal_id) {
+            log::error!(
+                "failed to mark message done in journal because of: {}",
+                error
+            );
+        }
+    }
+}
+
+fn bind(socket: &Socket, endpoint: &Endpoint) -> Result<(), BusError> {
+    if let Endpoint::Ipc(path) = endpoint {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|error| {
+                BusError::CantCreateIpcDirectory(directory.to_path_buf(), error)
+            })?;
+        }
+    }
+
+    endpoint.bind(socket).map_err(|source| BusError::CantBind {
+        endpoint: endpoint.clone(),
+        source,
+    })
+}
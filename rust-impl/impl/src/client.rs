@@ -0,0 +1,817 @@
+use crate::config::BusConfig;
+use crate::curve::CurveClient;
+use crate::curve::CurveError;
+use crate::endpoint::join_endpoints;
+use crate::helpers::DeadLockSafeMutex;
+use crate::shutdown::set_linger;
+use crate::topic::recv_published_message;
+use crate::topic::subscribe_to_kinds;
+use crate::PAYLOAD_FORMAT;
+use crate::ZEROMQ_ZERO_FLAG;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Iterator;
+use std::marker::PhantomData;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+use uuid::Uuid;
+use zeromq_messages::codec::encode_directed_message_with_format;
+use zeromq_messages::codec::encode_message_with_format;
+use zeromq_messages::codec::MessageDecodeError;
+use zeromq_messages::codec::MessageEncodeError;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::BusAcknowledgement;
+use zeromq_messages::messages::BusRegistry;
+use zeromq_messages::messages::BusRegistryQuery;
+use zeromq_messages::messages::BusRejection;
+use zeromq_messages::messages::BusSubscriptions;
+use zeromq_messages::messages::BusSubscriptionsQuery;
+use zeromq_messages::template::ZeromqMessageTrait;
+use zeromq_messages::view::MessageView;
+use zmq::Context;
+use zmq::Message;
+use zmq::PollEvents;
+use zmq::Socket;
+use zmq::SocketType;
+
+pub const RESEND_REQUESTS_EVERY_DURATION: Duration = Duration::from_secs(5_u64);
+/// Requests acknowledged by BUS are resent rarely, only in case their response is lost.
+pub const RESEND_ACKNOWLEDGED_REQUESTS_EVERY_DURATION: Duration = Duration::from_secs(30_u64);
+const IO_THREAD_POLL_TIMEOUT: Duration = Duration::from_millis(100_u64);
+
+type AwaitingRequestsStorage = DeadLockSafeMutex<HashMap<Uuid, RequestData>>;
+type ListenersStorage = DeadLockSafeMutex<Vec<MessageListener>>;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum BusClientError {
+    #[error("Failed to encode request")]
+    CantEncodeRequest(#[from] MessageEncodeError),
+
+    #[error("Failed to decode response")]
+    CantDecodeResponse(#[from] MessageDecodeError),
+
+    #[error("ZeroMQ operation failed")]
+    Zmq(#[from] zmq::Error),
+
+    #[error("Failed to set up CURVE security")]
+    Curve(#[from] CurveError),
+
+    #[error("Client is not subscribed to messages of kind {0:?}")]
+    NotSubscribed(ZeromqMessageKind),
+
+    #[error("Response to request {uuid} not received within {timeout:?}")]
+    Timeout { uuid: Uuid, timeout: Duration },
+
+    #[error("Client input/output thread has stopped")]
+    Disconnected,
+
+    #[error("Request {uuid} rejected by BUS: {reason}")]
+    Rejected { uuid: Uuid, reason: String },
+}
+
+//-----------------------------------------------------------------------------------------
+// RequestData
+//-----------------------------------------------------------------------------------------
+
+/// Called with response and round-trip latency of request.
+type ResponseCallback = Box<dyn FnOnce(Message, Duration) + Send>;
+
+struct RequestData {
+    response_kind: ZeromqMessageKind,
+    message_bytes: Vec<u8>,
+    /// Latency is measured from the first attempt, so it includes resends.
+    first_send_time: Instant,
+    last_send_attempt_time: Instant,
+    acknowledged: bool,
+    response_callback: ResponseCallback,
+}
+
+impl RequestData {
+    fn new(
+        response_kind: ZeromqMessageKind,
+        message_bytes: Vec<u8>,
+        response_callback: ResponseCallback,
+    ) -> Self {
+        let now = Instant::now();
+
+        Self {
+            response_kind,
+            message_bytes,
+            first_send_time: now,
+            last_send_attempt_time: now,
+            acknowledged: false,
+            response_callback,
+        }
+    }
+
+    fn should_resend_request(&self) -> bool {
+        let resend_duration = if self.acknowledged {
+            RESEND_ACKNOWLEDGED_REQUESTS_EVERY_DURATION
+        } else {
+            RESEND_REQUESTS_EVERY_DURATION
+        };
+
+        Instant::now().duration_since(self.last_send_attempt_time) > resend_duration
+    }
+
+    /// Resent request may be lost by BUS again, so it waits for a new acknowledgement.
+    fn update_last_send_attempt_time(&mut self) {
+        self.last_send_attempt_time = Instant::now();
+        self.acknowledged = false;
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// MessageListener
+//-----------------------------------------------------------------------------------------
+
+/// Called on input/output thread with received message of listened kind which is not a
+/// response to awaiting request. Listener which returns `false` is forgotten.
+type ListenerCallback = Box<dyn FnMut(&Message) -> bool + Send>;
+
+struct MessageListener {
+    kinds: Vec<ZeromqMessageKind>,
+    callback: ListenerCallback,
+}
+
+//-----------------------------------------------------------------------------------------
+// PendingResponse
+//-----------------------------------------------------------------------------------------
+
+/// Handle of request which was sent to BUS but response to which was not taken yet.
+/// Dropping the handle forgets the request, so it will not be resent anymore.
+#[must_use]
+pub struct PendingResponse<Resp> {
+    uuid: Uuid,
+    response_receiver: Receiver<Message>,
+    awaiting_requests_storage: AwaitingRequestsStorage,
+    response_type: PhantomData<fn() -> Resp>,
+}
+
+impl<Resp: for<'de> ZeromqMessageTrait<'de>> PendingResponse<Resp> {
+    #[must_use]
+    pub fn uuid(&self) -> Uuid {
+        self.uuid
+    }
+
+    /// Blocks until response is received or timeout is elapsed.
+    pub fn wait(self, timeout: Duration) -> Result<Resp, BusClientError> {
+        match self.response_receiver.recv_timeout(timeout) {
+            Ok(message) => decode_response(&message),
+            Err(RecvTimeoutError::Timeout) => Err(BusClientError::Timeout {
+                uuid: self.uuid,
+                timeout,
+            }),
+            Err(RecvTimeoutError::Disconnected) => Err(BusClientError::Disconnected),
+        }
+    }
+}
+
+impl<Resp> Drop for PendingResponse<Resp> {
+    fn drop(&mut self) {
+        let uuid = self.uuid;
+        let _ = self
+            .awaiting_requests_storage
+            .lock(move |awaiting_requests_storage| awaiting_requests_storage.remove(&uuid));
+    }
+}
+
+impl<Resp> fmt::Debug for PendingResponse<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingResponse")
+            .field("uuid", &self.uuid)
+            .finish_non_exhaustive()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// BusClient
+//-----------------------------------------------------------------------------------------
+
+/// Client which sends requests to BUS and matches published responses to them by uuid.
+///
+/// Sockets connected to BUS are owned by input/output thread of the client. Requests are
+/// passed to that thread through inproc socket, so client can be shared between threads.
+pub struct BusClient {
+    commands_socket: DeadLockSafeMutex<Socket>,
+    awaiting_requests_storage: AwaitingRequestsStorage,
+    listeners_storage: ListenersStorage,
+    response_kinds: Vec<ZeromqMessageKind>,
+    io_thread_join_handle: Option<JoinHandle<()>>,
+}
+
+impl BusClient {
+    /// Connects to BUS and subscribes to responses of given kinds.
+    pub fn connect(
+        context: &Context,
+        config: &BusConfig,
+        response_kinds: &[ZeromqMessageKind],
+    ) -> Result<Self, BusClientError> {
+        let router_endpoint = config.router_connect_endpoint();
+        let publisher_endpoints = config.publisher_connect_endpoints();
+
+        let curve_client = CurveClient::from_config(config)?;
+
+        let sender = context.socket(SocketType::DEALER)?;
+        set_linger(&sender)?;
+        if let Some(curve_client) = &curve_client {
+            curve_client.apply(&sender)?;
+        }
+        router_endpoint.connect(&sender)?;
+
+        log::debug!(
+            "[CLIENT] client has connected to BUS router socket {}",
+            router_endpoint
+        );
+
+        let receiver = context.socket(SocketType::SUB)?;
+        if let Some(curve_client) = &curve_client {
+            curve_client.apply(&receiver)?;
+        }
+        for publisher_endpoint in &publisher_endpoints {
+            publisher_endpoint.connect(&receiver)?;
+        }
+        subscribe_to_kinds(&receiver, response_kinds)?;
+
+        log::debug!(
+            "[CLIENT] client has connected to all BUS publishers: {}",
+            join_endpoints(&publisher_endpoints)
+        );
+
+        let commands_endpoint = format!("inproc://bus-client-commands-{}", Uuid::new_v4());
+        let commands_receiver = context.socket(SocketType::PULL)?;
+        commands_receiver.bind(commands_endpoint.as_str())?;
+        let commands_socket = context.socket(SocketType::PUSH)?;
+        commands_socket.connect(commands_endpoint.as_str())?;
+
+        let awaiting_requests_storage = AwaitingRequestsStorage::default();
+        let awaiting_requests_storage_clone = awaiting_requests_storage.clone();
+        let listeners_storage = ListenersStorage::default();
+        let listeners_storage_clone = listeners_storage.clone();
+        let io_thread_join_handle = thread::Builder::new()
+            .name(String::from("bus-client-io"))
+            .spawn(move || {
+                run_io_loop(
+                    &sender,
+                    &receiver,
+                    &commands_receiver,
+                    &awaiting_requests_storage_clone,
+                    &listeners_storage_clone,
+                );
+            })
+            .expect("failed to spawn client input/output thread");
+
+        Ok(Self {
+            commands_socket: DeadLockSafeMutex::new(commands_socket),
+            awaiting_requests_storage,
+            listeners_storage,
+            response_kinds: response_kinds.to_vec(),
+            io_thread_join_handle: Some(io_thread_join_handle),
+        })
+    }
+
+    /// Sends request and returns handle which can be used to wait for response later.
+    pub fn start_request<Req, Resp>(
+        &self,
+        payload: Req,
+    ) -> Result<PendingResponse<Resp>, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        self.start_request_with_destination(None, payload)
+    }
+
+    /// Same as `start_request`, but request is delivered to one instance of `destination`
+    /// service instead of being published to all subscribers. Destination which is not a
+    /// service name addresses instance with that id.
+    pub fn start_request_to<Req, Resp>(
+        &self,
+        destination: &str,
+        payload: Req,
+    ) -> Result<PendingResponse<Resp>, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        self.start_request_with_destination(Some(destination), payload)
+    }
+
+    /// Sends request and calls given callback on input/output thread once response is
+    /// received, together with time elapsed since request was sent. Request is resent
+    /// periodically until that moment.
+    pub fn request_with_callback<Req, Resp, F>(
+        &self,
+        payload: Req,
+        callback: F,
+    ) -> Result<Uuid, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+        F: FnOnce(Result<Resp, BusClientError>, Duration) + Send + 'static,
+    {
+        self.send_request::<Req, Resp>(
+            None,
+            payload,
+            Box::new(move |message, latency| callback(decode_response(&message), latency)),
+        )
+    }
+
+    /// Same as `request_with_callback`, but request is delivered to one instance of
+    /// `destination` service.
+    pub fn request_with_callback_to<Req, Resp, F>(
+        &self,
+        destination: &str,
+        payload: Req,
+        callback: F,
+    ) -> Result<Uuid, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+        F: FnOnce(Result<Resp, BusClientError>, Duration) + Send + 'static,
+    {
+        self.send_request::<Req, Resp>(
+            Some(destination),
+            payload,
+            Box::new(move |message, latency| callback(decode_response(&message), latency)),
+        )
+    }
+
+    /// Sends request and blocks until response is received or timeout is elapsed. Request
+    /// is resent periodically while waiting.
+    pub fn request<Req, Resp>(
+        &self,
+        payload: Req,
+        timeout: Duration,
+    ) -> Result<Resp, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        self.start_request::<Req, Resp>(payload)?.wait(timeout)
+    }
+
+    /// Same as `request`, but request is delivered to one instance of `destination`
+    /// service.
+    pub fn request_to<Req, Resp>(
+        &self,
+        destination: &str,
+        payload: Req,
+        timeout: Duration,
+    ) -> Result<Resp, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        self.start_request_to::<Req, Resp>(destination, payload)?
+            .wait(timeout)
+    }
+
+    /// Asks BUS which service instances are alive, only those which handle given kind if it
+    /// is set, e.g. to check that somebody handles request before sending it.
+    pub fn query_registry(
+        &self,
+        kind: Option<ZeromqMessageKind>,
+        timeout: Duration,
+    ) -> Result<BusRegistry, BusClientError> {
+        self.request::<_, BusRegistry>(
+            BusRegistryQuery {
+                kind: kind.map(ZeromqMessageKind::number),
+            },
+            timeout,
+        )
+    }
+
+    /// Asks BUS to which kinds subscribers of every BUS publisher socket are subscribed.
+    pub fn query_subscriptions(
+        &self,
+        timeout: Duration,
+    ) -> Result<BusSubscriptions, BusClientError> {
+        self.request::<_, BusSubscriptions>(BusSubscriptionsQuery {}, timeout)
+    }
+
+    /// Sends message which needs no response, e.g. event for subscribers of its kind, and
+    /// returns its uuid. Unlike request, message is not resent if BUS is unreachable.
+    pub fn send<P>(&self, payload: P) -> Result<Uuid, BusClientError>
+    where
+        P: for<'de> ZeromqMessageTrait<'de>,
+    {
+        let uuid = Uuid::new_v4();
+        self.respond(uuid, payload)?;
+        Ok(uuid)
+    }
+
+    /// Sends response to request with given uuid, which client received as a message of
+    /// subscribed kind.
+    pub fn respond<P>(&self, uuid: Uuid, payload: P) -> Result<(), BusClientError>
+    where
+        P: for<'de> ZeromqMessageTrait<'de>,
+    {
+        let message_bytes = encode_message_with_format(uuid, payload, PAYLOAD_FORMAT)?;
+        self.send_to_io_thread(message_bytes)?;
+
+        log::trace!("[CLIENT] message {} sent", uuid);
+
+        Ok(())
+    }
+
+    /// Returns count of requests which are waiting for response.
+    #[must_use]
+    pub fn awaiting_requests_count(&self) -> usize {
+        self.awaiting_requests_storage
+            .lock(|awaiting_requests_storage| awaiting_requests_storage.len())
+    }
+
+    fn start_request_with_destination<Req, Resp>(
+        &self,
+        destination: Option<&str>,
+        payload: Req,
+    ) -> Result<PendingResponse<Resp>, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        let (response_sender, response_receiver) = mpsc::sync_channel(1);
+        let uuid = self.send_request::<Req, Resp>(
+            destination,
+            payload,
+            Box::new(move |message, _| {
+                // Receiver may be already dropped if nobody waits for response anymore.
+                let _ = response_sender.try_send(message);
+            }),
+        )?;
+
+        Ok(PendingResponse {
+            uuid,
+            response_receiver,
+            awaiting_requests_storage: self.awaiting_requests_storage.clone(),
+            response_type: PhantomData,
+        })
+    }
+
+    /// Calls given callback on input/output thread with every received message of given
+    /// kinds which is not a response to request of this client, until callback returns
+    /// `false`. Client has to be subscribed to these kinds.
+    pub fn listen<F>(
+        &self,
+        kinds: &[ZeromqMessageKind],
+        callback: F,
+    ) -> Result<(), BusClientError>
+    where
+        F: FnMut(&Message) -> bool + Send + 'static,
+    {
+        if let Some(kind) = kinds
+            .iter()
+            .find(|kind| !self.response_kinds.contains(kind))
+        {
+            return Err(BusClientError::NotSubscribed(*kind));
+        }
+
+        let listener = MessageListener {
+            kinds: kinds.to_vec(),
+            callback: Box::new(callback),
+        };
+        self.listeners_storage
+            .lock(move |listeners_storage| listeners_storage.push(listener));
+
+        Ok(())
+    }
+
+    /// Stops waiting for response to request, so it will not be resent anymore.
+    pub(crate) fn forget_request(&self, uuid: Uuid) {
+        let _ = self
+            .awaiting_requests_storage
+            .lock(move |awaiting_requests_storage| awaiting_requests_storage.remove(&uuid));
+    }
+
+    pub(crate) fn send_request<Req, Resp>(
+        &self,
+        destination: Option<&str>,
+        payload: Req,
+        response_callback: ResponseCallback,
+    ) -> Result<Uuid, BusClientError>
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        // Registry and subscriptions are sent by BUS itself directly to the client, not
+        // published.
+        let response_kind = <Resp as ZeromqMessageTrait<'_>>::kind();
+        if !self.response_kinds.contains(&response_kind)
+            && response_kind != ZeromqMessageKind::BusRegistry
+            && response_kind != ZeromqMessageKind::BusSubscriptions
+        {
+            return Err(BusClientError::NotSubscribed(response_kind));
+        }
+
+        let uuid = Uuid::new_v4();
+        let message_bytes = match destination {
+            Some(destination) => encode_directed_message_with_format(
+                uuid,
+                destination,
+                payload,
+                PAYLOAD_FORMAT,
+            )?,
+            None => encode_message_with_format(uuid, payload, PAYLOAD_FORMAT)?,
+        };
+
+        // Request is written to the storage before sending, so response can't outrun it.
+        let request_data =
+            RequestData::new(response_kind, message_bytes.clone(), response_callback);
+        let _ = self
+            .awaiting_requests_storage
+            .lock(move |awaiting_requests_storage| {
+                awaiting_requests_storage.insert(uuid, request_data)
+            });
+
+        if let Err(error) = self.send_to_io_thread(message_bytes) {
+            self.forget_request(uuid);
+            return Err(error.into());
+        }
+
+        log::trace!("[CLIENT] request {} sent", uuid);
+
+        Ok(uuid)
+    }
+
+    /// Passes message to input/output thread, which sends it to BUS.
+    pub(crate) fn send_to_io_thread(&self, message_bytes: Vec<u8>) -> zmq::Result<()> {
+        self.commands_socket
+            .lock(move |commands_socket| commands_socket.send(message_bytes, ZEROMQ_ZERO_FLAG))
+    }
+
+    /// Same as `send_to_io_thread`, but fails with `EAGAIN` instead of blocking while
+    /// input/output thread is behind.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn try_send_to_io_thread(&self, message_bytes: &[u8]) -> zmq::Result<()> {
+        let message = Message::from(message_bytes);
+        self.commands_socket
+            .lock(move |commands_socket| commands_socket.send(message, zmq::DONTWAIT))
+    }
+}
+
+impl Drop for BusClient {
+    fn drop(&mut self) {
+        // Empty frame is never a valid request, so it is used to stop input/output thread.
+        if let Err(error) = self
+            .commands_socket
+            .lock(|commands_socket| commands_socket.send(Vec::<u8>::new(), ZEROMQ_ZERO_FLAG))
+        {
+            log::error!("[CLIENT] failed to stop input/output thread: {}", error);
+            return;
+        }
+
+        if let Some(io_thread_join_handle) = self.io_thread_join_handle.take() {
+            if io_thread_join_handle.join().is_err() {
+                log::error!("[CLIENT] input/output thread panicked");
+            }
+        }
+    }
+}
+
+impl fmt::Debug for BusClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BusClient")
+            .field("response_kinds", &self.response_kinds)
+            .field("awaiting_requests_count", &self.awaiting_requests_count())
+            .finish_non_exhaustive()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Input/output loop
+//-----------------------------------------------------------------------------------------
+
+#[allow(clippy::cast_possible_truncation)]
+fn run_io_loop(
+    sender: &Socket,
+    receiver: &Socket,
+    commands_receiver: &Socket,
+    awaiting_requests_storage: &AwaitingRequestsStorage,
+    listeners_storage: &ListenersStorage,
+) {
+    let mut last_resend_check = Instant::now();
+    let mut message = Message::new();
+
+    'io_loop: loop {
+        let mut poll_items = [
+            commands_receiver.as_poll_item(PollEvents::POLLIN),
+            receiver.as_poll_item(PollEvents::POLLIN),
+            sender.as_poll_item(PollEvents::POLLIN),
+        ];
+
+        if let Err(error) =
+            zmq::poll(&mut poll_items, IO_THREAD_POLL_TIMEOUT.as_millis() as i64)
+        {
+            log::error!("[CLIENT] failed to poll sockets because of: {}", error);
+            continue 'io_loop;
+        }
+
+        if poll_items[0].is_readable() {
+            while commands_receiver.recv(&mut message, zmq::DONTWAIT).is_ok() {
+                if message.is_empty() {
+                    log::debug!("[CLIENT] input/output thread stopped");
+                    break 'io_loop;
+                }
+
+                // Sending blocks while BUS is unreachable, which would stop the whole
+                // thread. Request stays in the storage, so it is resent later.
+                match sender.send(&*message, zmq::DONTWAIT) {
+                    Ok(()) => {}
+                    Err(zmq::Error::EAGAIN) => {
+                        log::trace!("[CLIENT] BUS is unreachable, request will be resent");
+                    }
+                    Err(error) => {
+                        log::error!("[CLIENT] failed to send request because of: {}", error);
+                    }
+                }
+            }
+        }
+
+        if poll_items[1].is_readable() {
+            loop {
+                // Message is handed over to the waiting thread, so it can't be reused.
+                let mut response = Message::new();
+                if recv_published_message(receiver, &mut response, zmq::DONTWAIT).is_err() {
+                    break;
+                }
+                deliver_response(response, awaiting_requests_storage, listeners_storage);
+            }
+        }
+
+        // BUS answers directly with acknowledgement or rejection of request.
+        if poll_items[2].is_readable() {
+            loop {
+                let mut reply = Message::new();
+                if sender.recv(&mut reply, zmq::DONTWAIT).is_err() {
+                    break;
+                }
+
+                match MessageView::new(&reply) {
+                    Ok(message_view)
+                        if message_view.kind() == ZeromqMessageKind::BusAcknowledgement =>
+                    {
+                        acknowledge_request(message_view, awaiting_requests_storage);
+                    }
+                    _ => deliver_response(reply, awaiting_requests_storage, listeners_storage),
+                }
+            }
+        }
+
+        if Instant::now().duration_since(last_resend_check) > RESEND_REQUESTS_EVERY_DURATION {
+            last_resend_check = Instant::now();
+            resend_requests(sender, awaiting_requests_storage);
+        }
+    }
+}
+
+pub(crate) fn decode_response<Resp>(message: &Message) -> Result<Resp, BusClientError>
+where
+    Resp: for<'de> ZeromqMessageTrait<'de>,
+{
+    let message_view = MessageView::new(message)?;
+
+    if message_view.kind() == ZeromqMessageKind::BusRejection {
+        let rejection = message_view.decode_payload::<BusRejection>()?;
+        return Err(BusClientError::Rejected {
+            uuid: message_view.uuid(),
+            reason: rejection.reason,
+        });
+    }
+
+    Ok(message_view.decode_payload::<Resp>()?)
+}
+
+fn deliver_response(
+    message: Message,
+    awaiting_requests_storage: &AwaitingRequestsStorage,
+    listeners_storage: &ListenersStorage,
+) {
+    let message_view = match MessageView::new(&message) {
+        Ok(message_view) => message_view,
+        Err(error) => {
+            log::error!(
+                "[CLIENT] failed to decode message header because of: {}",
+                error
+            );
+            return;
+        }
+    };
+
+    let uuid = message_view.uuid();
+    let kind = message_view.kind();
+    let maybe_request_data =
+        awaiting_requests_storage.lock(move |awaiting_requests_storage| {
+            match awaiting_requests_storage.get(&uuid) {
+                Some(request_data)
+                    if request_data.response_kind == kind
+                        || kind == ZeromqMessageKind::BusRejection =>
+                {
+                    awaiting_requests_storage.remove(&uuid)
+                }
+                _ => None,
+            }
+        });
+
+    match maybe_request_data {
+        Some(request_data) => {
+            let latency = request_data.first_send_time.elapsed();
+            (request_data.response_callback)(message, latency);
+            log::trace!("[CLIENT] request {} completed", uuid);
+        }
+        None if notify_listeners(kind, message, listeners_storage) => {}
+        None => {
+            log::error!("[CLIENT] received message with unexpected uuid: {}", uuid);
+        }
+    }
+}
+
+/// Hands message over to listeners of its kind, returns `false` if nobody listens to it.
+fn notify_listeners(
+    kind: ZeromqMessageKind,
+    message: Message,
+    listeners_storage: &ListenersStorage,
+) -> bool {
+    listeners_storage.lock(move |listeners_storage| {
+        let mut is_delivered = false;
+        listeners_storage.retain_mut(|listener| {
+            if !listener.kinds.contains(&kind) {
+                return true;
+            }
+            let is_listening = (listener.callback)(&message);
+            is_delivered |= is_listening;
+            is_listening
+        });
+        is_delivered
+    })
+}
+
+fn acknowledge_request(
+    message_view: MessageView<'_>,
+    awaiting_requests_storage: &AwaitingRequestsStorage,
+) {
+    let uuid = message_view.uuid();
+    let journaled = match message_view.decode_payload::<BusAcknowledgement>() {
+        Ok(acknowledgement) => acknowledgement.journaled,
+        Err(error) => {
+            log::error!(
+                "[CLIENT] failed to decode acknowledgement because of: {}",
+                error
+            );
+            return;
+        }
+    };
+
+    let is_awaiting = awaiting_requests_storage.lock(move |awaiting_requests_storage| {
+        awaiting_requests_storage
+            .get_mut(&uuid)
+            .map(|request_data| request_data.acknowledged = true)
+            .is_some()
+    });
+
+    // Response may outrun acknowledgement, then request is already completed.
+    if is_awaiting {
+        log::trace!(
+            "[CLIENT] request {} acknowledged, journaled: {}",
+            uuid,
+            journaled
+        );
+    }
+}
+
+fn resend_requests(sender: &Socket, awaiting_requests_storage: &AwaitingRequestsStorage) {
+    let resend_requests = awaiting_requests_storage.lock(|awaiting_requests_storage| {
+        awaiting_requests_storage
+            .values_mut()
+            .filter(|request_data| request_data.should_resend_request())
+            .map(|request_data| {
+                request_data.update_last_send_attempt_time();
+                request_data.message_bytes.clone()
+            })
+            .collect::<Vec<Vec<u8>>>()
+    });
+
+    log::debug!("[CLIENT] resend {} requests", resend_requests.len());
+
+    for message_bytes in resend_requests {
+        match sender.send(message_bytes, zmq::DONTWAIT) {
+            Ok(()) => {}
+            Err(zmq::Error::EAGAIN) => {
+                log::debug!("[CLIENT] BUS is unreachable, requests will be resent later");
+                break;
+            }
+            Err(error) => {
+                log::error!("[CLIENT] failed to resend request because of: {}", error);
+            }
+        }
+    }
+}
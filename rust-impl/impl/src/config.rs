@@ -0,0 +1,971 @@
+use crate::endpoint::Endpoint;
+use crate::endpoint::Transport;
+use crate::journal::JournalSync;
+use crate::queue::OverflowPolicy;
+use crate::selector::PublisherSelection;
+use crate::LOG_LEVEL;
+use crate::REQUESTS_COUNT_INSIDE_ONE_GROUP;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use structopt::StructOpt;
+use zeromq_messages::kind::ZeromqMessageKind;
+
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_BIND_HOST: &str = "0.0.0.0";
+pub const DEFAULT_ROUTER_PORT: u16 = 56731;
+pub const DEFAULT_PUBLISHERS_BASE_PORT: u16 = 56738;
+pub const DEFAULT_PUBLISHERS_COUNT: u16 = 5;
+pub const DEFAULT_IPC_DIRECTORY: &str = "/tmp/zeromq-bus";
+pub const DEFAULT_QUEUE_CAPACITY: usize = 100_000;
+pub const DEFAULT_JOURNAL_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_JOURNAL_COMPACTION_PERCENT: u8 = 80;
+pub const DEFAULT_WORKER_CREDIT: usize = 100;
+pub const DEFAULT_HEARTBEAT_INTERVAL_MILLIS: u64 = 1000;
+pub const DEFAULT_HEARTBEAT_LIVENESS: u32 = 3;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum BusConfigError {
+    #[error("Failed to read config file {0}")]
+    CantReadFile(PathBuf, #[source] io::Error),
+
+    #[error("Failed to parse config file {0}")]
+    CantParseFile(PathBuf, #[source] toml::de::Error),
+
+    #[error("At least one publisher is required")]
+    NoPublishers,
+
+    #[error("Group size must be greater than zero")]
+    ZeroGroupSize,
+
+    #[error("Queue capacity must be greater than zero")]
+    ZeroQueueCapacity,
+
+    #[error("Journal segment size must be greater than zero")]
+    ZeroJournalSegmentSize,
+
+    #[error("Worker credit must be greater than zero")]
+    ZeroWorkerCredit,
+
+    #[error("Heartbeat interval must be greater than zero")]
+    ZeroHeartbeatInterval,
+
+    #[error("Heartbeat liveness must be greater than zero")]
+    ZeroHeartbeatLiveness,
+
+    #[error("Journal compaction percent {0} is out of 1..=100 range")]
+    InvalidJournalCompactionPercent(u8),
+
+    #[error("CURVE allowlist requires BUS server key file")]
+    CurveAllowlistWithoutServerKey,
+
+    #[error("CURVE requires both BUS public key file and client key file")]
+    IncompleteCurveClientKeys,
+
+    #[error("Publishers ports starting from {base_port} exceed maximum port for {count} publishers")]
+    PublishersPortsOverflow { base_port: u16, count: u16 },
+}
+
+//-----------------------------------------------------------------------------------------
+// BusConfigArgs
+//-----------------------------------------------------------------------------------------
+
+/// Command line flags and environment variables which override values of config file.
+/// Every binary flattens these into its own arguments, they tell how to connect to BUS.
+#[derive(Debug, Clone, Default, StructOpt)]
+#[structopt(about = "Communication BUS between microservices based on ZeroMQ")]
+pub struct BusConfigArgs {
+    /// Path to TOML config file.
+    #[structopt(long = "config", env = "BUS_CONFIG", parse(from_os_str))]
+    pub config_path: Option<PathBuf>,
+
+    /// Transport used to generate endpoints: tcp, ipc or inproc.
+    #[structopt(long, env = "BUS_TRANSPORT")]
+    pub transport: Option<Transport>,
+
+    /// Host which services use to connect to BUS.
+    #[structopt(long, env = "BUS_HOST")]
+    pub host: Option<String>,
+
+    /// Port of BUS router socket.
+    #[structopt(long, env = "BUS_ROUTER_PORT")]
+    pub router_port: Option<u16>,
+
+    /// Port of first BUS publisher socket, the rest of publishers use following ports.
+    #[structopt(long, env = "BUS_PUBLISHERS_BASE_PORT")]
+    pub publishers_base_port: Option<u16>,
+
+    /// Count of BUS publisher sockets.
+    #[structopt(long, env = "BUS_PUBLISHERS_COUNT")]
+    pub publishers_count: Option<u16>,
+
+    /// Directory in which ipc socket files are created.
+    #[structopt(long, env = "BUS_IPC_DIRECTORY", parse(from_os_str))]
+    pub ipc_directory: Option<PathBuf>,
+
+    /// Full router endpoint, overrides transport, host and port.
+    #[structopt(long, env = "BUS_ROUTER_ENDPOINT")]
+    pub router_endpoint: Option<Endpoint>,
+
+    /// Comma separated publisher endpoints, override transport, host, ports and count.
+    #[structopt(long, env = "BUS_PUBLISHER_ENDPOINTS", use_delimiter = true)]
+    pub publisher_endpoints: Option<Vec<Endpoint>>,
+
+    /// Log level used when `RUST_LOG` is not set.
+    #[structopt(long, env = "BUS_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Count of messages after which progress is logged.
+    #[structopt(long, env = "BUS_GROUP_SIZE")]
+    pub group_size: Option<usize>,
+
+    /// Comma separated kinds of requests which are dispatched to one worker instead of
+    /// being published, e.g. value-multiplication-request.
+    #[structopt(long, env = "BUS_QUEUE_KINDS", use_delimiter = true)]
+    pub queue_kinds: Option<Vec<ZeromqMessageKind>>,
+
+    /// Count of queued requests which service takes at once as a worker.
+    #[structopt(long, env = "BUS_WORKER_CREDIT")]
+    pub worker_credit: Option<usize>,
+
+    /// Milliseconds between heartbeats which services send to BUS.
+    #[structopt(long, env = "BUS_HEARTBEAT_INTERVAL_MILLIS")]
+    pub heartbeat_interval_millis: Option<u64>,
+
+    /// Public key file of BUS, which enables CURVE security of service sockets.
+    #[structopt(long, env = "BUS_CURVE_SERVER_PUBLIC_KEY_FILE", parse(from_os_str))]
+    pub curve_server_public_key_file: Option<PathBuf>,
+
+    /// Secret key file of service, required together with public key file of BUS.
+    #[structopt(long, env = "BUS_CURVE_CLIENT_KEY_FILE", parse(from_os_str))]
+    pub curve_client_key_file: Option<PathBuf>,
+}
+
+/// Command line flags and environment variables which override values of config file
+/// used by BUS binary only, services ignore them.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub struct BusServerArgs {
+    /// Host on which BUS binds its sockets.
+    #[structopt(long, env = "BUS_BIND_HOST")]
+    pub bind_host: Option<String>,
+
+    /// Count of received messages BUS holds before publishing them.
+    #[structopt(long, env = "BUS_QUEUE_CAPACITY")]
+    pub queue_capacity: Option<usize>,
+
+    /// What to do when queue is full: block, drop-oldest, drop-newest or reject.
+    #[structopt(long, env = "BUS_OVERFLOW_POLICY")]
+    pub overflow_policy: Option<OverflowPolicy>,
+
+    /// How publisher socket is chosen for every message: round-robin,
+    /// least-recently-used, least-queued or consistent-hash.
+    #[structopt(long, env = "BUS_PUBLISHER_SELECTION")]
+    pub publisher_selection: Option<PublisherSelection>,
+
+    /// Directory of write-ahead journal of received messages, journal is disabled if unset.
+    #[structopt(long, env = "BUS_JOURNAL_DIRECTORY", parse(from_os_str))]
+    pub journal_directory: Option<PathBuf>,
+
+    /// Size in bytes after which journal starts a new segment.
+    #[structopt(long, env = "BUS_JOURNAL_SEGMENT_SIZE")]
+    pub journal_segment_size: Option<u64>,
+
+    /// Percent of done messages after which the oldest journal segment is compacted.
+    #[structopt(long, env = "BUS_JOURNAL_COMPACTION_PERCENT")]
+    pub journal_compaction_percent: Option<u8>,
+
+    /// When journal flushes received messages to disk before acknowledging them: batch or
+    /// every-message.
+    #[structopt(long, env = "BUS_JOURNAL_SYNC")]
+    pub journal_sync: Option<JournalSync>,
+
+    /// Whether BUS answers clients with `BusAcknowledgement` for every accepted message,
+    /// messages of registered services are never acknowledged.
+    #[structopt(long, env = "BUS_ACKNOWLEDGE_MESSAGES")]
+    pub acknowledge_messages: Option<bool>,
+
+    /// Whether BUS keeps messages of kinds nobody is subscribed to until somebody
+    /// subscribes, instead of skipping them.
+    #[structopt(long, env = "BUS_PARK_UNSUBSCRIBED_MESSAGES")]
+    pub park_unsubscribed_messages: Option<bool>,
+
+    /// Count of missed heartbeats after which BUS expires service instance.
+    #[structopt(long, env = "BUS_HEARTBEAT_LIVENESS")]
+    pub heartbeat_liveness: Option<u32>,
+
+    /// Secret key file of BUS, which enables CURVE security of BUS sockets.
+    #[structopt(long, env = "BUS_CURVE_SERVER_KEY_FILE", parse(from_os_str))]
+    pub curve_server_key_file: Option<PathBuf>,
+
+    /// File with public keys of services allowed to connect to BUS, one per line. Every
+    /// service which knows public key of BUS is allowed if unset.
+    #[structopt(long, env = "BUS_CURVE_ALLOWLIST_FILE", parse(from_os_str))]
+    pub curve_allowlist_file: Option<PathBuf>,
+}
+
+//-----------------------------------------------------------------------------------------
+// BusConfig
+//-----------------------------------------------------------------------------------------
+
+/// Configuration shared by BUS and services. Values are taken from defaults, then from
+/// TOML config file, then from environment variables and finally from command line flags.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BusConfig {
+    pub transport: Transport,
+    pub host: String,
+    pub bind_host: String,
+    pub router_port: u16,
+    pub publishers_base_port: u16,
+    pub publishers_count: u16,
+    pub ipc_directory: PathBuf,
+    pub router_endpoint: Option<Endpoint>,
+    pub publisher_endpoints: Option<Vec<Endpoint>>,
+    pub log_level: String,
+    pub group_size: usize,
+    pub queue_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+    pub publisher_selection: PublisherSelection,
+    pub journal_directory: Option<PathBuf>,
+    pub journal_segment_size: u64,
+    pub journal_compaction_percent: u8,
+    pub journal_sync: JournalSync,
+    pub acknowledge_messages: bool,
+    pub park_unsubscribed_messages: bool,
+    pub queue_kinds: Vec<ZeromqMessageKind>,
+    pub worker_credit: usize,
+    pub heartbeat_interval_millis: u64,
+    pub heartbeat_liveness: u32,
+    pub bus_metrics_address: Option<SocketAddr>,
+    pub responder_metrics_address: Option<SocketAddr>,
+    pub sender_metrics_address: Option<SocketAddr>,
+    pub curve_server_key_file: Option<PathBuf>,
+    pub curve_allowlist_file: Option<PathBuf>,
+    pub curve_server_public_key_file: Option<PathBuf>,
+    pub curve_client_key_file: Option<PathBuf>,
+}
+
+impl Default for BusConfig {
+    fn default() -> Self {
+        Self {
+            transport: Transport::default(),
+            host: String::from(DEFAULT_HOST),
+            bind_host: String::from(DEFAULT_BIND_HOST),
+            router_port: DEFAULT_ROUTER_PORT,
+            publishers_base_port: DEFAULT_PUBLISHERS_BASE_PORT,
+            publishers_count: DEFAULT_PUBLISHERS_COUNT,
+            ipc_directory: PathBuf::from(DEFAULT_IPC_DIRECTORY),
+            router_endpoint: None,
+            publisher_endpoints: None,
+            log_level: String::from(LOG_LEVEL),
+            group_size: REQUESTS_COUNT_INSIDE_ONE_GROUP,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            overflow_policy: OverflowPolicy::default(),
+            publisher_selection: PublisherSelection::default(),
+            journal_directory: None,
+            journal_segment_size: DEFAULT_JOURNAL_SEGMENT_SIZE,
+            journal_compaction_percent: DEFAULT_JOURNAL_COMPACTION_PERCENT,
+            journal_sync: JournalSync::default(),
+            acknowledge_messages: true,
+            park_unsubscribed_messages: false,
+            queue_kinds: Vec::new(),
+            worker_credit: DEFAULT_WORKER_CREDIT,
+            heartbeat_interval_millis: DEFAULT_HEARTBEAT_INTERVAL_MILLIS,
+            heartbeat_liveness: DEFAULT_HEARTBEAT_LIVENESS,
+            bus_metrics_address: None,
+            responder_metrics_address: None,
+            sender_metrics_address: None,
+            curve_server_key_file: None,
+            curve_allowlist_file: None,
+            curve_server_public_key_file: None,
+            curve_client_key_file: None,
+        }
+    }
+}
+
+impl BusConfig {
+    /// Loads config of binary which does not have own arguments.
+    pub fn from_args() -> Result<Self, BusConfigError> {
+        Self::load(BusConfigArgs::from_args())
+    }
+
+    /// Loads config file pointed by arguments, if any, and applies arguments on top of it.
+    pub fn load(args: BusConfigArgs) -> Result<Self, BusConfigError> {
+        Self::load_file(&args)?.with_args(args).validated()
+    }
+
+    /// Same as `load`, but applies also arguments which only BUS binary accepts.
+    pub fn load_with_server_args(
+        args: BusConfigArgs,
+        server_args: BusServerArgs,
+    ) -> Result<Self, BusConfigError> {
+        Self::load_file(&args)?
+            .with_args(args)
+            .with_server_args(server_args)
+            .validated()
+    }
+
+    fn load_file(args: &BusConfigArgs) -> Result<Self, BusConfigError> {
+        match &args.config_path {
+            Some(config_path) => Self::from_file(config_path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, BusConfigError> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| BusConfigError::CantReadFile(path.to_path_buf(), error))?;
+
+        Self::from_toml(&content)
+            .map_err(|error| BusConfigError::CantParseFile(path.to_path_buf(), error))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    #[must_use]
+    pub fn with_args(mut self, args: BusConfigArgs) -> Self {
+        let BusConfigArgs {
+            config_path: _,
+            transport,
+            host,
+            router_port,
+            publishers_base_port,
+            publishers_count,
+            ipc_directory,
+            router_endpoint,
+            publisher_endpoints,
+            log_level,
+            group_size,
+            queue_kinds,
+            worker_credit,
+            heartbeat_interval_millis,
+            curve_server_public_key_file,
+            curve_client_key_file,
+        } = args;
+
+        self.transport = transport.unwrap_or(self.transport);
+        self.host = host.unwrap_or(self.host);
+        self.router_port = router_port.unwrap_or(self.router_port);
+        self.publishers_base_port = publishers_base_port.unwrap_or(self.publishers_base_port);
+        self.publishers_count = publishers_count.unwrap_or(self.publishers_count);
+        self.ipc_directory = ipc_directory.unwrap_or(self.ipc_directory);
+        self.router_endpoint = router_endpoint.or(self.router_endpoint);
+        self.publisher_endpoints = publisher_endpoints.or(self.publisher_endpoints);
+        self.log_level = log_level.unwrap_or(self.log_level);
+        self.group_size = group_size.unwrap_or(self.group_size);
+        self.queue_kinds = queue_kinds.unwrap_or(self.queue_kinds);
+        self.worker_credit = worker_credit.unwrap_or(self.worker_credit);
+        self.heartbeat_interval_millis =
+            heartbeat_interval_millis.unwrap_or(self.heartbeat_interval_millis);
+        self.curve_server_public_key_file =
+            curve_server_public_key_file.or(self.curve_server_public_key_file);
+        self.curve_client_key_file = curve_client_key_file.or(self.curve_client_key_file);
+
+        self
+    }
+
+    #[must_use]
+    pub fn with_server_args(mut self, args: BusServerArgs) -> Self {
+        let BusServerArgs {
+            bind_host,
+            queue_capacity,
+            overflow_policy,
+            publisher_selection,
+            journal_directory,
+            journal_segment_size,
+            journal_compaction_percent,
+            journal_sync,
+            acknowledge_messages,
+            park_unsubscribed_messages,
+            heartbeat_liveness,
+            curve_server_key_file,
+            curve_allowlist_file,
+        } = args;
+
+        self.bind_host = bind_host.unwrap_or(self.bind_host);
+        self.queue_capacity = queue_capacity.unwrap_or(self.queue_capacity);
+        self.overflow_policy = overflow_policy.unwrap_or(self.overflow_policy);
+        self.publisher_selection = publisher_selection.unwrap_or(self.publisher_selection);
+        self.journal_directory = journal_directory.or(self.journal_directory);
+        self.journal_segment_size = journal_segment_size.unwrap_or(self.journal_segment_size);
+        self.journal_compaction_percent =
+            journal_compaction_percent.unwrap_or(self.journal_compaction_percent);
+        self.journal_sync = journal_sync.unwrap_or(self.journal_sync);
+        self.acknowledge_messages = acknowledge_messages.unwrap_or(self.acknowledge_messages);
+        self.park_unsubscribed_messages =
+            park_unsubscribed_messages.unwrap_or(self.park_unsubscribed_messages);
+        self.heartbeat_liveness = heartbeat_liveness.unwrap_or(self.heartbeat_liveness);
+        self.curve_server_key_file = curve_server_key_file.or(self.curve_server_key_file);
+        self.curve_allowlist_file = curve_allowlist_file.or(self.curve_allowlist_file);
+
+        self
+    }
+
+    pub fn validated(self) -> Result<Self, BusConfigError> {
+        if self.group_size == 0 {
+            return Err(BusConfigError::ZeroGroupSize);
+        }
+
+        if self.queue_capacity == 0 {
+            return Err(BusConfigError::ZeroQueueCapacity);
+        }
+
+        if self.journal_segment_size == 0 {
+            return Err(BusConfigError::ZeroJournalSegmentSize);
+        }
+
+        if self.worker_credit == 0 {
+            return Err(BusConfigError::ZeroWorkerCredit);
+        }
+
+        if self.heartbeat_interval_millis == 0 {
+            return Err(BusConfigError::ZeroHeartbeatInterval);
+        }
+
+        if self.heartbeat_liveness == 0 {
+            return Err(BusConfigError::ZeroHeartbeatLiveness);
+        }
+
+        if !(1..=100).contains(&self.journal_compaction_percent) {
+            return Err(BusConfigError::InvalidJournalCompactionPercent(
+                self.journal_compaction_percent,
+            ));
+        }
+
+        if self.curve_allowlist_file.is_some() && self.curve_server_key_file.is_none() {
+            return Err(BusConfigError::CurveAllowlistWithoutServerKey);
+        }
+
+        if self.curve_server_public_key_file.is_some() != self.curve_client_key_file.is_some()
+        {
+            return Err(BusConfigError::IncompleteCurveClientKeys);
+        }
+
+        match &self.publisher_endpoints {
+            Some(publisher_endpoints) if publisher_endpoints.is_empty() => {
+                return Err(BusConfigError::NoPublishers);
+            }
+            Some(_) => {}
+            None if self.publishers_count == 0 => return Err(BusConfigError::NoPublishers),
+            None if self.transport != Transport::Tcp => {}
+            None => {
+                if self
+                    .publishers_base_port
+                    .checked_add(self.publishers_count - 1)
+                    .is_none()
+                {
+                    return Err(BusConfigError::PublishersPortsOverflow {
+                        base_port: self.publishers_base_port,
+                        count: self.publishers_count,
+                    });
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// How often services send heartbeats to BUS.
+    #[must_use]
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_millis)
+    }
+
+    /// How long BUS waits for any message from service instance before expiring it.
+    #[must_use]
+    pub fn heartbeat_timeout(&self) -> Duration {
+        self.heartbeat_interval() * self.heartbeat_liveness
+    }
+
+    /// Endpoint on which BUS binds router socket.
+    #[must_use]
+    pub fn router_bind_endpoint(&self) -> Endpoint {
+        self.router_endpoint(&self.bind_host)
+    }
+
+    /// Endpoint to which services connect their sender sockets.
+    #[must_use]
+    pub fn router_connect_endpoint(&self) -> Endpoint {
+        self.router_endpoint(&self.host)
+    }
+
+    /// Endpoints on which BUS binds publisher sockets.
+    #[must_use]
+    pub fn publisher_bind_endpoints(&self) -> Vec<Endpoint> {
+        self.publisher_endpoints(&self.bind_host)
+    }
+
+    /// Endpoints to which services connect their receiver sockets.
+    #[must_use]
+    pub fn publisher_connect_endpoints(&self) -> Vec<Endpoint> {
+        self.publisher_endpoints(&self.host)
+    }
+
+    fn router_endpoint(&self, host: &str) -> Endpoint {
+        if let Some(router_endpoint) = &self.router_endpoint {
+            return router_endpoint.clone();
+        }
+
+        match self.transport {
+            Transport::Tcp => Endpoint::tcp(host, self.router_port),
+            Transport::Ipc => Endpoint::Ipc(self.ipc_directory.join("router")),
+            Transport::Inproc => Endpoint::Inproc(String::from("bus-router")),
+        }
+    }
+
+    fn publisher_endpoints(&self, host: &str) -> Vec<Endpoint> {
+        if let Some(publisher_endpoints) = &self.publisher_endpoints {
+            return publisher_endpoints.clone();
+        }
+
+        (0..self.publishers_count)
+            .map(|index| match self.transport {
+                Transport::Tcp => Endpoint::tcp(host, self.publishers_base_port + index),
+                Transport::Ipc => {
+                    Endpoint::Ipc(self.ipc_directory.join(format!("publisher-{index}")))
+                }
+                Transport::Inproc => Endpoint::Inproc(format!("bus-publisher-{index}")),
+            })
+            .collect()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::config::BusConfig;
+    use crate::config::BusConfigArgs;
+    use crate::config::BusConfigError;
+    use crate::config::BusServerArgs;
+    use crate::endpoint::Endpoint;
+    use crate::endpoint::Transport;
+    use crate::journal::JournalSync;
+    use crate::queue::OverflowPolicy;
+    use crate::selector::PublisherSelection;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use structopt::StructOpt;
+    use zeromq_messages::kind::ZeromqMessageKind;
+
+    fn to_strings(endpoints: &[Endpoint]) -> Vec<String> {
+        endpoints.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn default_endpoints() {
+        let config = BusConfig::default();
+
+        assert_eq!(
+            "tcp://0.0.0.0:56731",
+            config.router_bind_endpoint().to_string()
+        );
+        assert_eq!(
+            "tcp://127.0.0.1:56731",
+            config.router_connect_endpoint().to_string()
+        );
+        assert_eq!(
+            vec![
+                "tcp://0.0.0.0:56738",
+                "tcp://0.0.0.0:56739",
+                "tcp://0.0.0.0:56740",
+                "tcp://0.0.0.0:56741",
+                "tcp://0.0.0.0:56742",
+            ],
+            to_strings(&config.publisher_bind_endpoints())
+        );
+        assert_eq!(5, config.publisher_connect_endpoints().len());
+    }
+
+    #[test]
+    fn from_toml() {
+        let config = BusConfig::from_toml(
+            r#"
+            host = "bus.local"
+            router_port = 1000
+            publishers_base_port = 2000
+            publishers_count = 2
+            group_size = 10
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "tcp://bus.local:1000",
+            config.router_connect_endpoint().to_string()
+        );
+        assert_eq!(
+            vec!["tcp://bus.local:2000", "tcp://bus.local:2001"],
+            to_strings(&config.publisher_connect_endpoints())
+        );
+        assert_eq!(10, config.group_size);
+        assert_eq!(BusConfig::default().log_level, config.log_level);
+    }
+
+    #[test]
+    fn transports() {
+        let config = BusConfig {
+            transport: Transport::Ipc,
+            ipc_directory: PathBuf::from("/run/bus"),
+            publishers_count: 2,
+            ..BusConfig::default()
+        };
+        assert_eq!(
+            "ipc:///run/bus/router",
+            config.router_bind_endpoint().to_string()
+        );
+        assert_eq!(
+            vec!["ipc:///run/bus/publisher-0", "ipc:///run/bus/publisher-1"],
+            to_strings(&config.publisher_connect_endpoints())
+        );
+
+        let config = BusConfig::from_toml("transport = \"inproc\"\npublishers_count = 1")
+            .unwrap()
+            .validated()
+            .unwrap();
+        assert_eq!(
+            config.router_bind_endpoint(),
+            config.router_connect_endpoint()
+        );
+        assert_eq!(
+            vec!["inproc://bus-publisher-0"],
+            to_strings(&config.publisher_bind_endpoints())
+        );
+    }
+
+    #[test]
+    fn example_file() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../bus.example.toml");
+
+        assert_eq!(BusConfig::default(), BusConfig::from_file(&path).unwrap());
+    }
+
+    #[test]
+    fn from_toml_unknown_field() {
+        assert!(BusConfig::from_toml("routr_port = 1000").is_err());
+    }
+
+    #[test]
+    fn args_override_file() {
+        let config = BusConfig::from_toml("router_port = 1000\nlog_level = \"info\"").unwrap();
+        let args = BusConfigArgs::from_iter_safe(vec![
+            "bin",
+            "--router-port",
+            "3000",
+            "--publisher-endpoints",
+            "ipc:///tmp/a,ipc:///tmp/b",
+        ])
+        .unwrap();
+        let config = config.with_args(args).validated().unwrap();
+
+        assert_eq!(
+            "tcp://127.0.0.1:3000",
+            config.router_connect_endpoint().to_string()
+        );
+        assert_eq!(
+            vec!["ipc:///tmp/a", "ipc:///tmp/b"],
+            to_strings(&config.publisher_bind_endpoints())
+        );
+        assert_eq!("info", config.log_level);
+    }
+
+    #[test]
+    fn validation() {
+        let config = BusConfig {
+            publishers_count: 0,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::NoPublishers)
+        ));
+
+        let config = BusConfig {
+            publisher_endpoints: Some(Vec::new()),
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::NoPublishers)
+        ));
+
+        let config = BusConfig {
+            publishers_base_port: u16::MAX,
+            publishers_count: 2,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::PublishersPortsOverflow { .. })
+        ));
+
+        let config = BusConfig {
+            group_size: 0,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::ZeroGroupSize)
+        ));
+
+        let config = BusConfig {
+            queue_capacity: 0,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::ZeroQueueCapacity)
+        ));
+
+        let config = BusConfig {
+            journal_compaction_percent: 101,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::InvalidJournalCompactionPercent(101))
+        ));
+
+        let config = BusConfig {
+            worker_credit: 0,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::ZeroWorkerCredit)
+        ));
+
+        let config = BusConfig {
+            heartbeat_interval_millis: 0,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::ZeroHeartbeatInterval)
+        ));
+
+        let config = BusConfig {
+            heartbeat_liveness: 0,
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::ZeroHeartbeatLiveness)
+        ));
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let config = BusConfig::from_toml("overflow_policy = \"drop-oldest\"").unwrap();
+        assert_eq!(OverflowPolicy::DropOldest, config.overflow_policy);
+
+        let args =
+            BusServerArgs::from_iter_safe(vec!["bin", "--overflow-policy", "reject"]).unwrap();
+        assert_eq!(
+            OverflowPolicy::Reject,
+            config.with_server_args(args).overflow_policy
+        );
+    }
+
+    #[test]
+    fn journal_sync() {
+        let config = BusConfig::from_toml("journal_sync = \"every-message\"").unwrap();
+        assert_eq!(JournalSync::EveryMessage, config.journal_sync);
+
+        let args =
+            BusServerArgs::from_iter_safe(vec!["bin", "--journal-sync", "batch"]).unwrap();
+        assert_eq!(
+            JournalSync::Batch,
+            config.with_server_args(args).journal_sync
+        );
+    }
+
+    #[test]
+    fn publisher_selection() {
+        assert_eq!(
+            PublisherSelection::RoundRobin,
+            BusConfig::default().publisher_selection
+        );
+
+        let config = BusConfig::from_toml("publisher_selection = \"least-queued\"").unwrap();
+        assert_eq!(PublisherSelection::LeastQueued, config.publisher_selection);
+
+        let args = BusServerArgs::from_iter_safe(vec![
+            "bin",
+            "--publisher-selection",
+            "consistent-hash",
+        ])
+        .unwrap();
+        assert_eq!(
+            PublisherSelection::ConsistentHash,
+            config.with_server_args(args).publisher_selection
+        );
+    }
+
+    #[test]
+    fn acknowledge_messages() {
+        let config = BusConfig::from_toml("acknowledge_messages = false").unwrap();
+        assert!(!config.acknowledge_messages);
+
+        let args =
+            BusServerArgs::from_iter_safe(vec!["bin", "--acknowledge-messages", "true"])
+                .unwrap();
+        assert!(config.with_server_args(args).acknowledge_messages);
+    }
+
+    #[test]
+    fn park_unsubscribed_messages() {
+        assert!(!BusConfig::default().park_unsubscribed_messages);
+
+        let config = BusConfig::from_toml("park_unsubscribed_messages = true").unwrap();
+        assert!(config.park_unsubscribed_messages);
+
+        let args = BusServerArgs::from_iter_safe(vec![
+            "bin",
+            "--park-unsubscribed-messages",
+            "false",
+        ])
+        .unwrap();
+        assert!(!config.with_server_args(args).park_unsubscribed_messages);
+    }
+
+    #[test]
+    fn queue_kinds() {
+        let config =
+            BusConfig::from_toml("queue_kinds = [\"value-multiplication-request\"]").unwrap();
+        assert_eq!(
+            vec![ZeromqMessageKind::ValueMultiplicationRequest],
+            config.queue_kinds
+        );
+        assert!(BusConfig::from_toml("queue_kinds = [\"unknown\"]").is_err());
+
+        let args = BusConfigArgs::from_iter_safe(vec![
+            "bin",
+            "--queue-kinds",
+            "value-multiplication-request,value-multiplication-response",
+        ])
+        .unwrap();
+        assert_eq!(
+            vec![
+                ZeromqMessageKind::ValueMultiplicationRequest,
+                ZeromqMessageKind::ValueMultiplicationResponse,
+            ],
+            config.with_args(args).queue_kinds
+        );
+    }
+
+    #[test]
+    fn server_args_only_for_bus() {
+        // Services do not accept options which only BUS uses.
+        assert!(
+            BusConfigArgs::from_iter_safe(vec!["bin", "--journal-directory", "journal"])
+                .is_err()
+        );
+
+        let args = BusConfigArgs::from_iter_safe(vec!["bin", "--host", "bus.local"]).unwrap();
+        let server_args =
+            BusServerArgs::from_iter_safe(vec!["bin", "--journal-directory", "journal"])
+                .unwrap();
+        let config = BusConfig::load_with_server_args(args, server_args).unwrap();
+        assert_eq!("bus.local", config.host);
+        assert_eq!(Some(PathBuf::from("journal")), config.journal_directory);
+    }
+
+    #[test]
+    fn heartbeat_timeout() {
+        let config = BusConfig::from_toml("heartbeat_interval_millis = 500").unwrap();
+        assert_eq!(Duration::from_millis(500_u64), config.heartbeat_interval());
+        assert_eq!(Duration::from_millis(1500_u64), config.heartbeat_timeout());
+    }
+
+    #[test]
+    fn metrics_addresses() {
+        assert_eq!(None, BusConfig::default().bus_metrics_address);
+
+        // Every binary has its own address, so all of them run on one host.
+        let config = BusConfig::from_toml(
+            r#"
+            bus_metrics_address = "127.0.0.1:9100"
+            responder_metrics_address = "127.0.0.1:9101"
+            sender_metrics_address = "127.0.0.1:9102"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some("127.0.0.1:9100".parse().unwrap()),
+            config.bus_metrics_address
+        );
+        assert_eq!(
+            Some("127.0.0.1:9101".parse().unwrap()),
+            config.responder_metrics_address
+        );
+        assert_eq!(
+            Some("127.0.0.1:9102".parse().unwrap()),
+            config.sender_metrics_address
+        );
+
+        assert!(BusConfig::from_toml("metrics_address = \"127.0.0.1:9100\"").is_err());
+    }
+
+    #[test]
+    fn curve_key_files() {
+        let config = BusConfig::from_toml(
+            r#"
+            curve_server_key_file = "/etc/bus/bus.key_secret"
+            curve_allowlist_file = "/etc/bus/allowlist"
+            "#,
+        )
+        .unwrap()
+        .validated()
+        .unwrap();
+        assert_eq!(
+            Some(PathBuf::from("/etc/bus/bus.key_secret")),
+            config.curve_server_key_file
+        );
+
+        let args = BusConfigArgs::from_iter_safe(vec![
+            "bin",
+            "--curve-server-public-key-file",
+            "/etc/bus/bus.key",
+            "--curve-client-key-file",
+            "/etc/bus/service.key_secret",
+        ])
+        .unwrap();
+        let config = config.with_args(args).validated().unwrap();
+        assert_eq!(
+            Some(PathBuf::from("/etc/bus/bus.key")),
+            config.curve_server_public_key_file
+        );
+
+        let config = BusConfig {
+            curve_allowlist_file: Some(PathBuf::from("/etc/bus/allowlist")),
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::CurveAllowlistWithoutServerKey)
+        ));
+
+        let config = BusConfig {
+            curve_server_public_key_file: Some(PathBuf::from("/etc/bus/bus.key")),
+            ..BusConfig::default()
+        };
+        assert!(matches!(
+            config.validated(),
+            Err(BusConfigError::IncompleteCurveClientKeys)
+        ));
+    }
+}
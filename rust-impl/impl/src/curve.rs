@@ -0,0 +1,655 @@
+use crate::config::BusConfig;
+use crate::shutdown::set_linger;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use zmq::Context;
+use zmq::Socket;
+use zmq::SocketType;
+
+pub const CURVE_KEY_LENGTH: usize = 32;
+/// Name of inproc endpoint on which libzmq looks for ZAP handler of its context.
+pub(crate) const ZAP_ENDPOINT_NAME: &str = "zeromq.zap.01";
+const Z85_KEY_LENGTH: usize = 40;
+const Z85_ALPHABET: &str =
+    "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+const ZAP_VERSION: &[u8] = b"1.0";
+const ZAP_MECHANISM: &[u8] = b"CURVE";
+const PUBLIC_KEY_FILE_EXTENSION: &str = "key";
+const SECRET_KEY_FILE_EXTENSION: &str = "key_secret";
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum CurveError {
+    #[error(
+        "CURVE security is not supported by linked libzmq, it has to be built with libsodium"
+    )]
+    Unsupported,
+
+    #[error("Failed to read key file {0}")]
+    CantReadKeyFile(PathBuf, #[source] io::Error),
+
+    #[error("Failed to parse key file {0}")]
+    CantParseKeyFile(PathBuf, #[source] toml::de::Error),
+
+    #[error("Invalid key in key file {0}")]
+    InvalidKey(PathBuf, #[source] CurveKeyParseError),
+
+    #[error("Key file {0} has no secret key")]
+    MissingSecretKey(PathBuf),
+
+    #[error("Failed to read allowlist file {0}")]
+    CantReadAllowlist(PathBuf, #[source] io::Error),
+
+    #[error("Invalid key at line {line} of allowlist file {path}")]
+    InvalidAllowlistKey {
+        path: PathBuf,
+        line: usize,
+        #[source]
+        source: CurveKeyParseError,
+    },
+
+    #[error("Failed to write key file {0}")]
+    CantWriteKeyFile(PathBuf, #[source] io::Error),
+
+    #[error("Failed to generate CURVE keypair")]
+    CantGenerateKeyPair(#[source] zmq::Error),
+}
+
+/// Key itself is never included into error, because it may be secret.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+#[error("Invalid CURVE key, expected 40 characters of Z85 encoding")]
+pub struct CurveKeyParseError;
+
+//-----------------------------------------------------------------------------------------
+// CurveKey
+//-----------------------------------------------------------------------------------------
+
+/// Public or secret CURVE key, which is written in Z85 encoding in files and config.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct CurveKey([u8; CURVE_KEY_LENGTH]);
+
+impl CurveKey {
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Loads public key from key file, which is either public or secret one.
+    pub fn load(path: &Path) -> Result<Self, CurveError> {
+        KeyFile::load(path)?.public_key(path)
+    }
+}
+
+impl From<[u8; CURVE_KEY_LENGTH]> for CurveKey {
+    fn from(bytes: [u8; CURVE_KEY_LENGTH]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl FromStr for CurveKey {
+    type Err = CurveKeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // libzmq silently accepts characters outside of Z85 alphabet, so they are checked
+        // before decoding.
+        if s.len() != Z85_KEY_LENGTH || !s.chars().all(|c| Z85_ALPHABET.contains(c)) {
+            return Err(CurveKeyParseError);
+        }
+
+        let bytes = zmq::z85_decode(s).map_err(|_| CurveKeyParseError)?;
+        <[u8; CURVE_KEY_LENGTH]>::try_from(bytes.as_slice())
+            .map(Self)
+            .map_err(|_| CurveKeyParseError)
+    }
+}
+
+impl fmt::Display for CurveKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = zmq::z85_encode(&self.0).map_err(|_| fmt::Error)?;
+        f.write_str(&encoded)
+    }
+}
+
+/// Keys are not printed, because they may be secret.
+impl fmt::Debug for CurveKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CurveKey").finish_non_exhaustive()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// KeyFile
+//-----------------------------------------------------------------------------------------
+
+/// TOML file with Z85 encoded keys. Public key file has only `public_key`, secret key
+/// file has both keys.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    public_key: String,
+    secret_key: Option<String>,
+}
+
+impl KeyFile {
+    fn load(path: &Path) -> Result<Self, CurveError> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| CurveError::CantReadKeyFile(path.to_path_buf(), error))?;
+
+        toml::from_str(&content)
+            .map_err(|error| CurveError::CantParseKeyFile(path.to_path_buf(), error))
+    }
+
+    fn public_key(&self, path: &Path) -> Result<CurveKey, CurveError> {
+        parse_key(&self.public_key, path)
+    }
+
+    fn secret_key(&self, path: &Path) -> Result<CurveKey, CurveError> {
+        match &self.secret_key {
+            Some(secret_key) => parse_key(secret_key, path),
+            None => Err(CurveError::MissingSecretKey(path.to_path_buf())),
+        }
+    }
+}
+
+fn parse_key(key: &str, path: &Path) -> Result<CurveKey, CurveError> {
+    key.parse()
+        .map_err(|error| CurveError::InvalidKey(path.to_path_buf(), error))
+}
+
+//-----------------------------------------------------------------------------------------
+// CurveKeyPair
+//-----------------------------------------------------------------------------------------
+
+/// Public and secret CURVE keys of BUS or of service.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct CurveKeyPair {
+    public_key: CurveKey,
+    secret_key: CurveKey,
+}
+
+impl CurveKeyPair {
+    /// Generates new keypair, which requires libzmq with CURVE support.
+    pub fn generate() -> Result<Self, CurveError> {
+        ensure_supported()?;
+
+        let key_pair = zmq::CurveKeyPair::new().map_err(CurveError::CantGenerateKeyPair)?;
+
+        Ok(Self {
+            public_key: CurveKey(key_pair.public_key),
+            secret_key: CurveKey(key_pair.secret_key),
+        })
+    }
+
+    /// Loads keypair from secret key file.
+    pub fn load(path: &Path) -> Result<Self, CurveError> {
+        let key_file = KeyFile::load(path)?;
+
+        Ok(Self {
+            public_key: key_file.public_key(path)?,
+            secret_key: key_file.secret_key(path)?,
+        })
+    }
+
+    /// Writes `<name>.key` file with public key, which is given to the other side, and
+    /// `<name>.key_secret` file with both keys, which only owner of keypair may read.
+    /// Existing files are not overwritten. Returns paths of public and secret key files.
+    pub fn write(
+        &self,
+        directory: &Path,
+        name: &str,
+    ) -> Result<(PathBuf, PathBuf), CurveError> {
+        let public_key_path = directory.join(format!("{name}.{PUBLIC_KEY_FILE_EXTENSION}"));
+        let secret_key_path = directory.join(format!("{name}.{SECRET_KEY_FILE_EXTENSION}"));
+
+        write_key_file(
+            &public_key_path,
+            &format!(
+                "# ZeroMQ CURVE public key of {name}.\npublic_key = \"{}\"\n",
+                self.public_key
+            ),
+            false,
+        )?;
+        write_key_file(
+            &secret_key_path,
+            &format!(
+                "# ZeroMQ CURVE keypair of {name}, keep this file private.\n\
+                 public_key = \"{}\"\nsecret_key = \"{}\"\n",
+                self.public_key, self.secret_key
+            ),
+            true,
+        )?;
+
+        Ok((public_key_path, secret_key_path))
+    }
+
+    #[must_use]
+    pub fn public_key(&self) -> CurveKey {
+        self.public_key
+    }
+}
+
+impl fmt::Debug for CurveKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CurveKeyPair")
+            .field("public_key", &self.public_key.to_string())
+            .finish_non_exhaustive()
+    }
+}
+
+fn write_key_file(path: &Path, content: &str, is_secret: bool) -> Result<(), CurveError> {
+    let mut options = OpenOptions::new();
+    let _ = options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    if is_secret {
+        use std::os::unix::fs::OpenOptionsExt;
+        let _ = options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = is_secret;
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|error| CurveError::CantWriteKeyFile(path.to_path_buf(), error))
+}
+
+fn ensure_supported() -> Result<(), CurveError> {
+    if zmq::has("curve") == Some(true) {
+        Ok(())
+    } else {
+        Err(CurveError::Unsupported)
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// CurveAllowlist
+//-----------------------------------------------------------------------------------------
+
+/// Public keys of clients which are allowed to connect to BUS. File has one Z85 encoded
+/// key per line, empty lines and lines starting with `#` are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct CurveAllowlist {
+    keys: HashSet<CurveKey>,
+}
+
+impl CurveAllowlist {
+    pub fn load(path: &Path) -> Result<Self, CurveError> {
+        let content = fs::read_to_string(path)
+            .map_err(|error| CurveError::CantReadAllowlist(path.to_path_buf(), error))?;
+
+        Self::parse(&content, path)
+    }
+
+    fn parse(content: &str, path: &Path) -> Result<Self, CurveError> {
+        let mut keys = HashSet::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let key = line
+                .parse()
+                .map_err(|source| CurveError::InvalidAllowlistKey {
+                    path: path.to_path_buf(),
+                    line: index + 1,
+                    source,
+                })?;
+            let _ = keys.insert(key);
+        }
+
+        Ok(Self { keys })
+    }
+
+    #[must_use]
+    pub fn contains(&self, key: &[u8]) -> bool {
+        <[u8; CURVE_KEY_LENGTH]>::try_from(key)
+            .is_ok_and(|key| self.keys.contains(&CurveKey(key)))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// CurveServer
+//-----------------------------------------------------------------------------------------
+
+/// CURVE security of BUS sockets, which is enabled by server key file in config.
+#[derive(Debug)]
+pub(crate) struct CurveServer {
+    key_pair: CurveKeyPair,
+    allowlist: Option<CurveAllowlist>,
+}
+
+impl CurveServer {
+    pub(crate) fn from_config(config: &BusConfig) -> Result<Option<Self>, CurveError> {
+        let Some(key_path) = &config.curve_server_key_file else {
+            return Ok(None);
+        };
+        ensure_supported()?;
+
+        let allowlist = match &config.curve_allowlist_file {
+            Some(allowlist_path) => Some(CurveAllowlist::load(allowlist_path)?),
+            None => None,
+        };
+
+        Ok(Some(Self {
+            key_pair: CurveKeyPair::load(key_path)?,
+            allowlist,
+        }))
+    }
+
+    /// Clients are checked against allowlist if there is one, otherwise every client which
+    /// knows public key of BUS is accepted.
+    pub(crate) fn zap_handler(
+        &self,
+        context: &Context,
+    ) -> Result<Option<ZapHandler>, zmq::Error> {
+        self.allowlist
+            .clone()
+            .map(|allowlist| ZapHandler::new(context, allowlist))
+            .transpose()
+    }
+
+    /// Has to be called before socket is bound.
+    pub(crate) fn apply(&self, socket: &Socket) -> Result<(), zmq::Error> {
+        socket.set_curve_server(true)?;
+        socket.set_curve_secretkey(self.key_pair.secret_key.as_bytes())
+    }
+
+    pub(crate) fn public_key(&self) -> CurveKey {
+        self.key_pair.public_key
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// CurveClient
+//-----------------------------------------------------------------------------------------
+
+/// CURVE security of sockets which services connect to BUS, it is enabled by public key
+/// file of BUS in config.
+#[derive(Debug)]
+pub(crate) struct CurveClient {
+    key_pair: CurveKeyPair,
+    server_key: CurveKey,
+}
+
+impl CurveClient {
+    pub(crate) fn from_config(config: &BusConfig) -> Result<Option<Self>, CurveError> {
+        // Incomplete keys are rejected by config validation.
+        let (Some(server_key_path), Some(key_path)) = (
+            &config.curve_server_public_key_file,
+            &config.curve_client_key_file,
+        ) else {
+            return Ok(None);
+        };
+        ensure_supported()?;
+
+        Ok(Some(Self {
+            key_pair: CurveKeyPair::load(key_path)?,
+            server_key: CurveKey::load(server_key_path)?,
+        }))
+    }
+
+    /// Has to be called before socket is connected.
+    pub(crate) fn apply(&self, socket: &Socket) -> Result<(), zmq::Error> {
+        socket.set_curve_serverkey(self.server_key.as_bytes())?;
+        socket.set_curve_publickey(self.key_pair.public_key.as_bytes())?;
+        socket.set_curve_secretkey(self.key_pair.secret_key.as_bytes())
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// ZapHandler
+//-----------------------------------------------------------------------------------------
+
+/// Handler of authentication protocol (ZAP, RFC 27), which libzmq asks whether
+/// client is allowed to finish CURVE handshake with BUS socket. It has to be bound on
+/// `ZAP_ENDPOINT_NAME` before BUS sockets are bound, and is polled by BUS loop.
+pub(crate) struct ZapHandler {
+    socket: Socket,
+    allowlist: CurveAllowlist,
+}
+
+impl ZapHandler {
+    fn new(context: &Context, allowlist: CurveAllowlist) -> Result<Self, zmq::Error> {
+        let socket = context.socket(SocketType::REP)?;
+        set_linger(&socket)?;
+
+        Ok(Self { socket, allowlist })
+    }
+
+    pub(crate) fn socket(&self) -> &Socket {
+        &self.socket
+    }
+
+    /// Answers all ZAP requests which already arrived.
+    pub(crate) fn handle_requests(&self) {
+        loop {
+            let request = match self.socket.recv_multipart(zmq::DONTWAIT) {
+                Ok(request) => request,
+                Err(zmq::Error::EAGAIN) => return,
+                Err(error) => {
+                    log::error!("failed to receive ZAP request because of: {}", error);
+                    return;
+                }
+            };
+
+            if let Err(error) = self
+                .socket
+                .send_multipart(zap_reply(&request, &self.allowlist), zmq::DONTWAIT)
+            {
+                log::error!("failed to send ZAP reply because of: {}", error);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ZapHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZapHandler")
+            .field("allowlist_length", &self.allowlist.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builds reply to ZAP request, which consists of version, request id, domain, address,
+/// routing id, mechanism and credentials frames. CURVE credentials are the public key of
+/// client, it becomes user id of accepted client.
+fn zap_reply(request: &[Vec<u8>], allowlist: &CurveAllowlist) -> Vec<Vec<u8>> {
+    let request_id = request.get(1).cloned().unwrap_or_default();
+    let reply = |status_code: &str, status_text: &str, user_id: Vec<u8>| {
+        vec![
+            ZAP_VERSION.to_vec(),
+            request_id.clone(),
+            status_code.as_bytes().to_vec(),
+            status_text.as_bytes().to_vec(),
+            user_id,
+            Vec::new(),
+        ]
+    };
+
+    let (address, mechanism, credentials) = match request {
+        [version, _, _, address, _, mechanism, credentials, ..]
+            if version.as_slice() == ZAP_VERSION =>
+        {
+            (address, mechanism, credentials)
+        }
+        _ => {
+            log::warn!("received malformed ZAP request");
+            return reply("500", "Malformed ZAP request", Vec::new());
+        }
+    };
+    let address = String::from_utf8_lossy(address);
+
+    if mechanism.as_slice() != ZAP_MECHANISM {
+        log::warn!("denied client {} which does not use CURVE", address);
+        return reply("400", "CURVE security is required", Vec::new());
+    }
+
+    if !allowlist.contains(credentials) {
+        log::warn!(
+            "denied client {} with public key which is not allowed",
+            address
+        );
+        return reply("400", "Public key is not allowed", Vec::new());
+    }
+
+    let user_id = zmq::z85_encode(credentials).unwrap_or_default();
+    log::debug!("accepted client {} with public key {}", address, user_id);
+    reply("200", "OK", user_id.into_bytes())
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::curve::zap_reply;
+    use crate::curve::CurveAllowlist;
+    use crate::curve::CurveError;
+    use crate::curve::CurveKey;
+    use crate::curve::CurveKeyPair;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use uuid::Uuid;
+
+    // Keys from test suite of libzmq.
+    const PUBLIC_KEY: &str = "Yne@$w-vo<fVvi]a<NY6T1ed:M$fCG*[IaLV{hID";
+    const SECRET_KEY: &str = "D:)Q[IlAW!ahhC2ac:9*A}h:p?([4%wOTJ%JR%cs";
+    const OTHER_PUBLIC_KEY: &str = "rq:rM>}U?@Lns47E1%kR.o@n%FcmmsL/@{H8]yf7";
+
+    fn key(z85: &str) -> CurveKey {
+        z85.parse().unwrap()
+    }
+
+    fn zap_request(mechanism: &str, credentials: &[u8]) -> Vec<Vec<u8>> {
+        vec![
+            b"1.0".to_vec(),
+            b"7".to_vec(),
+            Vec::new(),
+            b"127.0.0.1".to_vec(),
+            Vec::new(),
+            mechanism.as_bytes().to_vec(),
+            credentials.to_vec(),
+        ]
+    }
+
+    #[test]
+    fn key_encoding() {
+        let public_key = key(PUBLIC_KEY);
+        assert_eq!(32, public_key.as_bytes().len());
+        assert_eq!(PUBLIC_KEY, public_key.to_string());
+
+        assert!("short".parse::<CurveKey>().is_err());
+        // Space is not part of Z85 alphabet.
+        assert!("Yne@$w-vo<fVvi]a<NY6T1ed:M$fCG*[IaLV{hI "
+            .parse::<CurveKey>()
+            .is_err());
+    }
+
+    #[test]
+    fn key_pair_files() {
+        let directory = env::temp_dir().join(format!("bus-curve-{}", Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        let key_pair = CurveKeyPair {
+            public_key: key(PUBLIC_KEY),
+            secret_key: key(SECRET_KEY),
+        };
+
+        let (public_key_path, secret_key_path) =
+            key_pair.write(&directory, "service").unwrap();
+        assert_eq!(directory.join("service.key"), public_key_path);
+        assert_eq!(directory.join("service.key_secret"), secret_key_path);
+        assert!(!fs::read_to_string(&public_key_path)
+            .unwrap()
+            .contains(SECRET_KEY));
+
+        assert_eq!(key_pair, CurveKeyPair::load(&secret_key_path).unwrap());
+        assert_eq!(key(PUBLIC_KEY), CurveKey::load(&public_key_path).unwrap());
+        assert_eq!(key(PUBLIC_KEY), CurveKey::load(&secret_key_path).unwrap());
+        assert!(matches!(
+            CurveKeyPair::load(&public_key_path),
+            Err(CurveError::MissingSecretKey(_))
+        ));
+
+        // Existing keys are never overwritten.
+        assert!(matches!(
+            key_pair.write(&directory, "service"),
+            Err(CurveError::CantWriteKeyFile(..))
+        ));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn allowlist() {
+        let allowlist = CurveAllowlist::parse(
+            &format!("# Services allowed to connect.\n\n{PUBLIC_KEY}\n  {PUBLIC_KEY}  \n"),
+            Path::new("allowlist"),
+        )
+        .unwrap();
+        assert_eq!(1, allowlist.len());
+        assert!(allowlist.contains(key(PUBLIC_KEY).as_bytes()));
+        assert!(!allowlist.contains(key(OTHER_PUBLIC_KEY).as_bytes()));
+        assert!(!allowlist.contains(b"short"));
+
+        assert!(matches!(
+            CurveAllowlist::parse(&format!("{PUBLIC_KEY}\nwrong"), Path::new("allowlist")),
+            Err(CurveError::InvalidAllowlistKey { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn zap_replies() {
+        let allowlist = CurveAllowlist::parse(PUBLIC_KEY, Path::new("allowlist")).unwrap();
+
+        let reply = zap_reply(
+            &zap_request("CURVE", key(PUBLIC_KEY).as_bytes()),
+            &allowlist,
+        );
+        assert_eq!(6, reply.len());
+        assert_eq!(b"1.0".to_vec(), reply[0]);
+        assert_eq!(b"7".to_vec(), reply[1]);
+        assert_eq!(b"200".to_vec(), reply[2]);
+        assert_eq!(PUBLIC_KEY.as_bytes().to_vec(), reply[4]);
+
+        let reply = zap_reply(
+            &zap_request("CURVE", key(OTHER_PUBLIC_KEY).as_bytes()),
+            &allowlist,
+        );
+        assert_eq!(b"400".to_vec(), reply[2]);
+        assert!(reply[4].is_empty());
+
+        let reply = zap_reply(&zap_request("NULL", &[]), &allowlist);
+        assert_eq!(b"400".to_vec(), reply[2]);
+
+        let reply = zap_reply(&[b"2.0".to_vec(), b"8".to_vec()], &allowlist);
+        assert_eq!(b"8".to_vec(), reply[1]);
+        assert_eq!(b"500".to_vec(), reply[2]);
+    }
+}
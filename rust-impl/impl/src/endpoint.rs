@@ -0,0 +1,241 @@
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use zmq::Socket;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+pub enum EndpointParseError {
+    #[error("Endpoint {0} does not contain transport")]
+    MissingTransport(String),
+
+    #[error("Unsupported transport {0}, expected one of tcp, ipc, inproc")]
+    UnsupportedTransport(String),
+
+    #[error("Tcp endpoint {0} must have host:port form")]
+    InvalidTcpAddress(String),
+
+    #[error("Endpoint {0} has empty address")]
+    EmptyAddress(String),
+}
+
+//-----------------------------------------------------------------------------------------
+// Transport
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Ipc,
+    Inproc,
+}
+
+impl Transport {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Ipc => "ipc",
+            Self::Inproc => "inproc",
+        }
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Transport {
+    type Err = EndpointParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(Self::Tcp),
+            "ipc" => Ok(Self::Ipc),
+            "inproc" => Ok(Self::Inproc),
+            _ => Err(EndpointParseError::UnsupportedTransport(s.to_owned())),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Endpoint
+//-----------------------------------------------------------------------------------------
+
+/// Address of BUS socket together with transport used to reach it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Endpoint {
+    /// Host and port reachable over network.
+    Tcp { host: String, port: u16 },
+    /// Unix domain socket, for services running on the same machine.
+    Ipc(PathBuf),
+    /// In-process transport, works only between sockets of the same `zmq::Context`.
+    Inproc(String),
+}
+
+impl Endpoint {
+    #[must_use]
+    pub fn tcp(host: &str, port: u16) -> Self {
+        Self::Tcp {
+            host: host.to_owned(),
+            port,
+        }
+    }
+
+    #[must_use]
+    pub fn transport(&self) -> Transport {
+        match self {
+            Self::Tcp { .. } => Transport::Tcp,
+            Self::Ipc(_) => Transport::Ipc,
+            Self::Inproc(_) => Transport::Inproc,
+        }
+    }
+
+    pub fn bind(&self, socket: &Socket) -> zmq::Result<()> {
+        socket.bind(self.to_string().as_str())
+    }
+
+    pub fn connect(&self, socket: &Socket) -> zmq::Result<()> {
+        socket.connect(self.to_string().as_str())
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp { host, port } => write!(f, "tcp://{host}:{port}"),
+            Self::Ipc(path) => write!(f, "ipc://{}", path.display()),
+            Self::Inproc(name) => write!(f, "inproc://{name}"),
+        }
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = EndpointParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (transport, address) = s
+            .split_once("://")
+            .ok_or_else(|| EndpointParseError::MissingTransport(s.to_owned()))?;
+
+        if address.is_empty() {
+            return Err(EndpointParseError::EmptyAddress(s.to_owned()));
+        }
+
+        match transport.parse::<Transport>()? {
+            Transport::Tcp => {
+                let (host, port) = address
+                    .rsplit_once(':')
+                    .ok_or_else(|| EndpointParseError::InvalidTcpAddress(s.to_owned()))?;
+                let port = port
+                    .parse::<u16>()
+                    .map_err(|_| EndpointParseError::InvalidTcpAddress(s.to_owned()))?;
+
+                if host.is_empty() {
+                    return Err(EndpointParseError::InvalidTcpAddress(s.to_owned()));
+                }
+
+                Ok(Self::tcp(host, port))
+            }
+            Transport::Ipc => Ok(Self::Ipc(PathBuf::from(address))),
+            Transport::Inproc => Ok(Self::Inproc(address.to_owned())),
+        }
+    }
+}
+
+impl TryFrom<String> for Endpoint {
+    type Error = EndpointParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Formats endpoints for logging.
+pub(crate) fn join_endpoints(endpoints: &[Endpoint]) -> String {
+    endpoints
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::endpoint::Endpoint;
+    use crate::endpoint::EndpointParseError;
+    use crate::endpoint::Transport;
+    use std::path::PathBuf;
+
+    #[test]
+    fn round_trip() {
+        for (endpoint_str, endpoint) in [
+            ("tcp://127.0.0.1:56731", Endpoint::tcp("127.0.0.1", 56731)),
+            ("tcp://[::1]:80", Endpoint::tcp("[::1]", 80)),
+            (
+                "ipc:///tmp/bus/router",
+                Endpoint::Ipc(PathBuf::from("/tmp/bus/router")),
+            ),
+            (
+                "inproc://bus-router",
+                Endpoint::Inproc(String::from("bus-router")),
+            ),
+        ] {
+            assert_eq!(Ok(endpoint.clone()), endpoint_str.parse::<Endpoint>());
+            assert_eq!(endpoint_str, endpoint.to_string());
+        }
+    }
+
+    #[test]
+    fn transport() {
+        assert_eq!(Transport::Tcp, Endpoint::tcp("localhost", 1).transport());
+        assert_eq!(Ok(Transport::Ipc), "ipc".parse());
+        assert_eq!("inproc", Transport::Inproc.to_string());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            Err(EndpointParseError::MissingTransport(String::from(
+                "localhost:80"
+            ))),
+            "localhost:80".parse::<Endpoint>()
+        );
+        assert_eq!(
+            Err(EndpointParseError::UnsupportedTransport(String::from(
+                "udp"
+            ))),
+            "udp://localhost:80".parse::<Endpoint>()
+        );
+        assert_eq!(
+            Err(EndpointParseError::InvalidTcpAddress(String::from(
+                "tcp://localhost"
+            ))),
+            "tcp://localhost".parse::<Endpoint>()
+        );
+        assert_eq!(
+            Err(EndpointParseError::InvalidTcpAddress(String::from(
+                "tcp://:80"
+            ))),
+            "tcp://:80".parse::<Endpoint>()
+        );
+        assert_eq!(
+            Err(EndpointParseError::EmptyAddress(String::from("inproc://"))),
+            "inproc://".parse::<Endpoint>()
+        );
+    }
+}
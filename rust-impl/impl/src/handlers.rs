@@ -0,0 +1,16 @@
+use zeromq_messages::messages::ValueMultiplicationRequest;
+use zeromq_messages::messages::ValueMultiplicationResponse;
+
+/// Name under which responder service registers on BUS, senders direct requests to it.
+pub const VALUE_MULTIPLICATION_SERVICE_NAME: &str = "value-multiplication";
+
+/// Handler of responder service, shared with in-process mode of sender.
+#[allow(clippy::needless_pass_by_value)]
+#[must_use]
+pub fn value_multiplication(
+    request: ValueMultiplicationRequest,
+) -> ValueMultiplicationResponse {
+    ValueMultiplicationResponse {
+        result: request.value * request.multiplier,
+    }
+}
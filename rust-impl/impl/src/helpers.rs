@@ -6,14 +6,13 @@ use std::ops::DerefMut;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
-use std::time::Instant;
 use zmq::Socket;
 
 //-----------------------------------------------------------------------------------------
 // DeadLockSafeRwLock
 //-----------------------------------------------------------------------------------------
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct DeadLockSafeRwLock<T>(Arc<RwLock<T>>);
 
 impl<T> DeadLockSafeRwLock<T> {
@@ -37,6 +36,12 @@ impl<T> DeadLockSafeRwLock<T> {
     }
 }
 
+impl<T> Clone for DeadLockSafeRwLock<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
 impl<T: Default> Default for DeadLockSafeRwLock<T> {
     fn default() -> Self {
         Self::new(T::default())
@@ -53,7 +58,7 @@ impl<T> From<T> for DeadLockSafeRwLock<T> {
 // DeadLockSafeMutex
 //-----------------------------------------------------------------------------------------
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct DeadLockSafeMutex<T>(Arc<Mutex<T>>);
 
 impl<T> DeadLockSafeMutex<T> {
@@ -71,6 +76,12 @@ impl<T> DeadLockSafeMutex<T> {
     }
 }
 
+impl<T> Clone for DeadLockSafeMutex<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
 impl<T: Default> Default for DeadLockSafeMutex<T> {
     fn default() -> Self {
         Self::new(T::default())
@@ -90,24 +101,11 @@ impl<T> From<T> for DeadLockSafeMutex<T> {
 #[must_use]
 pub struct BusPublisherData {
     socket: Socket,
-    last_action_time: Instant,
 }
 
 impl BusPublisherData {
     pub fn new(socket: Socket) -> Self {
-        Self {
-            socket,
-            last_action_time: Instant::now(),
-        }
-    }
-
-    #[must_use]
-    pub fn get_last_action_time(&self) -> Instant {
-        self.last_action_time
-    }
-
-    pub fn update_last_action_time(&mut self) {
-        self.last_action_time = Instant::now();
+        Self { socket }
     }
 }
 
@@ -129,7 +127,6 @@ impl fmt::Debug for BusPublisherData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("BusPublisherData")
             .field("socket", &self.socket.get_socket_type())
-            .field("last_action_time", &self.last_action_time)
             .finish()
     }
 }
@@ -0,0 +1,697 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+const SEGMENT_EXTENSION: &str = "wal";
+const APPEND_RECORD_TYPE: u8 = 1;
+const DONE_RECORD_TYPE: u8 = 2;
+const RECORD_TYPE_LENGTH: usize = 1;
+const ID_LENGTH: usize = 8;
+const BYTES_LENGTH_LENGTH: usize = 4;
+const CHECKSUM_LENGTH: usize = 4;
+
+/// Identifier of message written to journal.
+pub(crate) type JournalId = u64;
+/// Messages which were written to journal but not done, with their bytes.
+pub(crate) type PendingMessages = Vec<(JournalId, Vec<u8>)>;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum JournalError {
+    #[error("Journal operation on {0} failed")]
+    Io(PathBuf, #[source] io::Error),
+
+    #[error("Message of {0} bytes is too large for journal")]
+    MessageTooLarge(usize),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("Unsupported journal sync {0}, expected one of batch, every-message")]
+pub struct JournalSyncParseError(String);
+
+//-----------------------------------------------------------------------------------------
+// JournalSync
+//-----------------------------------------------------------------------------------------
+
+/// When journal flushes written records to disk. Message is acknowledged as journaled
+/// only after its record is flushed.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JournalSync {
+    /// Flush once for all messages received in one iteration of BUS loop, their
+    /// acknowledgements are sent after that.
+    #[default]
+    Batch,
+    /// Flush after every message, so it is acknowledged right away.
+    EveryMessage,
+}
+
+impl JournalSync {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Batch => "batch",
+            Self::EveryMessage => "every-message",
+        }
+    }
+}
+
+impl fmt::Display for JournalSync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JournalSync {
+    type Err = JournalSyncParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "batch" => Ok(Self::Batch),
+            "every-message" => Ok(Self::EveryMessage),
+            _ => Err(JournalSyncParseError(s.to_owned())),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Records
+//-----------------------------------------------------------------------------------------
+
+enum Record<'a> {
+    Append { id: JournalId, bytes: &'a [u8] },
+    Done { id: JournalId },
+}
+
+impl Record<'_> {
+    fn encode(&self) -> Result<Vec<u8>, JournalError> {
+        let mut record_bytes = Vec::new();
+
+        match self {
+            Self::Append { id, bytes } => {
+                let bytes_length = u32::try_from(bytes.len())
+                    .map_err(|_| JournalError::MessageTooLarge(bytes.len()))?;
+                record_bytes.push(APPEND_RECORD_TYPE);
+                record_bytes.extend_from_slice(&id.to_be_bytes());
+                record_bytes.extend_from_slice(&bytes_length.to_be_bytes());
+                record_bytes.extend_from_slice(bytes);
+            }
+            Self::Done { id } => {
+                record_bytes.push(DONE_RECORD_TYPE);
+                record_bytes.extend_from_slice(&id.to_be_bytes());
+            }
+        }
+
+        let checksum = crc32fast::hash(&record_bytes);
+        record_bytes.extend_from_slice(&checksum.to_be_bytes());
+
+        Ok(record_bytes)
+    }
+
+    /// Decodes record from the start of given bytes, returns it together with its length.
+    /// `None` means that bytes are truncated or corrupted.
+    fn decode(bytes: &[u8]) -> Option<(Record<'_>, usize)> {
+        let id_end = RECORD_TYPE_LENGTH + ID_LENGTH;
+        let id =
+            JournalId::from_be_bytes(bytes.get(RECORD_TYPE_LENGTH..id_end)?.try_into().ok()?);
+
+        let (record, body_length) = match *bytes.first()? {
+            APPEND_RECORD_TYPE => {
+                let bytes_start = id_end + BYTES_LENGTH_LENGTH;
+                let bytes_length =
+                    u32::from_be_bytes(bytes.get(id_end..bytes_start)?.try_into().ok()?);
+                let bytes_end =
+                    bytes_start.checked_add(usize::try_from(bytes_length).ok()?)?;
+                let record = Record::Append {
+                    id,
+                    bytes: bytes.get(bytes_start..bytes_end)?,
+                };
+                (record, bytes_end)
+            }
+            DONE_RECORD_TYPE => (Record::Done { id }, id_end),
+            _ => return None,
+        };
+
+        let record_length = body_length + CHECKSUM_LENGTH;
+        let checksum =
+            u32::from_be_bytes(bytes.get(body_length..record_length)?.try_into().ok()?);
+        if checksum != crc32fast::hash(&bytes[..body_length]) {
+            return None;
+        }
+
+        Some((record, record_length))
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Journal
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug)]
+struct Segment {
+    index: u64,
+    path: PathBuf,
+    appended_count: usize,
+    done_count: usize,
+}
+
+impl Segment {
+    fn new(directory: &Path, index: u64) -> Self {
+        Self {
+            index,
+            path: directory.join(format!("{index:020}.{SEGMENT_EXTENSION}")),
+            appended_count: 0,
+            done_count: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct JournalState {
+    /// Segments from the oldest to the active one.
+    segments: VecDeque<Segment>,
+    active_file: File,
+    active_size: u64,
+    /// Whether records were written to active file after it was flushed the last time.
+    is_dirty: bool,
+    /// Segment index of every message which is not done yet.
+    live_messages: HashMap<JournalId, u64>,
+    next_id: JournalId,
+}
+
+impl JournalState {
+    fn mark_appended(&mut self) -> u64 {
+        let active_segment = self
+            .segments
+            .back_mut()
+            .expect("journal always has active segment");
+        active_segment.appended_count += 1;
+
+        active_segment.index
+    }
+
+    fn sync_active_file(&mut self, directory: &Path) -> Result<(), JournalError> {
+        if self.is_dirty {
+            self.active_file
+                .sync_data()
+                .map_err(|error| JournalError::Io(directory.to_path_buf(), error))?;
+            self.is_dirty = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Append-only on-disk journal of messages which BUS has received but not published yet.
+/// Journal is split into segments, the oldest segment is removed once enough of its
+/// messages are done, still live messages are moved to the active segment beforehand.
+#[derive(Debug)]
+pub(crate) struct Journal {
+    directory: PathBuf,
+    segment_size: u64,
+    compaction_percent: u8,
+    sync: JournalSync,
+    state: Mutex<JournalState>,
+}
+
+impl Journal {
+    /// Opens journal in given directory and returns messages which were not done before
+    /// previous BUS run stopped, in order they were written.
+    pub(crate) fn open(
+        directory: &Path,
+        segment_size: u64,
+        compaction_percent: u8,
+        sync: JournalSync,
+    ) -> Result<(Self, PendingMessages), JournalError> {
+        fs::create_dir_all(directory)
+            .map_err(|error| JournalError::Io(directory.to_path_buf(), error))?;
+
+        let mut segments = VecDeque::new();
+        let mut live_messages = HashMap::new();
+        let mut pending_messages = BTreeMap::new();
+        let mut next_id = 0;
+
+        for index in segment_indexes(directory)? {
+            let mut segment = Segment::new(directory, index);
+            let segment_bytes = fs::read(&segment.path)
+                .map_err(|error| JournalError::Io(segment.path.clone(), error))?;
+            let mut offset = 0;
+
+            while offset < segment_bytes.len() {
+                let Some((record, record_length)) = Record::decode(&segment_bytes[offset..])
+                else {
+                    log::warn!(
+                        "journal segment {} is corrupted after {} bytes, the rest is dropped",
+                        segment.path.display(),
+                        offset
+                    );
+                    truncate(&segment.path, offset)?;
+                    break;
+                };
+
+                match record {
+                    Record::Append { id, bytes } => {
+                        // Message copied by compaction which was interrupted may be met
+                        // twice, the older copy is considered as done.
+                        if let Some(previous_index) = live_messages.insert(id, index) {
+                            mark_segment_done(&mut segments, previous_index);
+                        }
+                        let _ = pending_messages.insert(id, bytes.to_vec());
+                        segment.appended_count += 1;
+                        next_id = next_id.max(id + 1);
+                    }
+                    Record::Done { id } => {
+                        if let Some(append_index) = live_messages.remove(&id) {
+                            let _ = pending_messages.remove(&id);
+                            if append_index == index {
+                                segment.done_count += 1;
+                            } else {
+                                mark_segment_done(&mut segments, append_index);
+                            }
+                        }
+                    }
+                }
+
+                offset += record_length;
+            }
+
+            segments.push_back(segment);
+        }
+
+        // New active segment is started on every run, so records are never appended after
+        // possibly truncated tail.
+        let active_segment = Segment::new(
+            directory,
+            segments.back().map_or(0, |segment| segment.index + 1),
+        );
+        let active_file = create_segment_file(directory, &active_segment.path)?;
+        segments.push_back(active_segment);
+
+        let journal = Self {
+            directory: directory.to_path_buf(),
+            segment_size,
+            compaction_percent,
+            sync,
+            state: Mutex::new(JournalState {
+                segments,
+                active_file,
+                active_size: 0,
+                is_dirty: false,
+                live_messages,
+                next_id,
+            }),
+        };
+
+        journal.compact()?;
+
+        Ok((journal, pending_messages.into_iter().collect()))
+    }
+
+    /// Writes message to journal before BUS takes responsibility for it. With `Batch` sync
+    /// the message is durable only after the following `sync`.
+    pub(crate) fn append(&self, message_bytes: &[u8]) -> Result<JournalId, JournalError> {
+        let mut state = self.lock();
+        let id = state.next_id;
+
+        self.write_record(
+            &mut state,
+            &Record::Append {
+                id,
+                bytes: message_bytes,
+            },
+        )?;
+        state.next_id += 1;
+        if self.sync == JournalSync::EveryMessage {
+            state.sync_active_file(&self.directory)?;
+        }
+
+        let active_index = state.mark_appended();
+        let _ = state.live_messages.insert(id, active_index);
+        self.rotate_if_full(&mut state)?;
+
+        Ok(id)
+    }
+
+    /// Whether every appended message is flushed right away, so it does not wait for
+    /// `sync`.
+    pub(crate) fn syncs_every_message(&self) -> bool {
+        self.sync == JournalSync::EveryMessage
+    }
+
+    /// Flushes records written since the previous flush to disk.
+    pub(crate) fn sync(&self) -> Result<(), JournalError> {
+        self.lock().sync_active_file(&self.directory)
+    }
+
+    /// Marks message as done, so it will not be replayed anymore. Done record is flushed
+    /// lazily, a message which was done right before crash is replayed once more. Segments
+    /// are compacted separately by `compact`.
+    pub(crate) fn mark_done(&self, id: JournalId) -> Result<(), JournalError> {
+        let mut state = self.lock();
+
+        let Some(append_index) = state.live_messages.remove(&id) else {
+            return Ok(());
+        };
+
+        self.write_record(&mut state, &Record::Done { id })?;
+        mark_segment_done(&mut state.segments, append_index);
+        self.rotate_if_full(&mut state)
+    }
+
+    /// Removes the oldest sealed segments while enough of their messages are done. Segment
+    /// is read record by record, so large segments are not loaded into memory at once.
+    pub(crate) fn compact(&self) -> Result<(), JournalError> {
+        let mut state = self.lock();
+        let state = &mut *state;
+        let mut record_bytes = Vec::new();
+
+        while state.segments.len() > 1 {
+            let oldest_segment = &state.segments[0];
+            if oldest_segment.done_count * 100
+                < oldest_segment.appended_count * usize::from(self.compaction_percent)
+            {
+                break;
+            }
+
+            let oldest_segment = state
+                .segments
+                .pop_front()
+                .expect("journal has at least two segments");
+            let io_error = |error| JournalError::Io(oldest_segment.path.clone(), error);
+            let mut segment_reader =
+                BufReader::new(File::open(&oldest_segment.path).map_err(io_error)?);
+            let mut moved_messages_count: usize = 0;
+
+            while read_record(&mut segment_reader, &mut record_bytes).map_err(io_error)? {
+                let Some((record, _)) = Record::decode(&record_bytes) else {
+                    break;
+                };
+
+                if let Record::Append { id, .. } = record {
+                    if state.live_messages.get(&id) == Some(&oldest_segment.index) {
+                        self.write_record(state, &record)?;
+                        let active_index = state.mark_appended();
+                        let _ = state.live_messages.insert(id, active_index);
+                        moved_messages_count += 1;
+                    }
+                }
+            }
+
+            // Copied records have to reach disk before their only other copy is removed.
+            state.sync_active_file(&self.directory)?;
+            fs::remove_file(&oldest_segment.path).map_err(io_error)?;
+
+            log::debug!(
+                "compacted journal segment {}, moved {} live messages",
+                oldest_segment.path.display(),
+                moved_messages_count
+            );
+        }
+
+        Ok(())
+    }
+
+    fn rotate_if_full(&self, state: &mut JournalState) -> Result<(), JournalError> {
+        if state.active_size < self.segment_size {
+            return Ok(());
+        }
+
+        let active_index = state
+            .segments
+            .back()
+            .expect("journal always has active segment")
+            .index;
+        let next_segment = Segment::new(&self.directory, active_index + 1);
+        // Sealed segment is never written again, so it is flushed before it is replaced.
+        state.sync_active_file(&self.directory)?;
+        state.active_file = create_segment_file(&self.directory, &next_segment.path)?;
+        state.active_size = 0;
+        state.segments.push_back(next_segment);
+
+        Ok(())
+    }
+
+    fn write_record(
+        &self,
+        state: &mut JournalState,
+        record: &Record<'_>,
+    ) -> Result<(), JournalError> {
+        let record_bytes = record.encode()?;
+
+        state
+            .active_file
+            .write_all(&record_bytes)
+            .map_err(|error| JournalError::Io(self.directory.clone(), error))?;
+        state.active_size += record_bytes.len() as u64;
+        state.is_dirty = true;
+
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JournalState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|_| panic!("mutex poisoned"))
+    }
+}
+
+/// Reads the next record as is into given buffer. `false` means that segment ends, its
+/// tail may be truncated.
+fn read_record(reader: &mut impl Read, record_bytes: &mut Vec<u8>) -> io::Result<bool> {
+    let header_length = RECORD_TYPE_LENGTH + ID_LENGTH;
+    record_bytes.resize(header_length, 0);
+    if !read_exact_or_end(reader, record_bytes)? {
+        return Ok(false);
+    }
+
+    let body_length = match record_bytes[0] {
+        APPEND_RECORD_TYPE => {
+            let bytes_start = header_length + BYTES_LENGTH_LENGTH;
+            record_bytes.resize(bytes_start, 0);
+            if !read_exact_or_end(reader, &mut record_bytes[header_length..])? {
+                return Ok(false);
+            }
+            let bytes_length = u32::from_be_bytes(
+                record_bytes[header_length..bytes_start]
+                    .try_into()
+                    .expect("bytes length has 4 bytes"),
+            );
+            bytes_start + bytes_length as usize
+        }
+        DONE_RECORD_TYPE => header_length,
+        _ => return Ok(false),
+    };
+
+    let read_length = record_bytes.len();
+    record_bytes.resize(body_length + CHECKSUM_LENGTH, 0);
+    read_exact_or_end(reader, &mut record_bytes[read_length..])
+}
+
+fn read_exact_or_end(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+fn mark_segment_done(segments: &mut VecDeque<Segment>, index: u64) {
+    if let Some(segment) = segments.iter_mut().find(|segment| segment.index == index) {
+        segment.done_count += 1;
+    }
+}
+
+fn segment_indexes(directory: &Path) -> Result<Vec<u64>, JournalError> {
+    let mut indexes = Vec::new();
+
+    for entry in fs::read_dir(directory)
+        .map_err(|error| JournalError::Io(directory.to_path_buf(), error))?
+    {
+        let path = entry
+            .map_err(|error| JournalError::Io(directory.to_path_buf(), error))?
+            .path();
+
+        if path.extension().and_then(|extension| extension.to_str()) != Some(SEGMENT_EXTENSION)
+        {
+            continue;
+        }
+
+        if let Some(index) = path
+            .file_stem()
+            .and_then(|file_stem| file_stem.to_str())
+            .and_then(|file_stem| file_stem.parse::<u64>().ok())
+        {
+            indexes.push(index);
+        }
+    }
+
+    indexes.sort_unstable();
+
+    Ok(indexes)
+}
+
+/// Creates segment file and flushes directory, so the file itself survives crash.
+fn create_segment_file(directory: &Path, path: &Path) -> Result<File, JournalError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|error| JournalError::Io(path.to_path_buf(), error))?;
+
+    File::open(directory)
+        .and_then(|directory_file| directory_file.sync_all())
+        .map_err(|error| JournalError::Io(directory.to_path_buf(), error))?;
+
+    Ok(file)
+}
+
+fn truncate(path: &Path, length: usize) -> Result<(), JournalError> {
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_len(length as u64))
+        .map_err(|error| JournalError::Io(path.to_path_buf(), error))
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::journal::segment_indexes;
+    use crate::journal::Journal;
+    use crate::journal::JournalSync;
+    use std::env;
+    use std::fs;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use uuid::Uuid;
+
+    fn temp_directory() -> PathBuf {
+        env::temp_dir().join(format!("bus-journal-{}", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn replay_pending() {
+        let directory = temp_directory();
+
+        let (journal, pending_messages) =
+            Journal::open(&directory, 1024, 100, JournalSync::EveryMessage).unwrap();
+        assert!(pending_messages.is_empty());
+        let first_id = journal.append(b"first").unwrap();
+        let second_id = journal.append(b"second").unwrap();
+        let third_id = journal.append(b"third").unwrap();
+        journal.mark_done(second_id).unwrap();
+        journal.sync().unwrap();
+        drop(journal);
+
+        let (journal, pending_messages) =
+            Journal::open(&directory, 1024, 100, JournalSync::Batch).unwrap();
+        assert_eq!(
+            vec![(first_id, b"first".to_vec()), (third_id, b"third".to_vec())],
+            pending_messages
+        );
+        assert!(journal.append(b"fourth").unwrap() > third_id);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rotation_and_compaction() {
+        let directory = temp_directory();
+
+        // Every append record is larger than segment, so each message gets own segment.
+        let (journal, _) = Journal::open(&directory, 16, 100, JournalSync::Batch).unwrap();
+        let ids = (0..5)
+            .map(|_| journal.append(b"message bytes").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(6, segment_indexes(&directory).unwrap().len());
+
+        for id in &ids[..4] {
+            journal.mark_done(*id).unwrap();
+        }
+        drop(journal);
+
+        let (_, pending_messages) =
+            Journal::open(&directory, 16, 100, JournalSync::Batch).unwrap();
+        assert_eq!(vec![(ids[4], b"message bytes".to_vec())], pending_messages);
+        assert!(segment_indexes(&directory).unwrap().len() < 6);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn live_messages_moved_by_compaction() {
+        let directory = temp_directory();
+
+        let (journal, _) = Journal::open(&directory, 1024, 50, JournalSync::Batch).unwrap();
+        let first_id = journal.append(b"first").unwrap();
+        let second_id = journal.append(b"second").unwrap();
+        drop(journal);
+
+        // Reopening seals segment with both messages, marking one of them done reaches
+        // compaction threshold, so the other one is moved to the active segment once
+        // journal is compacted.
+        let (journal, _) = Journal::open(&directory, 1024, 50, JournalSync::Batch).unwrap();
+        journal.mark_done(first_id).unwrap();
+        assert_eq!(2, segment_indexes(&directory).unwrap().len());
+        journal.compact().unwrap();
+        assert_eq!(1, segment_indexes(&directory).unwrap().len());
+        drop(journal);
+
+        let (_, pending_messages) =
+            Journal::open(&directory, 1024, 50, JournalSync::Batch).unwrap();
+        assert_eq!(vec![(second_id, b"second".to_vec())], pending_messages);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn corrupted_tail() {
+        let directory = temp_directory();
+
+        let (journal, _) = Journal::open(&directory, 1024, 100, JournalSync::Batch).unwrap();
+        let id = journal.append(b"message").unwrap();
+        drop(journal);
+
+        let segment_path = directory.join(format!("{:020}.wal", 0));
+        let mut segment_file = OpenOptions::new().append(true).open(&segment_path).unwrap();
+        segment_file.write_all(&[1, 0, 0]).unwrap();
+        drop(segment_file);
+
+        let (_, pending_messages) =
+            Journal::open(&directory, 1024, 100, JournalSync::Batch).unwrap();
+        assert_eq!(vec![(id, b"message".to_vec())], pending_messages);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn journal_sync_parse() {
+        for journal_sync in [JournalSync::Batch, JournalSync::EveryMessage] {
+            assert_eq!(Ok(journal_sync), journal_sync.to_string().parse());
+        }
+        assert!("always".parse::<JournalSync>().is_err());
+    }
+}
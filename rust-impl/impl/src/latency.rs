@@ -0,0 +1,271 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+/// Count of bits kept from recorded value, which gives precision of 3 significant digits.
+const SIGNIFICANT_BITS: u32 = 11;
+const SUB_BUCKET_COUNT: u64 = 1 << SIGNIFICANT_BITS;
+const SUB_BUCKET_HALF_COUNT: u64 = SUB_BUCKET_COUNT / 2;
+/// Exactly counted values, then half of sub-buckets for every following power of two.
+const BUCKETS_COUNT: usize =
+    (1 << SIGNIFICANT_BITS) + (64 - SIGNIFICANT_BITS as usize) * (1 << (SIGNIFICANT_BITS - 1));
+
+/// Quantiles included into latency report.
+pub const REPORTED_QUANTILES: &[(&str, f64)] =
+    &[("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p999", 0.999)];
+
+//-----------------------------------------------------------------------------------------
+// LatencyHistogram
+//-----------------------------------------------------------------------------------------
+
+/// Histogram of durations in nanoseconds with high dynamic range: every recorded value is
+/// kept with relative error below 0.1%, from one nanosecond up to centuries, using fixed
+/// amount of memory.
+///
+/// Values below 2048 are counted exactly, bigger values are grouped by power of two, and
+/// every power of two is split into 1024 linear sub-buckets.
+#[derive(Clone)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total_count: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+
+impl LatencyHistogram {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS_COUNT],
+            total_count: 0,
+            min: u64::MAX,
+            max: 0,
+            sum: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        let value = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+
+        self.counts[bucket_index(value)] += 1;
+        self.total_count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += u128::from(value);
+    }
+
+    /// Adds values recorded by other histogram.
+    pub fn merge(&mut self, other: &Self) {
+        for (count, other_count) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other_count;
+        }
+        self.total_count += other.total_count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+        self.total_count = 0;
+        self.min = u64::MAX;
+        self.max = 0;
+        self.sum = 0;
+    }
+
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.total_count
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.total_count == 0
+    }
+
+    #[must_use]
+    pub fn min(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.min)
+    }
+
+    #[must_use]
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+        let mean = self.sum / u128::from(self.total_count);
+        Duration::from_nanos(u64::try_from(mean).unwrap_or(u64::MAX))
+    }
+
+    /// Returns duration below or equal to which given fraction of recorded durations is,
+    /// e.g. `0.99` for 99th percentile. Result is the highest duration which falls into the
+    /// same bucket as the exact one, so it is never lower than the exact one.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn value_at_quantile(&self, quantile: f64) -> Duration {
+        if self.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let quantile = quantile.clamp(0.0, 1.0);
+        let rank = ((quantile * self.total_count as f64).ceil() as u64).max(1);
+
+        let mut cumulative_count = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            cumulative_count += count;
+            if cumulative_count >= rank {
+                let value = highest_equivalent_value(index).min(self.max);
+                return Duration::from_nanos(value.max(self.min));
+            }
+        }
+
+        self.max()
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("len", &self.len())
+            .field("min", &self.min())
+            .field("max", &self.max())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Formats count, mean, reported quantiles and maximum, e.g. for logging.
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "count {}, mean {:?}", self.len(), self.mean())?;
+        for (name, quantile) in REPORTED_QUANTILES {
+            write!(f, ", {} {:?}", name, self.value_at_quantile(*quantile))?;
+        }
+        write!(f, ", max {:?}", self.max())
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKET_COUNT {
+        return value as usize;
+    }
+
+    // Values with the same highest bit share one group of sub-buckets, which are
+    // addressed by the following significant bits.
+    let magnitude = u64::from(value.ilog2());
+    let shift = magnitude - u64::from(SIGNIFICANT_BITS - 1);
+    let sub_bucket = (value >> shift) - SUB_BUCKET_HALF_COUNT;
+
+    (SUB_BUCKET_COUNT + (shift - 1) * SUB_BUCKET_HALF_COUNT + sub_bucket) as usize
+}
+
+fn highest_equivalent_value(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKET_COUNT {
+        return index;
+    }
+
+    let shift = (index - SUB_BUCKET_COUNT) / SUB_BUCKET_HALF_COUNT + 1;
+    let sub_bucket =
+        (index - SUB_BUCKET_COUNT) % SUB_BUCKET_HALF_COUNT + SUB_BUCKET_HALF_COUNT;
+
+    ((sub_bucket + 1) << shift).wrapping_sub(1)
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::latency::bucket_index;
+    use crate::latency::highest_equivalent_value;
+    use crate::latency::LatencyHistogram;
+    use crate::latency::BUCKETS_COUNT;
+    use std::time::Duration;
+
+    #[test]
+    fn buckets() {
+        assert_eq!(0, bucket_index(0));
+        assert_eq!(2047, bucket_index(2047));
+        assert_eq!(2048, bucket_index(2048));
+        assert_eq!(2048, bucket_index(2049));
+        assert_eq!(2049, bucket_index(2050));
+        assert_eq!(BUCKETS_COUNT - 1, bucket_index(u64::MAX));
+
+        for value in &[0, 2047, 2049, 123_456_789, u64::MAX / 3, u64::MAX] {
+            let highest_value = highest_equivalent_value(bucket_index(*value));
+            assert!(highest_value >= *value);
+            assert!(highest_value - value <= value / 1000);
+        }
+    }
+
+    #[test]
+    fn quantiles() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(Duration::ZERO, histogram.value_at_quantile(0.5));
+
+        for micros in 1..=1000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        let assert_close = |expected_micros: u64, quantile: f64| {
+            let value = histogram.value_at_quantile(quantile);
+            let expected = Duration::from_micros(expected_micros);
+            assert!(
+                value
+                    .checked_sub(expected)
+                    .is_some_and(|error| error <= expected / 1000),
+                "{:?} is not close to {:?}",
+                value,
+                expected
+            );
+        };
+        assert_close(500, 0.5);
+        assert_close(990, 0.99);
+        assert_close(999, 0.999);
+        assert_close(1000, 1.0);
+        assert_close(1, 0.0);
+
+        assert_eq!(1000, histogram.len());
+        assert_eq!(Duration::from_micros(1), histogram.min());
+        assert_eq!(Duration::from_millis(1_u64), histogram.max());
+        assert_eq!(Duration::from_nanos(500_500), histogram.mean());
+    }
+
+    #[test]
+    fn merge_and_reset() {
+        let mut left = LatencyHistogram::new();
+        let mut right = LatencyHistogram::new();
+        left.record(Duration::from_millis(1));
+        right.record(Duration::from_millis(3));
+
+        left.merge(&right);
+        assert_eq!(2, left.len());
+        assert_eq!(Duration::from_millis(2), left.mean());
+        assert_eq!(Duration::from_millis(3), left.max());
+
+        left.reset();
+        assert!(left.is_empty());
+        assert_eq!(Duration::ZERO, left.min());
+    }
+}
@@ -22,34 +22,127 @@
 #![allow(clippy::missing_panics_doc)]
 #![allow(clippy::missing_errors_doc)]
 
-#[macro_export]
-macro_rules! __format_endpoint {
-    ($endpoint:expr) => {
-        format!("tcp://{}", $endpoint)
-    };
-}
-
-lazy_static::lazy_static! {
-    pub static ref __BUS_ROUTER_SOCKET_ADDR: String = format_endpoint!("0.0.0.0:56731");
-    pub static ref __BUS_PUBLISHERS_SOCKET_ADDRS: Vec<String> = vec![
-        format_endpoint!("0.0.0.0:56738"),
-        format_endpoint!("0.0.0.0:56739"),
-        format_endpoint!("0.0.0.0:56740"),
-        format_endpoint!("0.0.0.0:56741"),
-        format_endpoint!("0.0.0.0:56742")
-    ];
-}
+use zeromq_messages::format::PayloadFormat;
 
 pub const REQUESTS_COUNT_INSIDE_ONE_GROUP: usize = 25_000;
 pub const ZEROMQ_ZERO_FLAG: i32 = 0;
 pub const LOG_LEVEL: &str = "debug";
 pub const RUST_LOG_ENVIRONMENT_VARIABLE_NAME: &str = "RUST_LOG";
+pub const PAYLOAD_FORMAT: PayloadFormat = PayloadFormat::MessagePack;
+
+#[cfg(feature = "tokio")]
+mod async_client;
+#[cfg(feature = "tokio")]
+pub use async_client::AsyncBusClient;
+#[cfg(feature = "tokio")]
+pub use async_client::IncomingMessage;
+#[cfg(feature = "tokio")]
+pub use async_client::MessageStream;
+
+#[cfg(feature = "tokio")]
+mod async_service;
+#[cfg(feature = "tokio")]
+pub use async_service::AsyncBusService;
+
+mod bus;
+pub use bus::Bus;
+pub use bus::BusError;
+pub use bus::BusStats;
+
+mod client;
+pub use client::BusClient;
+pub use client::BusClientError;
+pub use client::PendingResponse;
+pub use client::RESEND_ACKNOWLEDGED_REQUESTS_EVERY_DURATION;
+pub use client::RESEND_REQUESTS_EVERY_DURATION;
+
+mod config;
+pub use config::BusConfig;
+pub use config::BusConfigArgs;
+pub use config::BusConfigError;
+pub use config::BusServerArgs;
+
+mod curve;
+pub use curve::CurveAllowlist;
+pub use curve::CurveError;
+pub use curve::CurveKey;
+pub use curve::CurveKeyPair;
+pub use curve::CurveKeyParseError;
+pub use curve::CURVE_KEY_LENGTH;
+
+mod endpoint;
+pub use endpoint::Endpoint;
+pub use endpoint::EndpointParseError;
+pub use endpoint::Transport;
+
+mod handlers;
+pub use handlers::value_multiplication;
+pub use handlers::VALUE_MULTIPLICATION_SERVICE_NAME;
 
 mod helpers;
 pub use helpers::BusPublisherData;
 pub use helpers::DeadLockSafeMutex;
 pub use helpers::DeadLockSafeRwLock;
 
-pub use __format_endpoint as format_endpoint;
-pub use __BUS_PUBLISHERS_SOCKET_ADDRS as BUS_PUBLISHERS_SOCKET_ADDRS;
-pub use __BUS_ROUTER_SOCKET_ADDR as BUS_ROUTER_SOCKET_ADDR;
+mod journal;
+pub use journal::JournalError;
+pub use journal::JournalSync;
+pub use journal::JournalSyncParseError;
+
+mod latency;
+pub use latency::LatencyHistogram;
+pub use latency::REPORTED_QUANTILES;
+
+mod load;
+pub use load::LoadArgs;
+pub use load::LoadArgsError;
+pub use load::LoadGenerator;
+pub use load::LoadProfile;
+pub use load::LoadProfileKind;
+pub use load::LoadProfileKindParseError;
+pub use load::Pace;
+
+mod metrics;
+pub use metrics::Counter;
+pub use metrics::Gauge;
+pub use metrics::Histogram;
+pub use metrics::Metrics;
+pub use metrics::MetricsError;
+pub use metrics::MetricsServer;
+pub use metrics::DEFAULT_LATENCY_BUCKETS;
+
+mod queue;
+pub use queue::OverflowPolicy;
+pub use queue::OverflowPolicyParseError;
+
+mod registry;
+
+mod selector;
+pub use selector::ConsistentHashSelector;
+pub use selector::LeastQueuedSelector;
+pub use selector::LeastRecentlyUsedSelector;
+pub use selector::PublisherLoad;
+pub use selector::PublisherSelection;
+pub use selector::PublisherSelectionParseError;
+pub use selector::PublisherSelector;
+pub use selector::RoundRobinSelector;
+
+mod service;
+pub use service::BusService;
+pub use service::BusServiceError;
+
+mod shutdown;
+pub use shutdown::Shutdown;
+pub use shutdown::ShutdownError;
+pub use shutdown::SOCKET_LINGER_DURATION;
+
+mod subscriptions;
+
+mod topic;
+pub use topic::kind_topic;
+pub use topic::recv_published_message;
+pub use topic::send_published_message;
+pub use topic::subscribe_to_kinds;
+pub use topic::TOPIC_LENGTH;
+
+mod work_queue;
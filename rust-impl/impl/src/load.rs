@@ -0,0 +1,432 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use structopt::StructOpt;
+
+/// How long sender waits before checking again whether outstanding requests are answered.
+const OUTSTANDING_CHECK_INTERVAL: Duration = Duration::from_micros(100_u64);
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("Unsupported load profile {0}, expected one of max, fixed-rate, ramp-up, bursty")]
+pub struct LoadProfileKindParseError(String);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, thiserror::Error)]
+pub enum LoadArgsError {
+    #[error("Rate is required by {0} load profile")]
+    MissingRate(LoadProfileKind),
+
+    #[error("Rate must be a positive number of messages per second")]
+    InvalidRate,
+
+    #[error("Ramp-up duration must be greater than zero")]
+    ZeroRampUpDuration,
+
+    #[error("Burst size must be greater than zero")]
+    ZeroBurstSize,
+
+    #[error("Burst interval must be greater than zero")]
+    ZeroBurstInterval,
+
+    #[error("Total count must be greater than zero")]
+    ZeroTotalCount,
+
+    #[error("Count of outstanding requests must be greater than zero")]
+    ZeroMaxOutstanding,
+}
+
+//-----------------------------------------------------------------------------------------
+// LoadProfileKind
+//-----------------------------------------------------------------------------------------
+
+/// How fast requests are sent, selected on command line.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LoadProfileKind {
+    /// As fast as possible.
+    #[default]
+    Max,
+    /// Constant count of requests per second.
+    FixedRate,
+    /// Rate grows linearly from zero, then stays constant.
+    RampUp,
+    /// Bursts of requests sent at once with pauses between them.
+    Bursty,
+}
+
+impl LoadProfileKind {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Max => "max",
+            Self::FixedRate => "fixed-rate",
+            Self::RampUp => "ramp-up",
+            Self::Bursty => "bursty",
+        }
+    }
+}
+
+impl fmt::Display for LoadProfileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LoadProfileKind {
+    type Err = LoadProfileKindParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Self::Max),
+            "fixed-rate" => Ok(Self::FixedRate),
+            "ramp-up" => Ok(Self::RampUp),
+            "bursty" => Ok(Self::Bursty),
+            _ => Err(LoadProfileKindParseError(s.to_owned())),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// LoadProfile
+//-----------------------------------------------------------------------------------------
+
+/// Schedule of requests, defined by the moment at which every request is due.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadProfile {
+    Max,
+    /// `rate` requests per second.
+    FixedRate {
+        rate: f64,
+    },
+    /// Rate grows linearly from zero to `rate` during `duration`, then stays constant.
+    RampUp {
+        rate: f64,
+        duration: Duration,
+    },
+    /// `burst_size` requests at once every `interval`.
+    Bursty {
+        burst_size: u64,
+        interval: Duration,
+    },
+}
+
+impl LoadProfile {
+    /// Returns time since start at which request is due when `sent_count` requests are
+    /// already sent. Time too far in the future to be represented saturates to
+    /// `Duration::MAX`.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn due_time(&self, sent_count: u64) -> Duration {
+        let sent_count_f64 = sent_count as f64;
+
+        match *self {
+            Self::Max => Duration::ZERO,
+            Self::FixedRate { rate } => saturating_duration(sent_count_f64 / rate),
+            Self::RampUp { rate, duration } => {
+                // Count of requests sent during ramp-up is the area under rate line.
+                let duration_secs = duration.as_secs_f64();
+                let ramp_up_count = rate * duration_secs / 2.0;
+                if sent_count_f64 < ramp_up_count {
+                    saturating_duration((2.0 * duration_secs * sent_count_f64 / rate).sqrt())
+                } else {
+                    duration.saturating_add(saturating_duration(
+                        (sent_count_f64 - ramp_up_count) / rate,
+                    ))
+                }
+            }
+            Self::Bursty {
+                burst_size,
+                interval,
+            } => interval
+                .checked_mul(u32::try_from(sent_count / burst_size).unwrap_or(u32::MAX))
+                .unwrap_or(Duration::MAX),
+        }
+    }
+}
+
+fn saturating_duration(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+//-----------------------------------------------------------------------------------------
+// LoadArgs
+//-----------------------------------------------------------------------------------------
+
+/// Command line flags of load generated by sender. Binaries flatten these into their own
+/// arguments.
+#[derive(Debug, Clone, Copy, StructOpt)]
+pub struct LoadArgs {
+    /// How fast requests are sent: max, fixed-rate, ramp-up or bursty.
+    #[structopt(long, env = "SENDER_LOAD_PROFILE", default_value = "max")]
+    pub load_profile: LoadProfileKind,
+
+    /// Requests per second of fixed-rate profile and final rate of ramp-up profile.
+    #[structopt(long, env = "SENDER_RATE")]
+    pub rate: Option<f64>,
+
+    /// Seconds during which ramp-up profile grows rate from zero.
+    #[structopt(long, env = "SENDER_RAMP_UP_SECS", default_value = "10")]
+    pub ramp_up_secs: u64,
+
+    /// Requests sent at once by bursty profile, defaults to group size.
+    #[structopt(long, env = "SENDER_BURST_SIZE")]
+    pub burst_size: Option<u64>,
+
+    /// Milliseconds between bursts of bursty profile.
+    #[structopt(long, env = "SENDER_BURST_INTERVAL_MILLIS", default_value = "1000")]
+    pub burst_interval_millis: u64,
+
+    /// Count of requests after which sender stops and exits, sends until stopped if unset.
+    #[structopt(long, env = "SENDER_TOTAL_COUNT")]
+    pub total_count: Option<u64>,
+
+    /// Maximum count of requests waiting for response, new requests wait until older ones
+    /// are answered. Unlimited if unset.
+    #[structopt(long, env = "SENDER_MAX_OUTSTANDING")]
+    pub max_outstanding: Option<usize>,
+}
+
+impl LoadArgs {
+    /// Validates flags and builds generator of load described by them, `default_burst_size`
+    /// is used by bursty profile if burst size is not set.
+    pub fn generator(&self, default_burst_size: u64) -> Result<LoadGenerator, LoadArgsError> {
+        let rate = || match self.rate {
+            Some(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+            Some(_) => Err(LoadArgsError::InvalidRate),
+            None => Err(LoadArgsError::MissingRate(self.load_profile)),
+        };
+
+        let profile = match self.load_profile {
+            LoadProfileKind::Max => LoadProfile::Max,
+            LoadProfileKind::FixedRate => LoadProfile::FixedRate { rate: rate()? },
+            LoadProfileKind::RampUp => {
+                if self.ramp_up_secs == 0 {
+                    return Err(LoadArgsError::ZeroRampUpDuration);
+                }
+                LoadProfile::RampUp {
+                    rate: rate()?,
+                    duration: Duration::from_secs(self.ramp_up_secs),
+                }
+            }
+            LoadProfileKind::Bursty => {
+                let burst_size = self.burst_size.unwrap_or(default_burst_size);
+                if burst_size == 0 {
+                    return Err(LoadArgsError::ZeroBurstSize);
+                }
+                if self.burst_interval_millis == 0 {
+                    return Err(LoadArgsError::ZeroBurstInterval);
+                }
+                LoadProfile::Bursty {
+                    burst_size,
+                    interval: Duration::from_millis(self.burst_interval_millis),
+                }
+            }
+        };
+
+        if self.total_count == Some(0) {
+            return Err(LoadArgsError::ZeroTotalCount);
+        }
+
+        if self.max_outstanding == Some(0) {
+            return Err(LoadArgsError::ZeroMaxOutstanding);
+        }
+
+        Ok(LoadGenerator {
+            profile,
+            total_count: self.total_count,
+            max_outstanding: self.max_outstanding,
+            sent_count: 0,
+        })
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// LoadGenerator
+//-----------------------------------------------------------------------------------------
+
+/// What sender should do next.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Pace {
+    Send,
+    /// Next request is not due yet or too many requests wait for response.
+    Wait(Duration),
+    /// Total count of requests is sent.
+    Done,
+}
+
+/// Decides when sender sends requests according to load profile and limits.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadGenerator {
+    profile: LoadProfile,
+    total_count: Option<u64>,
+    max_outstanding: Option<usize>,
+    sent_count: u64,
+}
+
+impl LoadGenerator {
+    /// Decides what to do `elapsed` after start of sending. Count of requests which wait
+    /// for response is asked only if it is limited.
+    pub fn pace(&self, elapsed: Duration, outstanding_count: impl FnOnce() -> usize) -> Pace {
+        if self
+            .total_count
+            .is_some_and(|total_count| self.sent_count >= total_count)
+        {
+            return Pace::Done;
+        }
+
+        let due_time = self.profile.due_time(self.sent_count);
+        if elapsed < due_time {
+            return Pace::Wait(due_time.saturating_sub(elapsed));
+        }
+
+        if self
+            .max_outstanding
+            .is_some_and(|max_outstanding| outstanding_count() >= max_outstanding)
+        {
+            return Pace::Wait(OUTSTANDING_CHECK_INTERVAL);
+        }
+
+        Pace::Send
+    }
+
+    pub fn record_sent(&mut self) {
+        self.sent_count += 1;
+    }
+
+    #[must_use]
+    pub fn profile(&self) -> LoadProfile {
+        self.profile
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::load::LoadArgs;
+    use crate::load::LoadArgsError;
+    use crate::load::LoadProfile;
+    use crate::load::LoadProfileKind;
+    use crate::load::Pace;
+    use std::time::Duration;
+    use structopt::StructOpt;
+
+    fn generator(args: &[&str]) -> Result<crate::load::LoadGenerator, LoadArgsError> {
+        LoadArgs::from_iter_safe(std::iter::once("bin").chain(args.iter().copied()))
+            .unwrap()
+            .generator(100)
+    }
+
+    #[test]
+    fn due_time() {
+        let fixed_rate = LoadProfile::FixedRate { rate: 100.0 };
+        assert_eq!(Duration::ZERO, fixed_rate.due_time(0));
+        assert_eq!(Duration::from_millis(10_u64), fixed_rate.due_time(1));
+        assert_eq!(Duration::from_secs(1_u64), fixed_rate.due_time(100));
+
+        // 100 requests are sent during 2 seconds of ramp-up, then 100 per second.
+        let ramp_up = LoadProfile::RampUp {
+            rate: 100.0,
+            duration: Duration::from_secs(2_u64),
+        };
+        assert_eq!(Duration::from_secs(1_u64), ramp_up.due_time(25));
+        assert_eq!(Duration::from_secs(2_u64), ramp_up.due_time(100));
+        assert_eq!(Duration::from_secs(3_u64), ramp_up.due_time(200));
+
+        let bursty = LoadProfile::Bursty {
+            burst_size: 10,
+            interval: Duration::from_millis(500_u64),
+        };
+        assert_eq!(Duration::ZERO, bursty.due_time(9));
+        assert_eq!(Duration::from_millis(500_u64), bursty.due_time(10));
+        assert_eq!(Duration::from_secs(1_u64), bursty.due_time(25));
+
+        assert_eq!(Duration::ZERO, LoadProfile::Max.due_time(1_000_000));
+
+        // Tiny rate or long interval make requests due later than `Duration` can hold.
+        let fixed_rate = LoadProfile::FixedRate { rate: 1e-20 };
+        assert_eq!(Duration::MAX, fixed_rate.due_time(1));
+        let ramp_up = LoadProfile::RampUp {
+            rate: 1e-20,
+            duration: Duration::from_secs(2_u64),
+        };
+        assert_eq!(Duration::MAX, ramp_up.due_time(1));
+        let bursty = LoadProfile::Bursty {
+            burst_size: 1,
+            interval: Duration::MAX,
+        };
+        assert_eq!(Duration::MAX, bursty.due_time(2));
+    }
+
+    #[test]
+    fn pace() {
+        let mut generator = generator(&[
+            "--load-profile",
+            "fixed-rate",
+            "--rate",
+            "10",
+            "--total-count",
+            "2",
+            "--max-outstanding",
+            "1",
+        ])
+        .unwrap();
+
+        assert_eq!(Pace::Send, generator.pace(Duration::ZERO, || 0));
+        generator.record_sent();
+        assert_eq!(
+            Pace::Wait(Duration::from_millis(60_u64)),
+            generator.pace(Duration::from_millis(40_u64), || 0)
+        );
+        assert!(matches!(
+            generator.pace(Duration::from_millis(100_u64), || 1),
+            Pace::Wait(_)
+        ));
+        assert_eq!(
+            Pace::Send,
+            generator.pace(Duration::from_millis(100_u64), || 0)
+        );
+        generator.record_sent();
+        assert_eq!(Pace::Done, generator.pace(Duration::from_secs(1_u64), || 0));
+    }
+
+    #[test]
+    fn args() {
+        assert_eq!(LoadProfile::Max, generator(&[]).unwrap().profile());
+        assert_eq!(
+            LoadProfile::Bursty {
+                burst_size: 100,
+                interval: Duration::from_secs(1_u64),
+            },
+            generator(&["--load-profile", "bursty"]).unwrap().profile()
+        );
+        assert_eq!(
+            LoadProfile::RampUp {
+                rate: 50.0,
+                duration: Duration::from_secs(10_u64),
+            },
+            generator(&["--load-profile", "ramp-up", "--rate", "50"])
+                .unwrap()
+                .profile()
+        );
+
+        assert_eq!(
+            Err(LoadArgsError::MissingRate(LoadProfileKind::FixedRate)),
+            generator(&["--load-profile", "fixed-rate"]).map(|generator| generator.profile())
+        );
+        assert!(matches!(
+            generator(&["--load-profile", "fixed-rate", "--rate", "0"]),
+            Err(LoadArgsError::InvalidRate)
+        ));
+        assert!(matches!(
+            generator(&["--max-outstanding", "0"]),
+            Err(LoadArgsError::ZeroMaxOutstanding)
+        ));
+        assert!(LoadArgs::from_iter_safe(vec!["bin", "--load-profile", "fast"]).is_err());
+    }
+}
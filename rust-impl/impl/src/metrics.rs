@@ -0,0 +1,617 @@
+use crate::shutdown::Shutdown;
+use crate::shutdown::SHUTDOWN_CHECK_INTERVAL;
+use std::fmt;
+use std::fmt::Write as _;
+use std::io;
+use std::io::Read;
+use std::io::Write as _;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Upper bounds in seconds of buckets of histograms which measure latency.
+pub const DEFAULT_LATENCY_BUCKETS: &[f64] = &[
+    0.000_1, 0.000_25, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+    2.5, 5.0, 10.0,
+];
+const METRICS_PATH: &str = "/metrics";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const SCRAPE_REQUEST_TIMEOUT: Duration = Duration::from_secs(1_u64);
+const MAX_SCRAPE_REQUEST_LENGTH: usize = 8 * 1024;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetricsError {
+    #[error("Failed to bind metrics endpoint on {0}")]
+    CantBind(SocketAddr, #[source] io::Error),
+}
+
+//-----------------------------------------------------------------------------------------
+// Counter, Gauge, Histogram
+//-----------------------------------------------------------------------------------------
+
+/// Value which only grows, e.g. count of received messages.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        let _ = self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Value which goes up and down, e.g. count of messages inside buffer.
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Distribution of observed values between buckets with given upper bounds.
+#[derive(Debug)]
+pub struct Histogram {
+    upper_bounds: Vec<f64>,
+    bucket_counts: Vec<AtomicU64>,
+    sum_bits: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new(upper_bounds: &[f64]) -> Self {
+        Self {
+            upper_bounds: upper_bounds.to_vec(),
+            bucket_counts: upper_bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_bits: AtomicU64::new(0_f64.to_bits()),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        if let Some(index) = self
+            .upper_bounds
+            .iter()
+            .position(|upper_bound| value <= *upper_bound)
+        {
+            let _ = self.bucket_counts[index].fetch_add(1, Ordering::Relaxed);
+        }
+
+        let _ = self
+            .sum_bits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum_bits| {
+                Some((f64::from_bits(sum_bits) + value).to_bits())
+            });
+        let _ = self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    #[must_use]
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.sum_bits.load(Ordering::Relaxed))
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Metrics
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+impl Metric {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Counter(_) => "counter",
+            Self::Gauge(_) => "gauge",
+            Self::Histogram(_) => "histogram",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MetricFamily {
+    name: String,
+    help: String,
+    series: Vec<(Vec<(String, String)>, Metric)>,
+}
+
+/// Registry of metrics which renders them in Prometheus text format. Clones share the
+/// same metrics.
+#[derive(Clone, Default)]
+pub struct Metrics(Arc<Mutex<Vec<MetricFamily>>>);
+
+impl Metrics {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns counter with given name and labels, registering it on first call.
+    #[must_use]
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Arc<Counter> {
+        match self.get_or_register(name, help, labels, || Metric::Counter(Arc::default())) {
+            Metric::Counter(counter) => counter,
+            metric => panic!(
+                "metric {} is already registered as {}",
+                name,
+                metric.type_name()
+            ),
+        }
+    }
+
+    /// Returns gauge with given name and labels, registering it on first call.
+    #[must_use]
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Arc<Gauge> {
+        match self.get_or_register(name, help, labels, || Metric::Gauge(Arc::default())) {
+            Metric::Gauge(gauge) => gauge,
+            metric => panic!(
+                "metric {} is already registered as {}",
+                name,
+                metric.type_name()
+            ),
+        }
+    }
+
+    /// Returns histogram with given name and labels, registering it with given bucket upper
+    /// bounds on first call.
+    #[must_use]
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        upper_bounds: &[f64],
+    ) -> Arc<Histogram> {
+        match self.get_or_register(name, help, labels, || {
+            Metric::Histogram(Arc::new(Histogram::new(upper_bounds)))
+        }) {
+            Metric::Histogram(histogram) => histogram,
+            metric => panic!(
+                "metric {} is already registered as {}",
+                name,
+                metric.type_name()
+            ),
+        }
+    }
+
+    /// Renders all metrics in Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        for family in self.lock().iter() {
+            let Some((_, first_metric)) = family.series.first() else {
+                continue;
+            };
+
+            let _ = writeln!(output, "# HELP {} {}", family.name, family.help);
+            let _ = writeln!(
+                output,
+                "# TYPE {} {}",
+                family.name,
+                first_metric.type_name()
+            );
+
+            for (labels, metric) in &family.series {
+                match metric {
+                    Metric::Counter(counter) => {
+                        write_sample(&mut output, &family.name, labels, None, counter.get());
+                    }
+                    Metric::Gauge(gauge) => {
+                        write_sample(&mut output, &family.name, labels, None, gauge.get());
+                    }
+                    Metric::Histogram(histogram) => {
+                        write_histogram(&mut output, &family.name, labels, histogram);
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Serves metrics over HTTP at `/metrics` path on a separate thread until shutdown is
+    /// requested.
+    pub fn serve(
+        &self,
+        address: SocketAddr,
+        shutdown: &Shutdown,
+    ) -> Result<MetricsServer, MetricsError> {
+        let listener = TcpListener::bind(address)
+            .and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            })
+            .map_err(|error| MetricsError::CantBind(address, error))?;
+        let local_address = listener
+            .local_addr()
+            .map_err(|error| MetricsError::CantBind(address, error))?;
+
+        log::debug!(
+            "serving metrics on http://{}{}",
+            local_address,
+            METRICS_PATH
+        );
+
+        let metrics = self.clone();
+        let shutdown = shutdown.clone();
+        let join_handle = thread::Builder::new()
+            .name(String::from("metrics"))
+            .spawn(move || serve_scrapes(&listener, &metrics, &shutdown))
+            .expect("failed to spawn metrics thread");
+
+        Ok(MetricsServer {
+            local_address,
+            join_handle,
+        })
+    }
+
+    fn get_or_register(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        new_metric: impl FnOnce() -> Metric,
+    ) -> Metric {
+        let labels: Vec<(String, String)> = labels
+            .iter()
+            .map(|(label_name, label_value)| {
+                ((*label_name).to_owned(), (*label_value).to_owned())
+            })
+            .collect();
+
+        let mut families = self.lock();
+        if !families.iter().any(|family| family.name == name) {
+            families.push(MetricFamily {
+                name: name.to_owned(),
+                help: help.to_owned(),
+                series: Vec::new(),
+            });
+        }
+
+        let family = families
+            .iter_mut()
+            .find(|family| family.name == name)
+            .expect("family is registered above");
+        if let Some((_, metric)) = family
+            .series
+            .iter()
+            .find(|(known_labels, _)| *known_labels == labels)
+        {
+            return metric.clone();
+        }
+
+        let metric = new_metric();
+        family.series.push((labels, metric.clone()));
+        metric
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<MetricFamily>> {
+        self.0.lock().unwrap_or_else(|_| panic!("mutex poisoned"))
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics")
+            .field("families_count", &self.lock().len())
+            .finish_non_exhaustive()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// MetricsServer
+//-----------------------------------------------------------------------------------------
+
+/// HTTP endpoint serving metrics, stops together with shutdown of its owner.
+#[derive(Debug)]
+pub struct MetricsServer {
+    local_address: SocketAddr,
+    join_handle: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Address on which endpoint listens, differs from requested one if port was zero.
+    #[must_use]
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+
+    /// Waits until endpoint is stopped by shutdown.
+    pub fn join(self) {
+        if self.join_handle.join().is_err() {
+            log::error!("metrics thread panicked");
+        }
+    }
+}
+
+fn serve_scrapes(listener: &TcpListener, metrics: &Metrics, shutdown: &Shutdown) {
+    while !shutdown.is_requested() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(error) = answer_scrape(stream, metrics) {
+                    log::debug!("failed to answer metrics scrape because of: {}", error);
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(SHUTDOWN_CHECK_INTERVAL);
+            }
+            Err(error) => {
+                log::error!("failed to accept metrics connection because of: {}", error);
+                thread::sleep(SHUTDOWN_CHECK_INTERVAL);
+            }
+        }
+    }
+
+    log::debug!("metrics endpoint stopped");
+}
+
+/// Reads request head and answers with metrics if `/metrics` is requested. Connection is
+/// closed after every response.
+fn answer_scrape(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(SCRAPE_REQUEST_TIMEOUT))?;
+
+    let mut request = Vec::new();
+    let mut buffer = [0_u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n")
+        && request.len() < MAX_SCRAPE_REQUEST_LENGTH
+    {
+        let read_length = stream.read(&mut buffer)?;
+        if read_length == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read_length]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let (method, path) = (request_line.next(), request_line.next());
+
+    let (status, body) = match (method, path) {
+        (Some("GET"), Some(METRICS_PATH)) => ("200 OK", metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", String::from("Not Found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            String::from("Method Not Allowed\n"),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn write_sample(
+    output: &mut String,
+    name: &str,
+    labels: &[(String, String)],
+    extra_label: Option<(&str, &str)>,
+    value: impl fmt::Display,
+) {
+    output.push_str(name);
+
+    let mut labels = labels
+        .iter()
+        .map(|(label_name, label_value)| (label_name.as_str(), label_value.as_str()))
+        .chain(extra_label)
+        .peekable();
+
+    if labels.peek().is_some() {
+        output.push('{');
+        for (index, (label_name, label_value)) in labels.enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+            let _ = write!(
+                output,
+                "{}=\"{}\"",
+                label_name,
+                escape_label_value(label_value)
+            );
+        }
+        output.push('}');
+    }
+
+    let _ = writeln!(output, " {value}");
+}
+
+fn write_histogram(
+    output: &mut String,
+    name: &str,
+    labels: &[(String, String)],
+    histogram: &Histogram,
+) {
+    let bucket_name = format!("{name}_bucket");
+    let mut cumulative_count = 0;
+
+    for (upper_bound, bucket_count) in
+        histogram.upper_bounds.iter().zip(&histogram.bucket_counts)
+    {
+        cumulative_count += bucket_count.load(Ordering::Relaxed);
+        let upper_bound = upper_bound.to_string();
+        write_sample(
+            output,
+            &bucket_name,
+            labels,
+            Some(("le", &upper_bound)),
+            cumulative_count,
+        );
+    }
+
+    write_sample(
+        output,
+        &bucket_name,
+        labels,
+        Some(("le", "+Inf")),
+        histogram.count(),
+    );
+    write_sample(
+        output,
+        &format!("{name}_sum"),
+        labels,
+        None,
+        histogram.sum(),
+    );
+    write_sample(
+        output,
+        &format!("{name}_count"),
+        labels,
+        None,
+        histogram.count(),
+    );
+}
+
+fn escape_label_value(label_value: &str) -> String {
+    label_value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::Metrics;
+    use crate::shutdown::Shutdown;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    fn scrape(metrics: &Metrics, path: &str) -> String {
+        let shutdown = Shutdown::new();
+        let metrics_server = metrics
+            .serve("127.0.0.1:0".parse().unwrap(), &shutdown)
+            .unwrap();
+
+        let mut stream = TcpStream::connect(metrics_server.local_address()).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).unwrap();
+
+        shutdown.request();
+        metrics_server.join();
+
+        response
+    }
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::new();
+        metrics.counter("received_total", "Received.", &[]).add(3);
+        metrics
+            .counter("errors_total", "Errors.", &[("error", "truncated")])
+            .inc();
+        metrics
+            .counter("errors_total", "Errors.", &[("error", "say \"hi\"")])
+            .inc();
+        metrics.gauge("depth", "Depth.", &[]).set(7);
+        let histogram = metrics.histogram("latency_seconds", "Latency.", &[], &[0.1, 1.0]);
+        histogram.observe(0.05);
+        histogram.observe(0.5);
+        histogram.observe(5.0);
+
+        assert_eq!(
+            "# HELP received_total Received.\n\
+             # TYPE received_total counter\n\
+             received_total 3\n\
+             # HELP errors_total Errors.\n\
+             # TYPE errors_total counter\n\
+             errors_total{error=\"truncated\"} 1\n\
+             errors_total{error=\"say \\\"hi\\\"\"} 1\n\
+             # HELP depth Depth.\n\
+             # TYPE depth gauge\n\
+             depth 7\n\
+             # HELP latency_seconds Latency.\n\
+             # TYPE latency_seconds histogram\n\
+             latency_seconds_bucket{le=\"0.1\"} 1\n\
+             latency_seconds_bucket{le=\"1\"} 2\n\
+             latency_seconds_bucket{le=\"+Inf\"} 3\n\
+             latency_seconds_sum 5.55\n\
+             latency_seconds_count 3\n",
+            metrics.render()
+        );
+    }
+
+    #[test]
+    fn same_metric() {
+        let metrics = Metrics::new();
+        let counter = metrics.counter("received_total", "Received.", &[]);
+        counter.inc();
+
+        assert!(Arc::ptr_eq(
+            &counter,
+            &metrics.counter("received_total", "Received.", &[])
+        ));
+        assert_eq!(1, metrics.clone().counter("received_total", "", &[]).get());
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered as counter")]
+    fn same_name_other_type() {
+        let metrics = Metrics::new();
+        let _ = metrics.counter("received_total", "Received.", &[]);
+        let _ = metrics.gauge("received_total", "Received.", &[]);
+    }
+
+    #[test]
+    fn serve() {
+        let metrics = Metrics::new();
+        metrics.counter("received_total", "Received.", &[]).inc();
+
+        let response = scrape(&metrics, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\n# HELP received_total Received.\n# TYPE received_total counter\nreceived_total 1\n"));
+
+        assert!(scrape(&metrics, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,186 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error(
+    "Unsupported overflow policy {0}, expected one of block, drop-oldest, drop-newest, reject"
+)]
+pub struct OverflowPolicyParseError(String);
+
+//-----------------------------------------------------------------------------------------
+// OverflowPolicy
+//-----------------------------------------------------------------------------------------
+
+/// What BUS does with received message when its queue is full.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Stop reading router socket until there is free space, so senders are slowed down
+    /// by high water marks of their sockets.
+    #[default]
+    Block,
+    /// Drop the oldest queued message to free space for received one.
+    DropOldest,
+    /// Drop received message and tell its sender with `BusRejection` that it is dropped.
+    DropNewest,
+    /// Drop received message and answer its sender with `BusRejection`.
+    Reject,
+}
+
+impl OverflowPolicy {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::DropOldest => "drop-oldest",
+            Self::DropNewest => "drop-newest",
+            Self::Reject => "reject",
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = OverflowPolicyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            "drop-newest" => Ok(Self::DropNewest),
+            "reject" => Ok(Self::Reject),
+            _ => Err(OverflowPolicyParseError(s.to_owned())),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// BoundedQueue
+//-----------------------------------------------------------------------------------------
+
+/// Queue with limited capacity which applies overflow policy when it is full.
+#[derive(Debug)]
+pub(crate) struct BoundedQueue<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+}
+
+impl<T> BoundedQueue<T> {
+    pub(crate) fn new(capacity: usize, overflow_policy: OverflowPolicy) -> Self {
+        Self {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            overflow_policy,
+        }
+    }
+
+    /// Pushes item, returns item which did not fit into queue: the oldest one for
+    /// `DropOldest` policy and given one for the rest of policies. Owner of queue with
+    /// `Block` policy stops taking new items while queue is full instead.
+    pub(crate) fn push(&mut self, item: T) -> Option<T> {
+        if !self.is_full() {
+            self.items.push_back(item);
+            return None;
+        }
+
+        match self.overflow_policy {
+            OverflowPolicy::DropOldest => {
+                let oldest_item = self.items.pop_front();
+                self.items.push_back(item);
+                oldest_item
+            }
+            OverflowPolicy::Block | OverflowPolicy::DropNewest | OverflowPolicy::Reject => {
+                Some(item)
+            }
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::BoundedQueue;
+    use crate::queue::OverflowPolicy;
+
+    fn filled_queue(overflow_policy: OverflowPolicy) -> BoundedQueue<u32> {
+        let mut queue = BoundedQueue::new(2, overflow_policy);
+        assert_eq!(None, queue.push(1));
+        assert!(!queue.is_full());
+        assert_eq!(None, queue.push(2));
+        assert!(queue.is_full());
+        queue
+    }
+
+    fn drain(queue: &mut BoundedQueue<u32>) -> Vec<u32> {
+        let items = std::iter::from_fn(|| queue.pop()).collect();
+        assert!(queue.is_empty());
+        items
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let mut queue = filled_queue(OverflowPolicy::DropOldest);
+        assert_eq!(Some(1), queue.push(3));
+        assert_eq!(vec![2, 3], drain(&mut queue));
+    }
+
+    #[test]
+    fn drop_newest_and_reject() {
+        for overflow_policy in [OverflowPolicy::DropNewest, OverflowPolicy::Reject] {
+            let mut queue = filled_queue(overflow_policy);
+            assert_eq!(Some(3), queue.push(3));
+            assert_eq!(vec![1, 2], drain(&mut queue));
+        }
+    }
+
+    #[test]
+    fn block() {
+        let mut queue = filled_queue(OverflowPolicy::Block);
+        assert_eq!(Some(3), queue.push(3));
+
+        assert_eq!(Some(1), queue.pop());
+        assert!(!queue.is_full());
+        assert_eq!(None, queue.push(3));
+        assert_eq!(vec![2, 3], drain(&mut queue));
+    }
+
+    #[test]
+    fn overflow_policy_parse() {
+        for overflow_policy in [
+            OverflowPolicy::Block,
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::Reject,
+        ] {
+            assert_eq!(Ok(overflow_policy), overflow_policy.to_string().parse());
+        }
+        assert!("drop".parse::<OverflowPolicy>().is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+use zeromq_messages::kind::ZeromqMessageKind;
+
+/// Routing identity of socket connected to BUS router socket.
+pub(crate) type Identity = Vec<u8>;
+
+//-----------------------------------------------------------------------------------------
+// ServiceRegistry
+//-----------------------------------------------------------------------------------------
+
+/// Alive instance of service as it registered itself on BUS.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct ServiceInstance {
+    pub(crate) name: String,
+    pub(crate) instance_id: String,
+    pub(crate) kinds: Vec<ZeromqMessageKind>,
+    last_seen_time: Instant,
+}
+
+#[derive(Debug, Default)]
+struct ServiceInstances {
+    identities: Vec<Identity>,
+    next_index: usize,
+}
+
+/// Services which registered on BUS, used to deliver directed messages and to answer
+/// registry queries. Instances which are not heard from for too long are expired.
+#[derive(Debug, Default)]
+pub(crate) struct ServiceRegistry {
+    instances: HashMap<Identity, ServiceInstance>,
+    services: HashMap<String, ServiceInstances>,
+    /// Identity of instance by its id, the latest registration wins if ids repeat.
+    instance_identities: HashMap<String, Identity>,
+}
+
+impl ServiceRegistry {
+    /// Adds instance of service or replaces registration of already known one, returns
+    /// `false` in the latter case.
+    pub(crate) fn register(
+        &mut self,
+        identity: &[u8],
+        name: &str,
+        instance_id: &str,
+        kinds: Vec<ZeromqMessageKind>,
+        now: Instant,
+    ) -> bool {
+        let is_new = self.unregister(identity).is_none();
+
+        let _ = self.instances.insert(
+            identity.to_vec(),
+            ServiceInstance {
+                name: name.to_owned(),
+                instance_id: instance_id.to_owned(),
+                kinds,
+                last_seen_time: now,
+            },
+        );
+        self.services
+            .entry(name.to_owned())
+            .or_default()
+            .identities
+            .push(identity.to_vec());
+        let _ = self
+            .instance_identities
+            .insert(instance_id.to_owned(), identity.to_vec());
+
+        is_new
+    }
+
+    /// Removes instance, returns its registration if it was known.
+    pub(crate) fn unregister(&mut self, identity: &[u8]) -> Option<ServiceInstance> {
+        let instance = self.instances.remove(identity)?;
+
+        if let Some(instances) = self.services.get_mut(&instance.name) {
+            instances.identities.retain(|known| known != identity);
+            if instances.identities.is_empty() {
+                let _ = self.services.remove(&instance.name);
+            }
+        }
+
+        if self
+            .instance_identities
+            .get(&instance.instance_id)
+            .map(Vec::as_slice)
+            == Some(identity)
+        {
+            let _ = self.instance_identities.remove(&instance.instance_id);
+        }
+
+        Some(instance)
+    }
+
+    pub(crate) fn contains(&self, identity: &[u8]) -> bool {
+        self.instances.contains_key(identity)
+    }
+
+    /// Marks instance as alive, returns `false` if it is not registered.
+    pub(crate) fn touch(&mut self, identity: &[u8], now: Instant) -> bool {
+        match self.instances.get_mut(identity) {
+            Some(instance) => {
+                instance.last_seen_time = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes instances which were not seen within timeout, returns their identities.
+    pub(crate) fn expire(&mut self, now: Instant, timeout: Duration) -> Vec<Identity> {
+        let expired_identities: Vec<Identity> = self
+            .instances
+            .iter()
+            .filter(|(_, instance)| now.duration_since(instance.last_seen_time) > timeout)
+            .map(|(identity, _)| identity.clone())
+            .collect();
+
+        for identity in &expired_identities {
+            let _ = self.unregister(identity);
+        }
+
+        expired_identities
+    }
+
+    /// Returns instances sorted by name and instance id, only of those which handle given
+    /// kind if it is set.
+    pub(crate) fn instances(&self, kind: Option<ZeromqMessageKind>) -> Vec<&ServiceInstance> {
+        let mut instances: Vec<&ServiceInstance> = self
+            .instances
+            .values()
+            .filter(|instance| kind.is_none_or(|kind| instance.kinds.contains(&kind)))
+            .collect();
+        instances.sort_by(|left, right| {
+            (&left.name, &left.instance_id).cmp(&(&right.name, &right.instance_id))
+        });
+
+        instances
+    }
+
+    /// Returns instances of service starting from the one whose turn it is, so messages
+    /// are spread between instances evenly, and passes the turn to the next instance.
+    /// Destination which is not a service name is looked up as instance id.
+    pub(crate) fn instances_in_turn(&mut self, destination: &str) -> Vec<Identity> {
+        let Some(instances) = self.services.get_mut(destination) else {
+            return self
+                .instance_identities
+                .get(destination)
+                .cloned()
+                .into_iter()
+                .collect();
+        };
+
+        let start_index = instances.next_index % instances.identities.len();
+        instances.next_index = start_index + 1;
+
+        let (head, tail) = instances.identities.split_at(start_index);
+        tail.iter().chain(head).cloned().collect()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::ServiceRegistry;
+    use std::time::Duration;
+    use std::time::Instant;
+    use zeromq_messages::kind::ZeromqMessageKind;
+
+    const KIND: ZeromqMessageKind = ZeromqMessageKind::ValueMultiplicationRequest;
+
+    fn register(registry: &mut ServiceRegistry, identity: &[u8], name: &str) -> bool {
+        registry.register(identity, name, "instance", vec![KIND], Instant::now())
+    }
+
+    #[test]
+    fn instances_in_turn() {
+        let mut registry = ServiceRegistry::default();
+        assert!(register(&mut registry, b"a", "multiplier"));
+        assert!(register(&mut registry, b"b", "multiplier"));
+        assert!(!register(&mut registry, b"a", "multiplier"));
+
+        assert_eq!(
+            vec![b"b".to_vec(), b"a".to_vec()],
+            registry.instances_in_turn("multiplier")
+        );
+        assert_eq!(
+            vec![b"a".to_vec(), b"b".to_vec()],
+            registry.instances_in_turn("multiplier")
+        );
+        assert_eq!(
+            vec![b"b".to_vec(), b"a".to_vec()],
+            registry.instances_in_turn("multiplier")
+        );
+        assert!(registry.instances_in_turn("divider").is_empty());
+    }
+
+    #[test]
+    fn unregister() {
+        let mut registry = ServiceRegistry::default();
+        assert!(register(&mut registry, b"a", "multiplier"));
+        assert!(register(&mut registry, b"b", "multiplier"));
+        assert!(register(&mut registry, b"c", "divider"));
+
+        assert_eq!("multiplier", registry.unregister(b"b").unwrap().name);
+        assert_eq!(
+            vec![b"a".to_vec()],
+            registry.instances_in_turn("multiplier")
+        );
+
+        assert!(registry.unregister(b"b").is_none());
+        let _ = registry.unregister(b"a");
+        assert!(registry.instances_in_turn("multiplier").is_empty());
+        assert_eq!(vec![b"c".to_vec()], registry.instances_in_turn("divider"));
+    }
+
+    #[test]
+    fn reregister_under_other_name() {
+        let mut registry = ServiceRegistry::default();
+        assert!(register(&mut registry, b"a", "multiplier"));
+        assert!(!register(&mut registry, b"a", "divider"));
+
+        assert!(registry.instances_in_turn("multiplier").is_empty());
+        assert_eq!(vec![b"a".to_vec()], registry.instances_in_turn("divider"));
+    }
+
+    #[test]
+    fn instance_id_destination() {
+        let mut registry = ServiceRegistry::default();
+        let now = Instant::now();
+        assert!(registry.register(b"a", "multiplier", "multiplier-0", vec![KIND], now));
+        assert!(registry.register(b"b", "multiplier", "multiplier-1", vec![KIND], now));
+
+        assert_eq!(
+            vec![b"b".to_vec()],
+            registry.instances_in_turn("multiplier-1")
+        );
+        assert_eq!(
+            vec![b"b".to_vec()],
+            registry.instances_in_turn("multiplier-1")
+        );
+
+        let _ = registry.unregister(b"b");
+        assert!(registry.instances_in_turn("multiplier-1").is_empty());
+        assert_eq!(
+            vec![b"a".to_vec()],
+            registry.instances_in_turn("multiplier-0")
+        );
+    }
+
+    #[test]
+    fn expire() {
+        let mut registry = ServiceRegistry::default();
+        let start_time = Instant::now();
+        let timeout = Duration::from_secs(3_u64);
+        assert!(registry.register(b"a", "multiplier", "a", vec![KIND], start_time));
+        assert!(registry.register(b"b", "multiplier", "b", vec![KIND], start_time));
+
+        assert!(registry.touch(b"b", start_time + Duration::from_secs(2_u64)));
+        assert!(!registry.touch(b"c", start_time));
+
+        assert_eq!(
+            vec![b"a".to_vec()],
+            registry.expire(start_time + Duration::from_secs(4_u64), timeout)
+        );
+        assert!(registry
+            .expire(start_time + Duration::from_secs(5_u64), timeout)
+            .is_empty());
+        assert_eq!(
+            vec![b"b".to_vec()],
+            registry.instances_in_turn("multiplier")
+        );
+    }
+
+    #[test]
+    fn instances() {
+        let mut registry = ServiceRegistry::default();
+        let now = Instant::now();
+        assert!(registry.register(b"b", "multiplier", "2", vec![KIND], now));
+        assert!(registry.register(b"a", "multiplier", "1", vec![KIND], now));
+        assert!(registry.register(b"c", "logger", "3", Vec::new(), now));
+
+        let instance_ids = |kind| {
+            registry
+                .instances(kind)
+                .into_iter()
+                .map(|instance| instance.instance_id.as_str())
+                .collect::<Vec<&str>>()
+        };
+        assert_eq!(vec!["3", "1", "2"], instance_ids(None));
+        assert_eq!(vec!["1", "2"], instance_ids(Some(KIND)));
+        assert!(instance_ids(Some(ZeromqMessageKind::BusHeartbeat)).is_empty());
+    }
+}
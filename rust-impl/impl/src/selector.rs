@@ -0,0 +1,444 @@
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Points of every publisher on hash ring, more points spread messages more evenly.
+const VIRTUAL_NODES_PER_PUBLISHER: u64 = 64;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error(
+    "Unsupported publisher selection {0}, expected one of round-robin, least-recently-used (lru), least-queued, consistent-hash"
+)]
+pub struct PublisherSelectionParseError(String);
+
+//-----------------------------------------------------------------------------------------
+// PublisherSelection
+//-----------------------------------------------------------------------------------------
+
+/// How BUS chooses publisher socket for every published message.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PublisherSelection {
+    /// Publishers take turns.
+    #[default]
+    RoundRobin,
+    /// Publisher which was idle for the longest time.
+    LeastRecentlyUsed,
+    /// Publisher with the fewest messages waiting for resend after failure.
+    LeastQueued,
+    /// Publisher chosen by uuid of message, so the same message always goes through the
+    /// same publisher and adding publisher moves only a small share of messages.
+    ConsistentHash,
+}
+
+impl PublisherSelection {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::RoundRobin => "round-robin",
+            Self::LeastRecentlyUsed => "least-recently-used",
+            Self::LeastQueued => "least-queued",
+            Self::ConsistentHash => "consistent-hash",
+        }
+    }
+
+    /// Creates selector implementing this strategy.
+    #[must_use]
+    pub fn selector(self) -> Box<dyn PublisherSelector> {
+        match self {
+            Self::RoundRobin => Box::new(RoundRobinSelector::default()),
+            Self::LeastRecentlyUsed => Box::new(LeastRecentlyUsedSelector),
+            Self::LeastQueued => Box::new(LeastQueuedSelector),
+            Self::ConsistentHash => Box::new(ConsistentHashSelector::default()),
+        }
+    }
+}
+
+impl fmt::Display for PublisherSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PublisherSelection {
+    type Err = PublisherSelectionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "least-recently-used" | "lru" => Ok(Self::LeastRecentlyUsed),
+            "least-queued" => Ok(Self::LeastQueued),
+            "consistent-hash" => Ok(Self::ConsistentHash),
+            _ => Err(PublisherSelectionParseError(s.to_owned())),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// PublisherLoad
+//-----------------------------------------------------------------------------------------
+
+/// What BUS knows about usage of one publisher socket.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct PublisherLoad {
+    /// When the last message was sent through publisher, `None` if nothing was sent yet.
+    pub last_sent_time: Option<Instant>,
+    /// Count of messages sent through publisher.
+    pub sent_count: u64,
+    /// Count of messages which failed to be sent through publisher and wait for resend.
+    pub queued_count: usize,
+}
+
+impl PublisherLoad {
+    pub fn record_sent(&mut self, now: Instant) {
+        self.last_sent_time = Some(now);
+        self.sent_count += 1;
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// PublisherSelector
+//-----------------------------------------------------------------------------------------
+
+/// Strategy which chooses publisher socket for message.
+pub trait PublisherSelector: fmt::Debug + Send {
+    /// Returns index of publisher through which message with given uuid is sent, one of
+    /// `subscribed`. `publishers` is never empty and keeps the same order between calls,
+    /// `subscribed` holds ascending indexes of publishers which have subscribers of message
+    /// kind, it is never empty and may change between calls.
+    fn select(
+        &mut self,
+        uuid: Uuid,
+        publishers: &[PublisherLoad],
+        subscribed: &[usize],
+    ) -> usize;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RoundRobinSelector {
+    next_index: usize,
+}
+
+impl PublisherSelector for RoundRobinSelector {
+    fn select(
+        &mut self,
+        _uuid: Uuid,
+        _publishers: &[PublisherLoad],
+        subscribed: &[usize],
+    ) -> usize {
+        // Cursor walks over all publishers, unsubscribed ones are skipped without moving
+        // turns of the rest.
+        let index = subscribed
+            .iter()
+            .copied()
+            .find(|index| *index >= self.next_index)
+            .unwrap_or(subscribed[0]);
+        self.next_index = index + 1;
+        index
+    }
+}
+
+/// Chooses publisher which was not used for the longest time, publishers which were never
+/// used go first.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LeastRecentlyUsedSelector;
+
+impl PublisherSelector for LeastRecentlyUsedSelector {
+    fn select(
+        &mut self,
+        _uuid: Uuid,
+        publishers: &[PublisherLoad],
+        subscribed: &[usize],
+    ) -> usize {
+        // `None` is ordered before any time and ties are won by the lowest index.
+        subscribed
+            .iter()
+            .copied()
+            .min_by_key(|index| publishers[*index].last_sent_time)
+            .unwrap_or(subscribed[0])
+    }
+}
+
+/// Chooses publisher with the fewest messages waiting for resend, then the one which sent
+/// the fewest messages.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LeastQueuedSelector;
+
+impl PublisherSelector for LeastQueuedSelector {
+    fn select(
+        &mut self,
+        _uuid: Uuid,
+        publishers: &[PublisherLoad],
+        subscribed: &[usize],
+    ) -> usize {
+        subscribed
+            .iter()
+            .copied()
+            .min_by_key(|index| {
+                let load = &publishers[*index];
+                (load.queued_count, load.sent_count)
+            })
+            .unwrap_or(subscribed[0])
+    }
+}
+
+/// Places virtual nodes of every publisher on hash ring and chooses subscribed publisher
+/// owning the first node at or after hash of message uuid. Ring is built over all
+/// publishers, so uuid keeps its publisher while that publisher stays subscribed.
+#[derive(Debug, Default, Clone)]
+pub struct ConsistentHashSelector {
+    /// Sorted hashes of virtual nodes with indexes of their publishers.
+    ring: Vec<(u64, usize)>,
+    publishers_count: usize,
+}
+
+impl ConsistentHashSelector {
+    fn build_ring(&mut self, publishers_count: usize) {
+        self.ring.clear();
+        for index in 0..publishers_count {
+            for node in 0..VIRTUAL_NODES_PER_PUBLISHER {
+                let index_u64 = index as u64;
+                self.ring.push((mix(index_u64 << 32 | node), index));
+            }
+        }
+        self.ring.sort_unstable();
+        self.publishers_count = publishers_count;
+    }
+}
+
+impl PublisherSelector for ConsistentHashSelector {
+    fn select(
+        &mut self,
+        uuid: Uuid,
+        publishers: &[PublisherLoad],
+        subscribed: &[usize],
+    ) -> usize {
+        if self.publishers_count != publishers.len() {
+            self.build_ring(publishers.len());
+        }
+
+        let uuid_bits = uuid.as_u128();
+        #[allow(clippy::cast_possible_truncation)]
+        let hash = mix((uuid_bits >> 64) as u64 ^ uuid_bits as u64);
+
+        let position = self
+            .ring
+            .partition_point(|(node_hash, _)| *node_hash < hash);
+        let (wrapped_nodes, following_nodes) = self.ring.split_at(position);
+        following_nodes
+            .iter()
+            .chain(wrapped_nodes)
+            .map(|(_, index)| *index)
+            .find(|index| subscribed.binary_search(index).is_ok())
+            .unwrap_or(subscribed[0])
+    }
+}
+
+/// Finalizer of splitmix64, spreads close inputs over the whole range. Unlike standard
+/// hasher it is stable between Rust releases, so messages keep their publishers.
+fn mix(value: u64) -> u64 {
+    let mut value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::selector::ConsistentHashSelector;
+    use crate::selector::PublisherLoad;
+    use crate::selector::PublisherSelection;
+    use crate::selector::PublisherSelector;
+    use std::time::Duration;
+    use std::time::Instant;
+    use uuid::Uuid;
+
+    fn all(publishers: &[PublisherLoad]) -> Vec<usize> {
+        (0..publishers.len()).collect()
+    }
+
+    /// Selects publishers for `count` messages sent a millisecond apart from `start_time`,
+    /// recording every send, and returns count of messages sent through every publisher.
+    fn distribute(
+        selector: &mut dyn PublisherSelector,
+        publishers: &mut [PublisherLoad],
+        start_time: Instant,
+        count: usize,
+    ) -> Vec<u64> {
+        for offset in 0..count {
+            let index = selector.select(Uuid::new_v4(), publishers, &all(publishers));
+            publishers[index].record_sent(start_time + Duration::from_millis(offset as u64));
+        }
+        publishers.iter().map(|load| load.sent_count).collect()
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut selector = PublisherSelection::RoundRobin.selector();
+        let mut publishers = vec![PublisherLoad::default(); 3];
+
+        let order: Vec<usize> = (0..5)
+            .map(|_| selector.select(Uuid::new_v4(), &publishers, &all(&publishers)))
+            .collect();
+        assert_eq!(vec![0, 1, 2, 0, 1], order);
+
+        assert_eq!(
+            vec![333, 333, 334],
+            distribute(&mut *selector, &mut publishers, Instant::now(), 1000)
+        );
+    }
+
+    #[test]
+    fn round_robin_over_subscribed() {
+        let mut selector = PublisherSelection::RoundRobin.selector();
+        let publishers = vec![PublisherLoad::default(); 4];
+
+        // Unsubscribed publishers are skipped, the rest keep their turns.
+        let order: Vec<usize> = [&[0, 1, 2, 3][..], &[0, 2], &[0, 1, 2, 3], &[1, 3], &[0, 3]]
+            .iter()
+            .map(|subscribed| selector.select(Uuid::new_v4(), &publishers, subscribed))
+            .collect();
+        assert_eq!(vec![0, 2, 3, 1, 3], order);
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let mut selector = PublisherSelection::LeastRecentlyUsed.selector();
+        let now = Instant::now();
+        let mut publishers = vec![PublisherLoad::default(); 3];
+        publishers[0].record_sent(now);
+        publishers[2].record_sent(now + Duration::from_millis(1));
+
+        // Never used publisher goes first, then the one idle for the longest time.
+        assert_eq!(
+            1,
+            selector.select(Uuid::new_v4(), &publishers, &all(&publishers))
+        );
+        assert_eq!(0, selector.select(Uuid::new_v4(), &publishers, &[0, 2]));
+        publishers[1].record_sent(now + Duration::from_millis(2));
+        assert_eq!(
+            0,
+            selector.select(Uuid::new_v4(), &publishers, &all(&publishers))
+        );
+
+        assert_eq!(
+            vec![334, 334, 334],
+            distribute(
+                &mut *selector,
+                &mut publishers,
+                now + Duration::from_secs(1),
+                999
+            )
+        );
+    }
+
+    #[test]
+    fn least_queued() {
+        let mut selector = PublisherSelection::LeastQueued.selector();
+        let mut publishers = vec![PublisherLoad::default(); 3];
+        publishers[0].queued_count = 2;
+
+        // Publisher with queued messages is avoided while others are fine.
+        assert_eq!(
+            vec![0, 500, 500],
+            distribute(&mut *selector, &mut publishers, Instant::now(), 1000)
+        );
+
+        publishers[0].queued_count = 0;
+        assert_eq!(
+            vec![500, 500, 500],
+            distribute(&mut *selector, &mut publishers, Instant::now(), 500)
+        );
+    }
+
+    #[test]
+    fn consistent_hash() {
+        let mut selector = ConsistentHashSelector::default();
+        let four_publishers = vec![PublisherLoad::default(); 4];
+        let five_publishers = vec![PublisherLoad::default(); 5];
+        let uuids: Vec<Uuid> = (0..10_000).map(|_| Uuid::new_v4()).collect();
+
+        let before: Vec<usize> = uuids
+            .iter()
+            .map(|uuid| selector.select(*uuid, &four_publishers, &all(&four_publishers)))
+            .collect();
+
+        // The same uuid always goes through the same publisher.
+        assert!(uuids
+            .iter()
+            .zip(&before)
+            .all(|(uuid, index)| selector.select(
+                *uuid,
+                &four_publishers,
+                &all(&four_publishers)
+            ) == *index));
+
+        // Every publisher gets a fair share.
+        for index in 0..4 {
+            let count = before.iter().filter(|selected| **selected == index).count();
+            assert!((1500..3500).contains(&count), "{} got {}", index, count);
+        }
+
+        // Added publisher takes messages only from others, about a fifth of them.
+        let after: Vec<usize> = uuids
+            .iter()
+            .map(|uuid| selector.select(*uuid, &five_publishers, &all(&five_publishers)))
+            .collect();
+        let moved: Vec<usize> = before
+            .iter()
+            .zip(&after)
+            .filter(|(before, after)| before != after)
+            .map(|(_, after)| *after)
+            .collect();
+        assert!(moved.iter().all(|index| *index == 4));
+        assert!((1000..3000).contains(&moved.len()), "moved {}", moved.len());
+
+        // Unsubscribed publisher hands its messages to others, the rest keep theirs.
+        let subscribed = [0, 1, 3];
+        let without_third: Vec<usize> = uuids
+            .iter()
+            .map(|uuid| selector.select(*uuid, &four_publishers, &subscribed))
+            .collect();
+        for (before, without_third) in before.iter().zip(&without_third) {
+            assert!(subscribed.contains(without_third));
+            if *before != 2 {
+                assert_eq!(before, without_third);
+            }
+        }
+        assert!(uuids
+            .iter()
+            .zip(&before)
+            .all(|(uuid, index)| selector.select(
+                *uuid,
+                &four_publishers,
+                &all(&four_publishers)
+            ) == *index));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Ok(PublisherSelection::LeastRecentlyUsed),
+            "lru".parse::<PublisherSelection>()
+        );
+        for selection in &[
+            PublisherSelection::RoundRobin,
+            PublisherSelection::LeastRecentlyUsed,
+            PublisherSelection::LeastQueued,
+            PublisherSelection::ConsistentHash,
+        ] {
+            assert_eq!(Ok(*selection), selection.as_str().parse());
+        }
+        assert!("random".parse::<PublisherSelection>().is_err());
+    }
+}
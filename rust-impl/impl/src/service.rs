@@ -0,0 +1,487 @@
+use crate::config::BusConfig;
+use crate::curve::CurveClient;
+use crate::curve::CurveError;
+use crate::endpoint::join_endpoints;
+use crate::metrics::Counter;
+use crate::metrics::Histogram;
+use crate::metrics::Metrics;
+use crate::metrics::DEFAULT_LATENCY_BUCKETS;
+use crate::shutdown::set_linger;
+use crate::shutdown::wait_any_readable;
+use crate::shutdown::Shutdown;
+use crate::topic::recv_published_message;
+use crate::topic::subscribe_to_kinds;
+use crate::PAYLOAD_FORMAT;
+use crate::ZEROMQ_ZERO_FLAG;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+use std::time::SystemTime;
+use uuid::Uuid;
+use zeromq_messages::codec::encode_message_with_format;
+use zeromq_messages::codec::MessageDecodeError;
+use zeromq_messages::codec::MessageEncodeError;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::BusHeartbeat;
+use zeromq_messages::messages::BusServiceRegistration;
+use zeromq_messages::messages::BusWorkerReady;
+use zeromq_messages::template::ZeromqMessageTrait;
+use zeromq_messages::view::MessageView;
+use zmq::Context;
+use zmq::Message;
+use zmq::Socket;
+use zmq::SocketType;
+
+type Handler = Box<dyn FnMut(MessageView<'_>) -> Result<Vec<u8>, BusServiceError> + Send>;
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum BusServiceError {
+    #[error("Failed to decode request")]
+    CantDecodeRequest(#[from] MessageDecodeError),
+
+    #[error("Failed to encode response")]
+    CantEncodeResponse(#[from] MessageEncodeError),
+
+    #[error("ZeroMQ operation failed")]
+    Zmq(#[from] zmq::Error),
+
+    #[error("Failed to set up CURVE security")]
+    Curve(#[from] CurveError),
+
+    #[cfg(feature = "tokio")]
+    #[error("BUS client failed")]
+    Client(#[from] crate::client::BusClientError),
+}
+
+//-----------------------------------------------------------------------------------------
+// ServiceMetrics
+//-----------------------------------------------------------------------------------------
+
+/// Metrics of requests which service handles.
+#[derive(Debug)]
+pub(crate) struct ServiceMetrics {
+    registry: Metrics,
+    pub(crate) processed: Arc<Counter>,
+    pub(crate) failed: Arc<Counter>,
+    pub(crate) handling_duration: Arc<Histogram>,
+}
+
+impl ServiceMetrics {
+    pub(crate) fn new(registry: &Metrics) -> Self {
+        Self {
+            registry: registry.clone(),
+            processed: registry.counter(
+                "service_processed_requests_total",
+                "Requests handled by service and answered.",
+                &[],
+            ),
+            failed: registry.counter(
+                "service_failed_requests_total",
+                "Requests which service failed to handle or answer.",
+                &[],
+            ),
+            handling_duration: registry.histogram(
+                "service_request_handling_duration_seconds",
+                "Time spent handling request and sending response.",
+                &[],
+                DEFAULT_LATENCY_BUCKETS,
+            ),
+        }
+    }
+
+    pub(crate) fn count_decode_error(&self, error: &MessageDecodeError) {
+        self.registry
+            .counter(
+                "service_decode_errors_total",
+                "Messages which service failed to decode.",
+                &[("error", error.variant_name())],
+            )
+            .inc();
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// BusService
+//-----------------------------------------------------------------------------------------
+
+/// Service which receives requests published, directed or dispatched to it by BUS and answers them
+/// with registered handlers. Responses are sent back with uuid of the request.
+#[derive(Default)]
+pub struct BusService {
+    name: Option<String>,
+    instance_id: Option<String>,
+    handlers: HashMap<ZeromqMessageKind, Handler>,
+    metrics: Metrics,
+}
+
+impl BusService {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers handler for requests of `Req` kind.
+    #[must_use]
+    pub fn on<Req, Resp>(
+        mut self,
+        mut handler: impl FnMut(Req) -> Resp + Send + 'static,
+    ) -> Self
+    where
+        Req: for<'de> ZeromqMessageTrait<'de>,
+        Resp: for<'de> ZeromqMessageTrait<'de>,
+    {
+        let kind = <Req as ZeromqMessageTrait<'_>>::kind();
+        let previous_handler = self.handlers.insert(
+            kind,
+            Box::new(move |message_view| {
+                let request = message_view.decode_payload::<Req>()?;
+                let response = handler(request);
+                Ok(encode_message_with_format(
+                    message_view.uuid(),
+                    response,
+                    PAYLOAD_FORMAT,
+                )?)
+            }),
+        );
+
+        assert!(
+            previous_handler.is_none(),
+            "handler for {:?} is already registered",
+            kind
+        );
+
+        self
+    }
+
+    /// Registers service on BUS under given name, so besides published requests it
+    /// receives requests directed to this name. Service without name is registered under
+    /// its instance id.
+    #[must_use]
+    pub fn named(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Sets id which distinguishes this instance in BUS registry, random uuid is used
+    /// by default.
+    #[must_use]
+    pub fn with_instance_id(mut self, instance_id: &str) -> Self {
+        self.instance_id = Some(instance_id.to_owned());
+        self
+    }
+
+    /// Records metrics of handled requests in given registry instead of a private one.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: &Metrics) -> Self {
+        self.metrics = metrics.clone();
+        self
+    }
+
+    /// Connects to BUS and processes requests until shutdown is requested.
+    pub fn run(
+        mut self,
+        context: &Context,
+        config: &BusConfig,
+        shutdown: &Shutdown,
+    ) -> Result<(), BusServiceError> {
+        let router_endpoint = config.router_connect_endpoint();
+        let publisher_endpoints = config.publisher_connect_endpoints();
+
+        let curve_client = CurveClient::from_config(config)?;
+
+        let sender = context.socket(SocketType::DEALER)?;
+        set_linger(&sender)?;
+        if let Some(curve_client) = &curve_client {
+            curve_client.apply(&sender)?;
+        }
+        router_endpoint.connect(&sender)?;
+
+        log::debug!(
+            "sender has connected to BUS router socket {}",
+            router_endpoint
+        );
+
+        // Requests of queued kinds are dispatched by BUS to sender socket of one worker, so
+        // service takes them as a worker instead of subscribing to them.
+        let (queued_kinds, published_kinds): (Vec<_>, Vec<_>) = self
+            .kinds()
+            .into_iter()
+            .partition(|kind| config.queue_kinds.contains(kind));
+
+        let instance_id = self
+            .instance_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        self.register(&sender, &instance_id, &queued_kinds, config)?;
+
+        let receiver = context.socket(SocketType::SUB)?;
+        if let Some(curve_client) = &curve_client {
+            curve_client.apply(&receiver)?;
+        }
+        for publisher_endpoint in &publisher_endpoints {
+            publisher_endpoint.connect(&receiver)?;
+        }
+        subscribe_to_kinds(&receiver, &published_kinds)?;
+
+        log::debug!(
+            "receiver has connected to all BUS publishers: {}",
+            join_endpoints(&publisher_endpoints)
+        );
+
+        let metrics = ServiceMetrics::new(&self.metrics);
+        let mut total_processed_messages_count: usize = 0;
+        let mut message = Message::new();
+        let mut heartbeat_uuid = Uuid::nil();
+        let mut next_heartbeat_time = Instant::now() + config.heartbeat_interval();
+
+        'messages_processing: while !shutdown.is_requested() {
+            if Instant::now() >= next_heartbeat_time {
+                next_heartbeat_time = Instant::now() + config.heartbeat_interval();
+                heartbeat_uuid = Uuid::new_v4();
+                if let Err(error) = send_heartbeat(&sender, heartbeat_uuid) {
+                    log::error!("failed to send heartbeat because of: {}", error);
+                }
+            }
+
+            let mut poll_items = [
+                receiver.as_poll_item(zmq::POLLIN),
+                sender.as_poll_item(zmq::POLLIN),
+            ];
+
+            match wait_any_readable(&mut poll_items) {
+                Ok(true) => {}
+                Ok(false) => continue 'messages_processing,
+                Err(error) => {
+                    log::error!("failed to poll sockets because of: {}", error);
+                    continue 'messages_processing;
+                }
+            }
+
+            if poll_items[0].is_readable() {
+                match recv_published_message(&receiver, &mut message, zmq::DONTWAIT) {
+                    Ok(()) if self.process(&message, &sender, &metrics) => {
+                        count_processed(&mut total_processed_messages_count, config);
+                    }
+                    Ok(()) => {}
+                    Err(error) => {
+                        log::error!("failed to receive message because of: {}", error);
+                    }
+                }
+            }
+
+            // BUS sends directed and queued requests, acknowledgements and rejections to
+            // sender socket. Rejected heartbeat means that BUS has forgotten the service,
+            // e.g. because it was restarted, so service registers again.
+            if poll_items[1].is_readable() {
+                match sender.recv(&mut message, zmq::DONTWAIT) {
+                    Ok(()) if is_rejection_of(&message, heartbeat_uuid) => {
+                        log::debug!("heartbeat rejected, registering on BUS again");
+                        if let Err(error) =
+                            self.register(&sender, &instance_id, &queued_kinds, config)
+                        {
+                            log::error!("failed to register on BUS because of: {}", error);
+                        }
+                    }
+                    Ok(()) if self.process(&message, &sender, &metrics) => {
+                        count_processed(&mut total_processed_messages_count, config);
+                    }
+                    Ok(()) => {}
+                    Err(error) => {
+                        log::error!("failed to receive message because of: {}", error);
+                    }
+                }
+            }
+        }
+
+        log::info!(
+            "service stopped: total processed {} messages",
+            total_processed_messages_count
+        );
+
+        Ok(())
+    }
+
+    /// Announces service instance with kinds it handles to BUS, and takes queued kinds as a
+    /// worker.
+    fn register(
+        &self,
+        sender: &Socket,
+        instance_id: &str,
+        queued_kinds: &[ZeromqMessageKind],
+        config: &BusConfig,
+    ) -> Result<(), BusServiceError> {
+        let name = self.name.as_deref().unwrap_or(instance_id);
+        let registration_bytes = encode_message_with_format(
+            Uuid::new_v4(),
+            BusServiceRegistration {
+                name: name.to_owned(),
+                instance_id: instance_id.to_owned(),
+                kinds: self.kinds().iter().map(|kind| kind.number()).collect(),
+            },
+            PAYLOAD_FORMAT,
+        )?;
+        sender.send(registration_bytes, ZEROMQ_ZERO_FLAG)?;
+
+        log::debug!("instance {} registered on BUS as {}", instance_id, name);
+
+        if !queued_kinds.is_empty() {
+            let worker_ready_bytes = encode_message_with_format(
+                Uuid::new_v4(),
+                BusWorkerReady {
+                    kinds: queued_kinds.iter().map(|kind| kind.number()).collect(),
+                    credit: i64::try_from(config.worker_credit).unwrap_or(i64::MAX),
+                },
+                PAYLOAD_FORMAT,
+            )?;
+            sender.send(worker_ready_bytes, ZEROMQ_ZERO_FLAG)?;
+
+            log::debug!("service is ready to take {:?} as a worker", queued_kinds);
+        }
+
+        Ok(())
+    }
+
+    /// Returns kinds of requests for which handlers are registered.
+    #[must_use]
+    pub fn kinds(&self) -> Vec<ZeromqMessageKind> {
+        self.handlers.keys().copied().collect()
+    }
+
+    /// Handles request and sends response to BUS, returns `false` if message was not a
+    /// request which service handles.
+    fn process(
+        &mut self,
+        message: &Message,
+        sender: &Socket,
+        metrics: &ServiceMetrics,
+    ) -> bool {
+        log::trace!("< {:?}", &**message);
+
+        let message_view = match MessageView::new(message) {
+            Ok(message_view) => message_view,
+            Err(error) => {
+                log::error!("failed to decode message header because of: {}", error);
+                metrics.count_decode_error(&error);
+                return false;
+            }
+        };
+
+        let Some(handler) = self.handlers.get_mut(&message_view.kind()) else {
+            log::trace!(
+                "ignored message with unexpected kind {:?}",
+                message_view.kind()
+            );
+            return false;
+        };
+
+        let start_time = Instant::now();
+        let response_message_bytes = match handler(message_view) {
+            Ok(response_message_bytes) => response_message_bytes,
+            Err(error) => {
+                log::error!(
+                    "failed to handle {:?} message because of: {}",
+                    message_view.kind(),
+                    error
+                );
+                if let BusServiceError::CantDecodeRequest(error) = &error {
+                    metrics.count_decode_error(error);
+                }
+                metrics.failed.inc();
+                return false;
+            }
+        };
+
+        log::trace!("> {:?}", response_message_bytes);
+
+        if let Err(error) = sender.send(response_message_bytes, ZEROMQ_ZERO_FLAG) {
+            log::error!("failed to send message because of: {}", error);
+            metrics.failed.inc();
+            return false;
+        }
+
+        metrics.processed.inc();
+        metrics
+            .handling_duration
+            .observe_duration(start_time.elapsed());
+
+        true
+    }
+}
+
+impl fmt::Debug for BusService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BusService")
+            .field("name", &self.name)
+            .field("instance_id", &self.instance_id)
+            .field("kinds", &self.kinds())
+            .finish_non_exhaustive()
+    }
+}
+
+fn send_heartbeat(sender: &Socket, uuid: Uuid) -> Result<(), BusServiceError> {
+    let heartbeat_bytes = encode_message_with_format(uuid, BusHeartbeat {}, PAYLOAD_FORMAT)?;
+    sender.send(heartbeat_bytes, zmq::DONTWAIT)?;
+
+    Ok(())
+}
+
+fn is_rejection_of(message: &Message, uuid: Uuid) -> bool {
+    matches!(
+        MessageView::new(message),
+        Ok(message_view)
+            if message_view.kind() == ZeromqMessageKind::BusRejection
+                && message_view.uuid() == uuid
+    )
+}
+
+pub(crate) fn count_processed(total_processed_messages_count: &mut usize, config: &BusConfig) {
+    *total_processed_messages_count += 1;
+
+    if total_processed_messages_count.is_multiple_of(config.group_size) {
+        log::debug!(
+            "{:?} | total processed {} messages",
+            SystemTime::now(),
+            total_processed_messages_count
+        );
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::service::BusService;
+    use zeromq_messages::kind::ZeromqMessageKind;
+    use zeromq_messages::messages::ValueMultiplicationRequest;
+    use zeromq_messages::messages::ValueMultiplicationResponse;
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn multiply(request: ValueMultiplicationRequest) -> ValueMultiplicationResponse {
+        ValueMultiplicationResponse {
+            result: request.value * request.multiplier,
+        }
+    }
+
+    #[test]
+    fn kinds() {
+        assert!(BusService::new().kinds().is_empty());
+        assert_eq!(
+            vec![ZeromqMessageKind::ValueMultiplicationRequest],
+            BusService::new().on(multiply).kinds()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered")]
+    fn duplicate_handler() {
+        let _ = BusService::new().on(multiply).on(multiply);
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use zmq::PollItem;
+use zmq::Socket;
+
+/// How long closed sockets keep trying to deliver pending messages.
+pub const SOCKET_LINGER_DURATION: Duration = Duration::from_secs(1_u64);
+/// How often blocking loops wake up to check whether shutdown was requested.
+pub(crate) const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(100_u64);
+
+//-----------------------------------------------------------------------------------------
+// Errors
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug, thiserror::Error)]
+pub enum ShutdownError {
+    #[error("Failed to set SIGINT/SIGTERM handler")]
+    CantSetSignalHandler(#[from] ctrlc::Error),
+}
+
+//-----------------------------------------------------------------------------------------
+// Shutdown
+//-----------------------------------------------------------------------------------------
+
+/// Flag shared between threads which asks BUS, services and clients to stop after
+/// draining messages they already hold.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates flag which is raised on SIGINT or SIGTERM. Can be called once per process.
+    pub fn on_signals() -> Result<Self, ShutdownError> {
+        let shutdown = Self::new();
+        let shutdown_clone = shutdown.clone();
+        ctrlc::set_handler(move || {
+            log::debug!("[SYSTEM] shutdown signal received");
+            shutdown_clone.request();
+        })?;
+
+        Ok(shutdown)
+    }
+
+    pub fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    #[must_use]
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn set_linger(socket: &Socket) -> zmq::Result<()> {
+    socket.set_linger(SOCKET_LINGER_DURATION.as_millis() as i32)
+}
+
+/// Waits until any of sockets becomes readable, returns `false` if nothing arrived in time
+/// or waiting was interrupted by a signal. Readiness of each socket is left in its poll
+/// item.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn wait_any_readable(poll_items: &mut [PollItem<'_>]) -> zmq::Result<bool> {
+    match zmq::poll(poll_items, SHUTDOWN_CHECK_INTERVAL.as_millis() as i64) {
+        Ok(events_count) => Ok(events_count > 0),
+        Err(zmq::Error::EINTR) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
@@ -0,0 +1,147 @@
+use crate::topic::kind_topic;
+use std::collections::BTreeSet;
+use zeromq_messages::kind::ZeromqMessageKind;
+
+/// First byte of subscription event which XPUB socket receives from subscribers.
+const SUBSCRIBE_EVENT: u8 = 1;
+const UNSUBSCRIBE_EVENT: u8 = 0;
+
+//-----------------------------------------------------------------------------------------
+// SubscriptionTable
+//-----------------------------------------------------------------------------------------
+
+/// Topic prefixes to which subscribers of one publisher socket are subscribed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct PublisherSubscriptions {
+    pub(crate) endpoint: String,
+    topics: BTreeSet<Vec<u8>>,
+}
+
+impl PublisherSubscriptions {
+    fn is_subscribed(&self, kind: ZeromqMessageKind) -> bool {
+        let topic = kind_topic(kind);
+        self.topics.iter().any(|prefix| topic.starts_with(prefix))
+    }
+
+    /// Kinds of messages which reach at least one subscriber of this publisher.
+    pub(crate) fn kinds(&self) -> Vec<ZeromqMessageKind> {
+        ZeromqMessageKind::ALL
+            .iter()
+            .copied()
+            .filter(|kind| self.is_subscribed(*kind))
+            .collect()
+    }
+}
+
+/// Subscriptions of every BUS publisher socket, built from events which XPUB sockets
+/// deliver. XPUB passes only the first subscription to topic and the last unsubscription
+/// from it, so the table knows which topics have subscribers, but not how many.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct SubscriptionTable {
+    publishers: Vec<PublisherSubscriptions>,
+}
+
+impl SubscriptionTable {
+    pub(crate) fn new(endpoints: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            publishers: endpoints
+                .into_iter()
+                .map(|endpoint| PublisherSubscriptions {
+                    endpoint,
+                    topics: BTreeSet::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Applies subscription event received by publisher with given index, returns `false`
+    /// if event is malformed or changes nothing.
+    pub(crate) fn apply(&mut self, publisher_index: usize, event: &[u8]) -> bool {
+        let topics = &mut self.publishers[publisher_index].topics;
+
+        match event.split_first() {
+            Some((&SUBSCRIBE_EVENT, topic)) => topics.insert(topic.to_vec()),
+            Some((&UNSUBSCRIBE_EVENT, topic)) => topics.remove(topic),
+            _ => false,
+        }
+    }
+
+    /// Whether message of given kind reaches any subscriber through any publisher.
+    pub(crate) fn is_subscribed(&self, kind: ZeromqMessageKind) -> bool {
+        self.publishers
+            .iter()
+            .any(|publisher| publisher.is_subscribed(kind))
+    }
+
+    /// Indexes of publishers through which message of given kind reaches any subscriber.
+    pub(crate) fn subscribed_publishers(&self, kind: ZeromqMessageKind) -> Vec<usize> {
+        self.publishers
+            .iter()
+            .enumerate()
+            .filter(|(_, publisher)| publisher.is_subscribed(kind))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub(crate) fn publishers(&self) -> &[PublisherSubscriptions] {
+        &self.publishers
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::subscriptions::SubscriptionTable;
+    use crate::topic::kind_topic;
+    use zeromq_messages::kind::ZeromqMessageKind;
+
+    fn event(first_byte: u8, topic: &[u8]) -> Vec<u8> {
+        let mut event = vec![first_byte];
+        event.extend_from_slice(topic);
+        event
+    }
+
+    #[test]
+    fn subscriptions() {
+        let request_topic = kind_topic(ZeromqMessageKind::ValueMultiplicationRequest);
+        let mut table = SubscriptionTable::new(vec![
+            String::from("inproc://publisher-0"),
+            String::from("inproc://publisher-1"),
+        ]);
+        assert!(!table.is_subscribed(ZeromqMessageKind::ValueMultiplicationRequest));
+
+        assert!(table.apply(1, &event(1, &request_topic)));
+        assert!(!table.apply(1, &event(1, &request_topic)));
+        assert!(table.is_subscribed(ZeromqMessageKind::ValueMultiplicationRequest));
+        assert!(!table.is_subscribed(ZeromqMessageKind::ValueMultiplicationResponse));
+        assert_eq!(
+            vec![1],
+            table.subscribed_publishers(ZeromqMessageKind::ValueMultiplicationRequest)
+        );
+        assert!(table.publishers()[0].kinds().is_empty());
+        assert_eq!(
+            vec![ZeromqMessageKind::ValueMultiplicationRequest],
+            table.publishers()[1].kinds()
+        );
+
+        assert!(table.apply(1, &event(0, &request_topic)));
+        assert!(!table.is_subscribed(ZeromqMessageKind::ValueMultiplicationRequest));
+
+        // Empty prefix subscribes to every kind.
+        assert!(table.apply(0, &event(1, &[])));
+        assert_eq!(
+            ZeromqMessageKind::ALL.len(),
+            table.publishers()[0].kinds().len()
+        );
+        assert_eq!(
+            vec![0],
+            table.subscribed_publishers(ZeromqMessageKind::ValueMultiplicationRequest)
+        );
+
+        assert!(!table.apply(0, &[]));
+        assert!(!table.apply(0, &event(2, &request_topic)));
+    }
+}
@@ -0,0 +1,72 @@
+use zeromq_messages::kind::ZeromqMessageKind;
+use zmq::Message;
+use zmq::Socket;
+
+/// Length of topic prefix in bytes.
+pub const TOPIC_LENGTH: usize = 4;
+
+/// Returns topic which is used by BUS for publishing messages of given kind.
+#[must_use]
+pub fn kind_topic(kind: ZeromqMessageKind) -> [u8; TOPIC_LENGTH] {
+    (kind as u32).to_be_bytes()
+}
+
+/// Subscribes socket to messages with given kinds, so filtering happens on the publisher
+/// side and other messages never reach the subscriber.
+pub fn subscribe_to_kinds(socket: &Socket, kinds: &[ZeromqMessageKind]) -> zmq::Result<()> {
+    for kind in kinds {
+        socket.set_subscribe(&kind_topic(*kind))?;
+    }
+
+    Ok(())
+}
+
+/// Sends message bytes through publisher socket with topic frame derived from message kind.
+pub fn send_published_message(
+    socket: &Socket,
+    kind: ZeromqMessageKind,
+    message_bytes: &[u8],
+) -> zmq::Result<()> {
+    socket.send(&kind_topic(kind)[..], zmq::SNDMORE)?;
+    socket.send(message_bytes, 0)
+}
+
+/// Receives message published by BUS into given message, dropping topic frame. Message is
+/// reused between calls, so receiving does not allocate for every frame. Flags are applied
+/// to the first frame only, the rest of frames are always available once it arrives.
+pub fn recv_published_message(
+    socket: &Socket,
+    message: &mut Message,
+    flags: i32,
+) -> zmq::Result<()> {
+    socket.recv(message, flags)?;
+
+    // Message content is always the last frame, topic frame goes before it.
+    while message.get_more() {
+        socket.recv(message, 0)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::topic::kind_topic;
+    use zeromq_messages::kind::ZeromqMessageKind;
+
+    #[test]
+    fn topics_are_unique() {
+        assert_ne!(
+            kind_topic(ZeromqMessageKind::ValueMultiplicationRequest),
+            kind_topic(ZeromqMessageKind::ValueMultiplicationResponse)
+        );
+    }
+
+    #[test]
+    fn topic_matches_kind_value() {
+        assert_eq!(
+            [0, 0, 0, 1],
+            kind_topic(ZeromqMessageKind::ValueMultiplicationRequest)
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use crate::registry::Identity;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use uuid::Uuid;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zmq::Message;
+
+//-----------------------------------------------------------------------------------------
+// QueuedRequest
+//-----------------------------------------------------------------------------------------
+
+/// Request of queued kind which waits for worker or is processed by one.
+#[derive(Debug)]
+pub(crate) struct QueuedRequest {
+    pub(crate) requester: Identity,
+    pub(crate) uuid: Uuid,
+    pub(crate) kind: ZeromqMessageKind,
+    pub(crate) message: Message,
+}
+
+//-----------------------------------------------------------------------------------------
+// WorkQueues
+//-----------------------------------------------------------------------------------------
+
+#[derive(Debug)]
+struct Worker {
+    identity: Identity,
+    kinds: Vec<ZeromqMessageKind>,
+    credit: usize,
+    in_flight_count: usize,
+}
+
+impl Worker {
+    fn has_credit(&self) -> bool {
+        self.in_flight_count < self.credit
+    }
+}
+
+#[derive(Debug)]
+struct InFlightRequest {
+    worker: Identity,
+    request: QueuedRequest,
+}
+
+/// Requests of kinds which are dispatched to exactly one worker instead of being
+/// published, together with workers which announced that they take these kinds.
+///
+/// Workers are kept in order of their last use, so request goes to the least recently
+/// used worker which handles its kind and has free credit.
+#[derive(Debug)]
+pub(crate) struct WorkQueues {
+    kinds: HashSet<ZeromqMessageKind>,
+    capacity: usize,
+    workers: VecDeque<Worker>,
+    pending_requests: HashMap<ZeromqMessageKind, VecDeque<QueuedRequest>>,
+    pending_uuids: HashSet<Uuid>,
+    in_flight_requests: HashMap<Uuid, InFlightRequest>,
+}
+
+impl WorkQueues {
+    pub(crate) fn new(kinds: &[ZeromqMessageKind], capacity: usize) -> Self {
+        Self {
+            kinds: kinds.iter().copied().collect(),
+            capacity,
+            workers: VecDeque::new(),
+            pending_requests: HashMap::new(),
+            pending_uuids: HashSet::new(),
+            in_flight_requests: HashMap::new(),
+        }
+    }
+
+    /// Whether messages of kind are dispatched to workers.
+    pub(crate) fn is_queued(&self, kind: ZeromqMessageKind) -> bool {
+        self.kinds.contains(&kind)
+    }
+
+    /// Adds worker or updates kinds and credit of already known one. Kinds which are not
+    /// queued are ignored.
+    pub(crate) fn add_worker(
+        &mut self,
+        identity: &[u8],
+        kinds: &[ZeromqMessageKind],
+        credit: usize,
+    ) {
+        let kinds = kinds
+            .iter()
+            .copied()
+            .filter(|kind| self.is_queued(*kind))
+            .collect();
+
+        match self
+            .workers
+            .iter_mut()
+            .find(|worker| worker.identity == identity)
+        {
+            Some(worker) => {
+                worker.kinds = kinds;
+                worker.credit = credit;
+            }
+            None => self.workers.push_back(Worker {
+                identity: identity.to_vec(),
+                kinds,
+                credit,
+                in_flight_count: 0,
+            }),
+        }
+    }
+
+    /// Forgets worker and puts requests it was processing back in front of their queues.
+    pub(crate) fn remove_worker(&mut self, identity: &[u8]) {
+        self.workers.retain(|worker| worker.identity != identity);
+
+        let lost_uuids: Vec<Uuid> = self
+            .in_flight_requests
+            .iter()
+            .filter(|(_, in_flight_request)| in_flight_request.worker == identity)
+            .map(|(uuid, _)| *uuid)
+            .collect();
+
+        for uuid in lost_uuids {
+            if let Some(in_flight_request) = self.in_flight_requests.remove(&uuid) {
+                self.requeue(in_flight_request.request);
+            }
+        }
+    }
+
+    /// Whether request with uuid waits for worker or is processed by one, e.g. when
+    /// client resends request which is already queued.
+    pub(crate) fn contains(&self, uuid: Uuid) -> bool {
+        self.pending_uuids.contains(&uuid) || self.in_flight_requests.contains_key(&uuid)
+    }
+
+    /// Puts request at the end of queue of its kind, gives it back if queues are full.
+    pub(crate) fn push(&mut self, request: QueuedRequest) -> Result<(), QueuedRequest> {
+        if self.pending_uuids.len() >= self.capacity {
+            return Err(request);
+        }
+
+        let _ = self.pending_uuids.insert(request.uuid);
+        self.pending_requests
+            .entry(request.kind)
+            .or_default()
+            .push_back(request);
+
+        Ok(())
+    }
+
+    /// Puts request which was not delivered to worker back in front of its queue.
+    pub(crate) fn requeue(&mut self, request: QueuedRequest) {
+        let _ = self.pending_uuids.insert(request.uuid);
+        self.pending_requests
+            .entry(request.kind)
+            .or_default()
+            .push_front(request);
+    }
+
+    /// Takes the oldest request which the least recently used worker with free credit is
+    /// able to process, returns it together with identity of that worker.
+    pub(crate) fn next_dispatch(&mut self) -> Option<(Identity, QueuedRequest)> {
+        if self.pending_uuids.is_empty() {
+            return None;
+        }
+
+        let pending_requests = &mut self.pending_requests;
+        let (worker_identity, request) = self
+            .workers
+            .iter()
+            .filter(|worker| worker.has_credit())
+            .find_map(|worker| {
+                worker.kinds.iter().find_map(|kind| {
+                    pending_requests
+                        .get_mut(kind)
+                        .and_then(VecDeque::pop_front)
+                        .map(|request| (worker.identity.clone(), request))
+                })
+            })?;
+
+        let _ = self.pending_uuids.remove(&request.uuid);
+
+        Some((worker_identity, request))
+    }
+
+    /// Records that request was delivered to worker, which takes one credit of the worker
+    /// and makes it the most recently used one.
+    pub(crate) fn mark_in_flight(
+        &mut self,
+        worker_identity: Identity,
+        request: QueuedRequest,
+    ) {
+        if let Some(index) = self
+            .workers
+            .iter()
+            .position(|worker| worker.identity == worker_identity)
+        {
+            if let Some(mut worker) = self.workers.remove(index) {
+                worker.in_flight_count += 1;
+                self.workers.push_back(worker);
+            }
+        }
+
+        let _ = self.in_flight_requests.insert(
+            request.uuid,
+            InFlightRequest {
+                worker: worker_identity,
+                request,
+            },
+        );
+    }
+
+    /// Completes request if message with its uuid came from the worker which processes it,
+    /// gives credit back to the worker and returns identity of requester.
+    pub(crate) fn complete(&mut self, uuid: Uuid, worker_identity: &[u8]) -> Option<Identity> {
+        match self.in_flight_requests.get(&uuid) {
+            Some(in_flight_request) if in_flight_request.worker == worker_identity => {}
+            _ => return None,
+        }
+
+        let in_flight_request = self.in_flight_requests.remove(&uuid)?;
+
+        if let Some(worker) = self
+            .workers
+            .iter_mut()
+            .find(|worker| worker.identity == worker_identity)
+        {
+            worker.in_flight_count = worker.in_flight_count.saturating_sub(1);
+        }
+
+        Some(in_flight_request.request.requester)
+    }
+
+    /// Count of requests which wait for worker or are processed by one.
+    pub(crate) fn len(&self) -> usize {
+        self.pending_uuids.len() + self.in_flight_requests.len()
+    }
+}
+
+//-----------------------------------------------------------------------------------------
+// Tests
+//-----------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::work_queue::QueuedRequest;
+    use crate::work_queue::WorkQueues;
+    use uuid::Uuid;
+    use zeromq_messages::kind::ZeromqMessageKind;
+    use zmq::Message;
+
+    const KIND: ZeromqMessageKind = ZeromqMessageKind::ValueMultiplicationRequest;
+
+    fn request(requester: &[u8]) -> QueuedRequest {
+        QueuedRequest {
+            requester: requester.to_vec(),
+            uuid: Uuid::new_v4(),
+            kind: KIND,
+            message: Message::new(),
+        }
+    }
+
+    fn dispatch(work_queues: &mut WorkQueues) -> Option<(Vec<u8>, Uuid)> {
+        let (worker_identity, request) = work_queues.next_dispatch()?;
+        let uuid = request.uuid;
+        work_queues.mark_in_flight(worker_identity.clone(), request);
+        Some((worker_identity, uuid))
+    }
+
+    #[test]
+    fn least_recently_used_worker() {
+        let mut work_queues = WorkQueues::new(&[KIND], 10);
+        assert!(work_queues.is_queued(KIND));
+        assert!(!work_queues.is_queued(ZeromqMessageKind::ValueMultiplicationResponse));
+
+        work_queues.add_worker(b"a", &[KIND], 10);
+        work_queues.add_worker(b"b", &[KIND], 10);
+        for _ in 0..3 {
+            work_queues.push(request(b"client")).unwrap();
+        }
+
+        assert_eq!(b"a".to_vec(), dispatch(&mut work_queues).unwrap().0);
+        assert_eq!(b"b".to_vec(), dispatch(&mut work_queues).unwrap().0);
+        assert_eq!(b"a".to_vec(), dispatch(&mut work_queues).unwrap().0);
+        assert!(dispatch(&mut work_queues).is_none());
+    }
+
+    #[test]
+    fn credit() {
+        let mut work_queues = WorkQueues::new(&[KIND], 10);
+        work_queues.add_worker(b"a", &[KIND], 1);
+        work_queues.push(request(b"client")).unwrap();
+        work_queues.push(request(b"client")).unwrap();
+
+        let (_, uuid) = dispatch(&mut work_queues).unwrap();
+        assert!(dispatch(&mut work_queues).is_none());
+
+        // Only the worker which took request is able to complete it.
+        assert_eq!(None, work_queues.complete(uuid, b"b"));
+        assert_eq!(Some(b"client".to_vec()), work_queues.complete(uuid, b"a"));
+        assert_eq!(None, work_queues.complete(uuid, b"a"));
+
+        assert!(dispatch(&mut work_queues).is_some());
+        assert_eq!(1, work_queues.len());
+    }
+
+    #[test]
+    fn contains() {
+        let mut work_queues = WorkQueues::new(&[KIND], 10);
+        work_queues.add_worker(b"a", &[KIND], 1);
+        let pending_request = request(b"client");
+        let uuid = pending_request.uuid;
+        assert!(!work_queues.contains(uuid));
+
+        work_queues.push(pending_request).unwrap();
+        assert!(work_queues.contains(uuid));
+
+        let _ = dispatch(&mut work_queues).unwrap();
+        assert!(work_queues.contains(uuid));
+
+        let _ = work_queues.complete(uuid, b"a").unwrap();
+        assert!(!work_queues.contains(uuid));
+    }
+
+    #[test]
+    fn capacity() {
+        let mut work_queues = WorkQueues::new(&[KIND], 1);
+        work_queues.push(request(b"client")).unwrap();
+        assert!(work_queues.push(request(b"client")).is_err());
+    }
+
+    #[test]
+    fn remove_worker() {
+        let mut work_queues = WorkQueues::new(&[KIND], 10);
+        work_queues.add_worker(b"a", &[KIND], 10);
+        work_queues.push(request(b"client")).unwrap();
+
+        let (_, uuid) = dispatch(&mut work_queues).unwrap();
+        work_queues.remove_worker(b"a");
+        assert_eq!(1, work_queues.len());
+        assert!(dispatch(&mut work_queues).is_none());
+
+        work_queues.add_worker(b"b", &[KIND], 10);
+        assert_eq!((b"b".to_vec(), uuid), dispatch(&mut work_queues).unwrap());
+    }
+}
@@ -0,0 +1,206 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use common::inproc_config;
+use common::spawn_bus;
+use futures_core::Stream;
+use rust_impl::value_multiplication;
+use rust_impl::AsyncBusClient;
+use rust_impl::AsyncBusService;
+use rust_impl::BusClientError;
+use rust_impl::Shutdown;
+use std::future;
+use std::pin::Pin;
+use std::time::Duration;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::ValueMultiplicationRequest;
+use zeromq_messages::messages::ValueMultiplicationResponse;
+use zmq::Context;
+
+const REQUESTS_COUNT: i64 = 10;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn request_and_messages() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("async-request");
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    // Async service takes requests from stream and answers them.
+    let service = AsyncBusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationRequest],
+    )
+    .unwrap();
+    let mut requests = service
+        .messages(&[ZeromqMessageKind::ValueMultiplicationRequest])
+        .unwrap();
+    let service_join_handle = tokio::spawn(async move {
+        for _ in 0..REQUESTS_COUNT {
+            let request_message = requests.next().await.unwrap();
+            assert_eq!(
+                ZeromqMessageKind::ValueMultiplicationRequest,
+                request_message.kind()
+            );
+            let request = request_message
+                .decode::<ValueMultiplicationRequest>()
+                .unwrap();
+            service
+                .respond(
+                    request_message.uuid(),
+                    ValueMultiplicationResponse {
+                        result: request.value * request.multiplier,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+    });
+
+    let client = AsyncBusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(200_u64)).await;
+
+    for value in 0..REQUESTS_COUNT {
+        let response: ValueMultiplicationResponse = client
+            .request(ValueMultiplicationRequest {
+                value,
+                multiplier: 3,
+            })
+            .await
+            .unwrap();
+        assert_eq!(value * 3, response.result);
+    }
+
+    service_join_handle.await.unwrap();
+    assert_eq!(0, client.blocking().awaiting_requests_count());
+
+    drop(client);
+    shutdown.request();
+    let _ = bus_join_handle.join().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn messages_of_chosen_kinds() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("async-messages");
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    let receiver = AsyncBusClient::connect(
+        &context,
+        &config,
+        &[
+            ZeromqMessageKind::ValueMultiplicationRequest,
+            ZeromqMessageKind::ValueMultiplicationResponse,
+        ],
+    )
+    .unwrap();
+    let mut responses = receiver
+        .messages(&[ZeromqMessageKind::ValueMultiplicationResponse])
+        .unwrap();
+    assert!(matches!(
+        receiver.messages(&[ZeromqMessageKind::BusRejection]),
+        Err(BusClientError::NotSubscribed(
+            ZeromqMessageKind::BusRejection
+        ))
+    ));
+
+    let sender = AsyncBusClient::connect(&context, &config, &[]).unwrap();
+    tokio::time::sleep(Duration::from_millis(200_u64)).await;
+
+    // Request is received by nobody, because receiver does not listen to its kind.
+    let _ = sender
+        .send(ValueMultiplicationRequest {
+            value: 6,
+            multiplier: 7,
+        })
+        .await
+        .unwrap();
+    let uuid = sender
+        .send(ValueMultiplicationResponse { result: 42 })
+        .await
+        .unwrap();
+
+    let response_message = responses.next().await.unwrap();
+    assert_eq!(uuid, response_message.uuid());
+    assert_eq!(
+        42,
+        response_message
+            .decode::<ValueMultiplicationResponse>()
+            .unwrap()
+            .result
+    );
+
+    // Stream ends together with client.
+    drop(receiver);
+    assert!(future::poll_fn(|cx| Pin::new(&mut responses).poll_next(cx))
+        .await
+        .is_none());
+
+    drop(sender);
+    shutdown.request();
+    let _ = bus_join_handle.join().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_service() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("async-service");
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    let service_context = context.clone();
+    let service_config = config.clone();
+    let service_join_handle = tokio::spawn(async move {
+        AsyncBusService::new()
+            .on(|request: ValueMultiplicationRequest| async move {
+                tokio::task::yield_now().await;
+                value_multiplication(request)
+            })
+            .run(&service_context, &service_config)
+            .await
+    });
+
+    let client = AsyncBusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(200_u64)).await;
+
+    for value in 0..REQUESTS_COUNT {
+        let response: ValueMultiplicationResponse = client
+            .request(ValueMultiplicationRequest {
+                value,
+                multiplier: 4,
+            })
+            .await
+            .unwrap();
+        assert_eq!(value * 4, response.result);
+    }
+
+    // Dropping service future disconnects it, so requests are not answered anymore.
+    service_join_handle.abort();
+    assert!(service_join_handle.await.unwrap_err().is_cancelled());
+    let response = tokio::time::timeout(
+        Duration::from_millis(500_u64),
+        client.request::<_, ValueMultiplicationResponse>(ValueMultiplicationRequest {
+            value: 1,
+            multiplier: 4,
+        }),
+    )
+    .await;
+    assert!(response.is_err());
+
+    drop(client);
+    shutdown.request();
+    let _ = bus_join_handle.join().unwrap();
+}
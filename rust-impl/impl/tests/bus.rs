@@ -0,0 +1,998 @@
+mod common;
+
+use common::inproc_config;
+use common::spawn_bus;
+use common::spawn_service;
+use rust_impl::recv_published_message;
+use rust_impl::subscribe_to_kinds;
+use rust_impl::value_multiplication;
+use rust_impl::Bus;
+use rust_impl::BusClient;
+use rust_impl::BusClientError;
+use rust_impl::BusConfig;
+use rust_impl::BusError;
+use rust_impl::BusService;
+use rust_impl::BusStats;
+use rust_impl::CurveError;
+use rust_impl::CurveKeyPair;
+use rust_impl::JournalSync;
+use rust_impl::Metrics;
+use rust_impl::OverflowPolicy;
+use rust_impl::Shutdown;
+use rust_impl::VALUE_MULTIPLICATION_SERVICE_NAME;
+use std::env;
+use std::fs;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use uuid::Uuid;
+use zeromq_messages::codec::encode_message;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::BusAcknowledgement;
+use zeromq_messages::messages::BusHeartbeat;
+use zeromq_messages::messages::BusRejection;
+use zeromq_messages::messages::BusServiceRegistration;
+use zeromq_messages::messages::BusWorkerReady;
+use zeromq_messages::messages::ValueMultiplicationRequest;
+use zeromq_messages::messages::ValueMultiplicationResponse;
+use zeromq_messages::view::MessageView;
+use zmq::Context;
+use zmq::Message;
+use zmq::Socket;
+use zmq::SocketType;
+
+const TIMEOUT: Duration = Duration::from_secs(10_u64);
+
+/// Runs BUS and multiplication service over inproc endpoints prefixed by `name`.
+fn spawn_bus_and_service(
+    context: &Context,
+    name: &str,
+    shutdown: &Shutdown,
+) -> (BusConfig, JoinHandle<BusStats>, JoinHandle<()>) {
+    spawn_bus_and_service_with_config(context, inproc_config(name), shutdown)
+}
+
+fn spawn_bus_and_service_with_config(
+    context: &Context,
+    config: BusConfig,
+    shutdown: &Shutdown,
+) -> (BusConfig, JoinHandle<BusStats>, JoinHandle<()>) {
+    let bus_join_handle = spawn_bus(context, &config, shutdown);
+    let service_join_handle = spawn_service(context, &config, shutdown);
+
+    (config, bus_join_handle, service_join_handle)
+}
+
+fn multiply(client: &BusClient, value: i64, multiplier: i64) -> i64 {
+    client
+        .request::<_, ValueMultiplicationResponse>(
+            ValueMultiplicationRequest { value, multiplier },
+            TIMEOUT,
+        )
+        .unwrap()
+        .result
+}
+
+#[test]
+fn in_process_bus() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let (config, _, _) = spawn_bus_and_service(&context, "in-process", &shutdown);
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    // Wait until subscriptions of service and client reach BUS publishers.
+    thread::sleep(Duration::from_millis(200_u64));
+
+    assert_eq!(42, multiply(&client, 6, 7));
+}
+
+#[test]
+fn graceful_shutdown() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let (config, bus_join_handle, service_join_handle) =
+        spawn_bus_and_service(&context, "graceful-shutdown", &shutdown);
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    assert_eq!(6, multiply(&client, 2, 3));
+    assert_eq!(20, multiply(&client, 4, 5));
+
+    drop(client);
+    shutdown.request();
+
+    service_join_handle.join().unwrap();
+    // Registration and requests are acknowledged, responses of the service are not.
+    assert_eq!(
+        BusStats {
+            received: 5,
+            replayed: 0,
+            published: 4,
+            routed: 0,
+            dispatched: 0,
+            dropped: 0,
+            rejected: 0,
+            acknowledged: 3,
+            skipped: 0,
+        },
+        bus_join_handle.join().unwrap()
+    );
+}
+
+#[test]
+fn acknowledgement() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let (config, _, _) = spawn_bus_and_service(&context, "acknowledgement", &shutdown);
+
+    let sender = context.socket(SocketType::DEALER).unwrap();
+    config.router_connect_endpoint().connect(&sender).unwrap();
+
+    let uuid = Uuid::new_v4();
+    let request_bytes = encode_message(
+        uuid,
+        ValueMultiplicationRequest {
+            value: 1,
+            multiplier: 1,
+        },
+    )
+    .unwrap();
+    sender.send(request_bytes, 0).unwrap();
+
+    let mut reply = Message::new();
+    sender.recv(&mut reply, 0).unwrap();
+    let reply_view = MessageView::new(&reply).unwrap();
+
+    assert_eq!(ZeromqMessageKind::BusAcknowledgement, reply_view.kind());
+    assert_eq!(uuid, reply_view.uuid());
+    assert!(
+        !reply_view
+            .decode_payload::<BusAcknowledgement>()
+            .unwrap()
+            .journaled
+    );
+}
+
+#[test]
+fn service_messages_are_not_acknowledged() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("service-messages");
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    let instance = context.socket(SocketType::DEALER).unwrap();
+    instance.set_rcvtimeo(300).unwrap();
+    config.router_connect_endpoint().connect(&instance).unwrap();
+    let registration_uuid = Uuid::new_v4();
+    let registration_bytes = encode_message(
+        registration_uuid,
+        BusServiceRegistration {
+            name: String::from("responder"),
+            instance_id: String::from("responder-0"),
+            kinds: vec![ZeromqMessageKind::ValueMultiplicationRequest.number()],
+        },
+    )
+    .unwrap();
+    instance.send(registration_bytes, 0).unwrap();
+
+    // Registration is acknowledged, response which registered instance publishes is not.
+    let mut reply = Message::new();
+    instance.recv(&mut reply, 0).unwrap();
+    let reply_view = MessageView::new(&reply).unwrap();
+    assert_eq!(ZeromqMessageKind::BusAcknowledgement, reply_view.kind());
+    assert_eq!(registration_uuid, reply_view.uuid());
+
+    let response_bytes =
+        encode_message(Uuid::new_v4(), ValueMultiplicationResponse { result: 1 }).unwrap();
+    instance.send(response_bytes, 0).unwrap();
+    assert_eq!(Err(zmq::Error::EAGAIN), instance.recv(&mut reply, 0));
+
+    shutdown.request();
+    let stats = bus_join_handle.join().unwrap();
+    assert_eq!(2, stats.received);
+    assert_eq!(1, stats.acknowledged);
+}
+
+#[test]
+fn drop_oldest_acknowledges_new_messages() {
+    const MESSAGES_COUNT: usize = 5;
+
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = BusConfig {
+        queue_capacity: 1,
+        overflow_policy: OverflowPolicy::DropOldest,
+        ..inproc_config("drop-oldest")
+    };
+    let bus = Bus::bind(&context, &config).unwrap();
+
+    // Messages wait in router socket until BUS runs, so they are received in one batch
+    // and all but the last one are dropped from queue.
+    let sender = context.socket(SocketType::DEALER).unwrap();
+    sender.set_rcvtimeo(10_000).unwrap();
+    config.router_connect_endpoint().connect(&sender).unwrap();
+    let uuids = (0..MESSAGES_COUNT)
+        .map(|_| {
+            let uuid = Uuid::new_v4();
+            let request_bytes = encode_message(
+                uuid,
+                ValueMultiplicationRequest {
+                    value: 1,
+                    multiplier: 1,
+                },
+            )
+            .unwrap();
+            sender.send(request_bytes, 0).unwrap();
+            uuid
+        })
+        .collect::<Vec<_>>();
+
+    let bus_shutdown = shutdown.clone();
+    let bus_join_handle = thread::spawn(move || bus.run(&bus_shutdown));
+
+    for uuid in uuids {
+        let mut reply = Message::new();
+        sender.recv(&mut reply, 0).unwrap();
+        let reply_view = MessageView::new(&reply).unwrap();
+
+        assert_eq!(ZeromqMessageKind::BusAcknowledgement, reply_view.kind());
+        assert_eq!(uuid, reply_view.uuid());
+    }
+
+    shutdown.request();
+    let stats = bus_join_handle.join().unwrap();
+    assert_eq!(MESSAGES_COUNT, stats.received);
+    assert_eq!(MESSAGES_COUNT - 1, stats.dropped);
+    assert_eq!(MESSAGES_COUNT, stats.acknowledged);
+}
+
+#[test]
+fn rejections_when_queue_is_full() {
+    const MESSAGES_COUNT: usize = 5;
+
+    for overflow_policy in [OverflowPolicy::Reject, OverflowPolicy::DropNewest] {
+        let context = Context::new();
+        let shutdown = Shutdown::new();
+        let journal_directory =
+            env::temp_dir().join(format!("bus-journal-{}", Uuid::new_v4()));
+        let config = BusConfig {
+            queue_capacity: 1,
+            overflow_policy,
+            journal_directory: Some(journal_directory.clone()),
+            ..inproc_config(&format!("full-queue-{}", overflow_policy))
+        };
+        let bus = Bus::bind(&context, &config).unwrap();
+
+        // Messages wait in router socket until BUS runs, so they are received in one
+        // batch and only the first one fits into queue.
+        let sender = context.socket(SocketType::DEALER).unwrap();
+        sender.set_rcvtimeo(10_000).unwrap();
+        config.router_connect_endpoint().connect(&sender).unwrap();
+        let uuids = (0..MESSAGES_COUNT)
+            .map(|_| {
+                let uuid = Uuid::new_v4();
+                let request_bytes = encode_message(
+                    uuid,
+                    ValueMultiplicationRequest {
+                        value: 1,
+                        multiplier: 1,
+                    },
+                )
+                .unwrap();
+                sender.send(request_bytes, 0).unwrap();
+                uuid
+            })
+            .collect::<Vec<_>>();
+
+        let bus_shutdown = shutdown.clone();
+        let bus_join_handle = thread::spawn(move || bus.run(&bus_shutdown));
+
+        // Rejections are sent right away, acknowledgement waits for journal flush.
+        let mut acknowledged_uuids = Vec::new();
+        let mut rejected_uuids = Vec::new();
+        for _ in &uuids {
+            let mut reply = Message::new();
+            sender.recv(&mut reply, 0).unwrap();
+            let reply_view = MessageView::new(&reply).unwrap();
+
+            match reply_view.kind() {
+                ZeromqMessageKind::BusAcknowledgement => {
+                    acknowledged_uuids.push(reply_view.uuid());
+                }
+                ZeromqMessageKind::BusRejection => rejected_uuids.push(reply_view.uuid()),
+                kind => panic!("unexpected reply {:?}", kind),
+            }
+        }
+        assert_eq!(uuids[..1], acknowledged_uuids[..]);
+        assert_eq!(uuids[1..], rejected_uuids[..]);
+
+        shutdown.request();
+        let stats = bus_join_handle.join().unwrap();
+        assert_eq!(MESSAGES_COUNT - 1, stats.rejected);
+        if overflow_policy == OverflowPolicy::DropNewest {
+            assert_eq!(MESSAGES_COUNT - 1, stats.dropped);
+        }
+
+        fs::remove_dir_all(&journal_directory).unwrap();
+    }
+}
+
+#[test]
+fn journaled_acknowledgements() {
+    const MESSAGES_COUNT: usize = 5;
+
+    for journal_sync in [JournalSync::Batch, JournalSync::EveryMessage] {
+        let context = Context::new();
+        let shutdown = Shutdown::new();
+        let journal_directory =
+            env::temp_dir().join(format!("bus-journal-{}", Uuid::new_v4()));
+        let config = BusConfig {
+            journal_directory: Some(journal_directory.clone()),
+            journal_sync,
+            ..inproc_config(&format!("journaled-{}", journal_sync))
+        };
+        let bus = Bus::bind(&context, &config).unwrap();
+
+        // Messages wait in router socket until BUS runs, so they are received in one
+        // batch and acknowledged after journal is flushed.
+        let sender = context.socket(SocketType::DEALER).unwrap();
+        sender.set_rcvtimeo(10_000).unwrap();
+        config.router_connect_endpoint().connect(&sender).unwrap();
+        let uuids = (0..MESSAGES_COUNT)
+            .map(|_| {
+                let uuid = Uuid::new_v4();
+                let request_bytes = encode_message(
+                    uuid,
+                    ValueMultiplicationRequest {
+                        value: 1,
+                        multiplier: 1,
+                    },
+                )
+                .unwrap();
+                sender.send(request_bytes, 0).unwrap();
+                uuid
+            })
+            .collect::<Vec<_>>();
+
+        let bus_shutdown = shutdown.clone();
+        let bus_join_handle = thread::spawn(move || bus.run(&bus_shutdown));
+
+        for uuid in uuids {
+            let mut reply = Message::new();
+            sender.recv(&mut reply, 0).unwrap();
+            let reply_view = MessageView::new(&reply).unwrap();
+
+            assert_eq!(ZeromqMessageKind::BusAcknowledgement, reply_view.kind());
+            assert_eq!(uuid, reply_view.uuid());
+            assert!(
+                reply_view
+                    .decode_payload::<BusAcknowledgement>()
+                    .unwrap()
+                    .journaled
+            );
+        }
+
+        shutdown.request();
+        let stats = bus_join_handle.join().unwrap();
+        assert_eq!(MESSAGES_COUNT, stats.acknowledged);
+
+        fs::remove_dir_all(&journal_directory).unwrap();
+    }
+}
+
+#[test]
+fn directed_delivery() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let (config, _, _) = spawn_bus_and_service(&context, "directed-delivery", &shutdown);
+
+    // Second instance counts requests it handles, the first one handles the rest.
+    let handled_requests_count = Arc::new(AtomicUsize::new(0));
+    let handled_requests_count_clone = Arc::clone(&handled_requests_count);
+    let service_context = context.clone();
+    let service_config = config.clone();
+    drop(thread::spawn(move || {
+        BusService::new()
+            .named(VALUE_MULTIPLICATION_SERVICE_NAME)
+            .with_instance_id("multiplier-1")
+            .on(move |request| {
+                let _ = handled_requests_count_clone.fetch_add(1, Ordering::SeqCst);
+                value_multiplication(request)
+            })
+            .run(&service_context, &service_config, &Shutdown::new())
+            .unwrap();
+    }));
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    // Directed requests are handled by one instance at a time, instances take turns.
+    for value in 0..10 {
+        let response = client
+            .request_to::<_, ValueMultiplicationResponse>(
+                VALUE_MULTIPLICATION_SERVICE_NAME,
+                ValueMultiplicationRequest {
+                    value,
+                    multiplier: 2,
+                },
+                TIMEOUT,
+            )
+            .unwrap();
+        assert_eq!(value * 2, response.result);
+    }
+
+    assert_eq!(5, handled_requests_count.load(Ordering::SeqCst));
+
+    // Instance id addresses one instance, so it handles every request directed to it.
+    for value in 0..4 {
+        let response = client
+            .request_to::<_, ValueMultiplicationResponse>(
+                "multiplier-1",
+                ValueMultiplicationRequest {
+                    value,
+                    multiplier: 3,
+                },
+                TIMEOUT,
+            )
+            .unwrap();
+        assert_eq!(value * 3, response.result);
+    }
+
+    assert_eq!(9, handled_requests_count.load(Ordering::SeqCst));
+
+    match client.request_to::<_, ValueMultiplicationResponse>(
+        "unknown-service",
+        ValueMultiplicationRequest {
+            value: 1,
+            multiplier: 1,
+        },
+        TIMEOUT,
+    ) {
+        Err(BusClientError::Rejected { reason, .. }) => {
+            assert_eq!("No available instance of service unknown-service", reason);
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn queued_requests() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = BusConfig {
+        queue_kinds: vec![ZeromqMessageKind::ValueMultiplicationRequest],
+        ..inproc_config("queued-requests")
+    };
+
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    // Both workers count requests they handle.
+    let handled_requests_counts =
+        [Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0))];
+    let worker_join_handles: Vec<JoinHandle<()>> = handled_requests_counts
+        .iter()
+        .map(|handled_requests_count| {
+            let handled_requests_count = Arc::clone(handled_requests_count);
+            let worker_context = context.clone();
+            let worker_config = config.clone();
+            let worker_shutdown = shutdown.clone();
+            thread::spawn(move || {
+                BusService::new()
+                    .on(move |request| {
+                        let _ = handled_requests_count.fetch_add(1, Ordering::SeqCst);
+                        value_multiplication(request)
+                    })
+                    .run(&worker_context, &worker_config, &worker_shutdown)
+                    .unwrap();
+            })
+        })
+        .collect();
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    // Every request is handled by exactly one worker, workers take turns.
+    for value in 0..10 {
+        assert_eq!(value * 3, multiply(&client, value, 3));
+    }
+
+    for handled_requests_count in &handled_requests_counts {
+        assert_eq!(5, handled_requests_count.load(Ordering::SeqCst));
+    }
+
+    drop(client);
+    shutdown.request();
+
+    for worker_join_handle in worker_join_handles {
+        worker_join_handle.join().unwrap();
+    }
+    let stats = bus_join_handle.join().unwrap();
+    assert_eq!(10, stats.dispatched);
+    assert_eq!(10, stats.routed);
+    assert_eq!(0, stats.published);
+    assert_eq!(0, stats.dropped);
+}
+
+/// Receives messages until one of given kind arrives, e.g. skipping acknowledgements.
+fn recv_message_of_kind(socket: &Socket, kind: ZeromqMessageKind) -> Message {
+    loop {
+        let mut message = Message::new();
+        socket.recv(&mut message, 0).unwrap();
+        if MessageView::new(&message).unwrap().kind() == kind {
+            return message;
+        }
+    }
+}
+
+#[test]
+fn resent_queued_request_keeps_worker_credit() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = BusConfig {
+        queue_kinds: vec![ZeromqMessageKind::ValueMultiplicationRequest],
+        ..inproc_config("resent-queued-request")
+    };
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    let worker = context.socket(SocketType::DEALER).unwrap();
+    worker.set_rcvtimeo(10_000).unwrap();
+    config.router_connect_endpoint().connect(&worker).unwrap();
+    let worker_ready_bytes = encode_message(
+        Uuid::new_v4(),
+        BusWorkerReady {
+            kinds: vec![ZeromqMessageKind::ValueMultiplicationRequest.number()],
+            credit: 1,
+        },
+    )
+    .unwrap();
+    worker.send(worker_ready_bytes, 0).unwrap();
+
+    let requester = context.socket(SocketType::DEALER).unwrap();
+    requester.set_rcvtimeo(10_000).unwrap();
+    config
+        .router_connect_endpoint()
+        .connect(&requester)
+        .unwrap();
+    thread::sleep(Duration::from_millis(200_u64));
+
+    let request = |uuid: Uuid, resends_count: usize| {
+        let request_bytes = encode_message(
+            uuid,
+            ValueMultiplicationRequest {
+                value: 6,
+                multiplier: 7,
+            },
+        )
+        .unwrap();
+        for _ in 0..=resends_count {
+            requester.send(&request_bytes, 0).unwrap();
+        }
+
+        // The only credit of worker is taken by this request, not by its resent copy.
+        let request_message =
+            recv_message_of_kind(&worker, ZeromqMessageKind::ValueMultiplicationRequest);
+        assert_eq!(uuid, MessageView::new(&request_message).unwrap().uuid());
+
+        let response_bytes =
+            encode_message(uuid, ValueMultiplicationResponse { result: 42 }).unwrap();
+        worker.send(response_bytes, 0).unwrap();
+
+        let response_message =
+            recv_message_of_kind(&requester, ZeromqMessageKind::ValueMultiplicationResponse);
+        assert_eq!(uuid, MessageView::new(&response_message).unwrap().uuid());
+    };
+    request(Uuid::new_v4(), 2);
+    request(Uuid::new_v4(), 0);
+
+    shutdown.request();
+    let stats = bus_join_handle.join().unwrap();
+    assert_eq!(2, stats.dispatched);
+    assert_eq!(2, stats.routed);
+}
+
+#[test]
+fn service_registry() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = BusConfig {
+        heartbeat_interval_millis: 50,
+        heartbeat_liveness: 2,
+        ..inproc_config("service-registry")
+    };
+    let (config, _, _) = spawn_bus_and_service_with_config(&context, config, &shutdown);
+
+    // Instance which registers and then stays silent is expired.
+    let silent_instance = context.socket(SocketType::DEALER).unwrap();
+    config
+        .router_connect_endpoint()
+        .connect(&silent_instance)
+        .unwrap();
+    let registration_bytes = encode_message(
+        Uuid::new_v4(),
+        BusServiceRegistration {
+            name: String::from("silent"),
+            instance_id: String::from("silent-0"),
+            kinds: Vec::new(),
+        },
+    )
+    .unwrap();
+    silent_instance.send(registration_bytes, 0).unwrap();
+
+    let client = BusClient::connect(&context, &config, &[]).unwrap();
+
+    thread::sleep(Duration::from_millis(50_u64));
+
+    let registry = client.query_registry(None, TIMEOUT).unwrap();
+    let instance_ids: Vec<&str> = registry
+        .instances
+        .iter()
+        .map(|instance| instance.instance_id.as_str())
+        .collect();
+    assert_eq!(vec!["silent-0", "multiplier-0"], instance_ids);
+    assert_eq!(
+        VALUE_MULTIPLICATION_SERVICE_NAME,
+        registry.instances[1].name
+    );
+
+    thread::sleep(Duration::from_millis(300_u64));
+
+    // Multiplication service keeps sending heartbeats, so only it is left.
+    let registry = client
+        .query_registry(Some(ZeromqMessageKind::ValueMultiplicationRequest), TIMEOUT)
+        .unwrap();
+    assert_eq!(1, registry.instances.len());
+    assert_eq!("multiplier-0", registry.instances[0].instance_id);
+    assert!(client
+        .query_registry(Some(ZeromqMessageKind::BusHeartbeat), TIMEOUT)
+        .unwrap()
+        .instances
+        .is_empty());
+
+    // Heartbeat of expired instance is rejected, so it knows that it should register again.
+    let heartbeat_uuid = Uuid::new_v4();
+    silent_instance
+        .send(encode_message(heartbeat_uuid, BusHeartbeat {}).unwrap(), 0)
+        .unwrap();
+
+    let mut reply = Message::new();
+    loop {
+        silent_instance.recv(&mut reply, 0).unwrap();
+        let reply_view = MessageView::new(&reply).unwrap();
+        if reply_view.uuid() == heartbeat_uuid {
+            assert_eq!(
+                "Service instance is not registered",
+                reply_view.decode_payload::<BusRejection>().unwrap().reason
+            );
+            break;
+        }
+    }
+}
+
+#[test]
+fn bus_metrics() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("bus-metrics");
+    let metrics = Metrics::new();
+
+    let bus = Bus::bind(&context, &config).unwrap().with_metrics(&metrics);
+    let bus_shutdown = shutdown.clone();
+    drop(thread::spawn(move || bus.run(&bus_shutdown)));
+
+    let service_context = context.clone();
+    let service_config = config.clone();
+    let service_metrics = metrics.clone();
+    drop(thread::spawn(move || {
+        BusService::new()
+            .with_metrics(&service_metrics)
+            .on(value_multiplication)
+            .run(&service_context, &service_config, &Shutdown::new())
+            .unwrap();
+    }));
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    assert_eq!(42, multiply(&client, 6, 7));
+
+    // Message which is not even a header is counted as decode error.
+    let sender = context.socket(SocketType::DEALER).unwrap();
+    config.router_connect_endpoint().connect(&sender).unwrap();
+    sender.send(&b"garbage"[..], 0).unwrap();
+
+    thread::sleep(Duration::from_millis(100_u64));
+
+    let rendered_metrics = metrics.render();
+    for sample in &[
+        "bus_published_messages_total 2",
+        "bus_publisher_sent_messages_total{publisher=\"0\"} 2",
+        "bus_decode_errors_total{error=\"truncated\"} 1",
+        "bus_retry_buffer_messages 0",
+        "service_processed_requests_total 1",
+        "service_request_handling_duration_seconds_count 1",
+    ] {
+        assert!(
+            rendered_metrics.contains(sample),
+            "{} is missing in:\n{}",
+            sample,
+            rendered_metrics
+        );
+    }
+}
+
+#[test]
+fn subscriptions() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let (config, bus_join_handle, _) =
+        spawn_bus_and_service(&context, "subscriptions", &shutdown);
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    let subscriptions = client.query_subscriptions(TIMEOUT).unwrap();
+    assert_eq!(1, subscriptions.publishers.len());
+    assert_eq!(
+        "inproc://subscriptions-publisher",
+        subscriptions.publishers[0].endpoint
+    );
+    assert_eq!(
+        vec![
+            ZeromqMessageKind::ValueMultiplicationRequest.number(),
+            ZeromqMessageKind::ValueMultiplicationResponse.number()
+        ],
+        subscriptions.publishers[0].kinds
+    );
+
+    // Nobody is subscribed to kind of rejections, so it is not published.
+    let sender = context.socket(SocketType::DEALER).unwrap();
+    config.router_connect_endpoint().connect(&sender).unwrap();
+    let rejection_bytes = encode_message(
+        Uuid::new_v4(),
+        BusRejection {
+            reason: String::from("nobody listens"),
+        },
+    )
+    .unwrap();
+    sender.send(rejection_bytes, 0).unwrap();
+
+    assert_eq!(42, multiply(&client, 6, 7));
+
+    drop(client);
+    shutdown.request();
+
+    let stats = bus_join_handle.join().unwrap();
+    assert_eq!(1, stats.skipped);
+    assert_eq!(2, stats.published);
+}
+
+#[test]
+fn publishers_without_subscribers_are_not_used() {
+    const MESSAGES_COUNT: usize = 10;
+
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = BusConfig {
+        publisher_endpoints: Some(vec![
+            "inproc://partial-subscriptions-publisher-0"
+                .parse()
+                .unwrap(),
+            "inproc://partial-subscriptions-publisher-1"
+                .parse()
+                .unwrap(),
+        ]),
+        acknowledge_messages: false,
+        ..inproc_config("partial-subscriptions")
+    };
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    // Round robin would send every second message to publisher nobody listens to.
+    let subscriber = context.socket(SocketType::SUB).unwrap();
+    subscriber.set_rcvtimeo(10_000).unwrap();
+    config.publisher_connect_endpoints()[1]
+        .connect(&subscriber)
+        .unwrap();
+    subscribe_to_kinds(
+        &subscriber,
+        &[ZeromqMessageKind::ValueMultiplicationRequest],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    let sender = context.socket(SocketType::DEALER).unwrap();
+    config.router_connect_endpoint().connect(&sender).unwrap();
+    for _ in 0..MESSAGES_COUNT {
+        let request_bytes = encode_message(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest {
+                value: 6,
+                multiplier: 7,
+            },
+        )
+        .unwrap();
+        sender.send(request_bytes, 0).unwrap();
+    }
+
+    let mut message = Message::new();
+    for _ in 0..MESSAGES_COUNT {
+        recv_published_message(&subscriber, &mut message, 0).unwrap();
+        assert_eq!(
+            ZeromqMessageKind::ValueMultiplicationRequest,
+            MessageView::new(&message).unwrap().kind()
+        );
+    }
+
+    shutdown.request();
+    let stats = bus_join_handle.join().unwrap();
+    assert_eq!(MESSAGES_COUNT, stats.published);
+    assert_eq!(0, stats.skipped);
+}
+
+#[test]
+fn parked_messages() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = BusConfig {
+        park_unsubscribed_messages: true,
+        ..inproc_config("parked-messages")
+    };
+    let metrics = Metrics::new();
+
+    let bus = Bus::bind(&context, &config).unwrap().with_metrics(&metrics);
+    let bus_shutdown = shutdown.clone();
+    drop(thread::spawn(move || bus.run(&bus_shutdown)));
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    // Request waits in BUS until service subscribes to it.
+    let request_join_handle = thread::spawn(move || multiply(&client, 6, 7));
+
+    thread::sleep(Duration::from_millis(200_u64));
+    assert!(metrics.render().contains("bus_parked_messages 1"));
+
+    let service_context = context.clone();
+    let service_config = config.clone();
+    let service_shutdown = shutdown.clone();
+    drop(thread::spawn(move || {
+        BusService::new()
+            .on(value_multiplication)
+            .run(&service_context, &service_config, &service_shutdown)
+            .unwrap();
+    }));
+
+    assert_eq!(42, request_join_handle.join().unwrap());
+    assert!(metrics.render().contains("bus_parked_messages 0"));
+    assert!(metrics.render().contains("bus_skipped_messages_total 0"));
+}
+
+#[test]
+fn curve_security() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let directory = env::temp_dir().join(format!("bus-curve-{}", Uuid::new_v4()));
+    // CURVE is not applied to inproc transport.
+    let config = BusConfig {
+        router_endpoint: Some("tcp://127.0.0.1:57931".parse().unwrap()),
+        publisher_endpoints: Some(vec!["tcp://127.0.0.1:57932".parse().unwrap()]),
+        curve_server_key_file: Some(directory.join("bus.key_secret")),
+        curve_allowlist_file: Some(directory.join("allowlist")),
+        curve_server_public_key_file: Some(directory.join("bus.key")),
+        curve_client_key_file: Some(directory.join("service.key_secret")),
+        ..BusConfig::default()
+    };
+
+    // BUS and services refuse to run without security they are configured to use.
+    if zmq::has("curve") != Some(true) {
+        assert!(matches!(
+            CurveKeyPair::generate(),
+            Err(CurveError::Unsupported)
+        ));
+        assert!(matches!(
+            Bus::bind(&context, &config),
+            Err(BusError::Curve(CurveError::Unsupported))
+        ));
+        assert!(matches!(
+            BusClient::connect(&context, &config, &[]),
+            Err(BusClientError::Curve(CurveError::Unsupported))
+        ));
+        return;
+    }
+
+    fs::create_dir_all(&directory).unwrap();
+    let mut allowlist = String::from("# Services allowed to connect to BUS.\n");
+    for name in &["bus", "service", "intruder"] {
+        let key_pair = CurveKeyPair::generate().unwrap();
+        let _ = key_pair.write(&directory, name).unwrap();
+        if *name == "service" {
+            allowlist.push_str(&format!("{}\n", key_pair.public_key()));
+        }
+    }
+    fs::write(directory.join("allowlist"), allowlist).unwrap();
+
+    let (config, _, _) = spawn_bus_and_service_with_config(&context, config, &shutdown);
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    assert_eq!(42, multiply(&client, 6, 7));
+
+    // Client with key which is not in allowlist never finishes handshake with BUS.
+    let intruder_config = BusConfig {
+        curve_client_key_file: Some(directory.join("intruder.key_secret")),
+        ..config
+    };
+    let intruder = BusClient::connect(
+        &context,
+        &intruder_config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    thread::sleep(Duration::from_millis(200_u64));
+
+    assert!(matches!(
+        intruder.request::<_, ValueMultiplicationResponse>(
+            ValueMultiplicationRequest {
+                value: 6,
+                multiplier: 7,
+            },
+            Duration::from_secs(1_u64),
+        ),
+        Err(BusClientError::Timeout { .. })
+    ));
+
+    fs::remove_dir_all(&directory).unwrap();
+}
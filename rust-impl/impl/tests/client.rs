@@ -0,0 +1,220 @@
+mod common;
+
+use common::inproc_config;
+use common::spawn_bus;
+use common::spawn_service;
+use rust_impl::BusClient;
+use rust_impl::BusClientError;
+use rust_impl::BusConfig;
+use rust_impl::Shutdown;
+use rust_impl::VALUE_MULTIPLICATION_SERVICE_NAME;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::ValueMultiplicationRequest;
+use zeromq_messages::messages::ValueMultiplicationResponse;
+use zmq::Context;
+
+const TIMEOUT: Duration = Duration::from_secs(5_u64);
+
+/// Runs BUS and multiplication service over inproc endpoints prefixed by `name`, and
+/// waits until service subscribes to requests.
+fn spawn_bus_and_service(context: &Context, name: &str, shutdown: &Shutdown) -> BusConfig {
+    let config = inproc_config(name);
+    drop(spawn_bus(context, &config, shutdown));
+    drop(spawn_service(context, &config, shutdown));
+    thread::sleep(Duration::from_millis(200_u64));
+
+    config
+}
+
+#[test]
+fn request() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = spawn_bus_and_service(&context, "request", &shutdown);
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    for value in 0..10 {
+        let response = client
+            .request::<_, ValueMultiplicationResponse>(
+                ValueMultiplicationRequest {
+                    value,
+                    multiplier: 3,
+                },
+                TIMEOUT,
+            )
+            .unwrap();
+
+        assert_eq!(value * 3, response.result);
+    }
+
+    assert_eq!(0, client.awaiting_requests_count());
+}
+
+#[test]
+fn pipelined_requests() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = spawn_bus_and_service(&context, "pipelined", &shutdown);
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    let pending_responses = (0..1_000)
+        .map(|value| {
+            let pending_response = client
+                .start_request::<_, ValueMultiplicationResponse>(ValueMultiplicationRequest {
+                    value,
+                    multiplier: 2,
+                })
+                .unwrap();
+            (value, pending_response)
+        })
+        .collect::<Vec<_>>();
+
+    for (value, pending_response) in pending_responses {
+        assert_eq!(value * 2, pending_response.wait(TIMEOUT).unwrap().result);
+    }
+}
+
+#[test]
+fn request_with_callback() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = spawn_bus_and_service(&context, "callback", &shutdown);
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+    let (results_sender, results_receiver) = mpsc::channel();
+
+    for value in 0..100 {
+        let results_sender = results_sender.clone();
+        let _ = client
+            .request_with_callback::<_, ValueMultiplicationResponse, _>(
+                ValueMultiplicationRequest {
+                    value,
+                    multiplier: 5,
+                },
+                move |result, latency| {
+                    assert!(latency < TIMEOUT);
+                    results_sender.send((value, result.unwrap())).unwrap();
+                },
+            )
+            .unwrap();
+    }
+
+    for _ in 0..100 {
+        let (value, response) = results_receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(value * 5, response.result);
+    }
+}
+
+#[test]
+fn timeout() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("timeout");
+    drop(spawn_bus(&context, &config, &shutdown));
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    let pending_response = client
+        .start_request::<_, ValueMultiplicationResponse>(ValueMultiplicationRequest {
+            value: 1,
+            multiplier: 1,
+        })
+        .unwrap();
+    let uuid = pending_response.uuid();
+
+    match pending_response.wait(Duration::from_millis(100_u64)) {
+        Err(BusClientError::Timeout {
+            uuid: timed_out_uuid,
+            ..
+        }) => assert_eq!(uuid, timed_out_uuid),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // Dropped handle forgets the request.
+    assert_eq!(0, client.awaiting_requests_count());
+}
+
+#[test]
+fn not_subscribed() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("not-subscribed");
+    drop(spawn_bus(&context, &config, &shutdown));
+    let client = BusClient::connect(&context, &config, &[]).unwrap();
+
+    match client.request::<_, ValueMultiplicationResponse>(
+        ValueMultiplicationRequest {
+            value: 1,
+            multiplier: 1,
+        },
+        TIMEOUT,
+    ) {
+        Err(BusClientError::NotSubscribed(ZeromqMessageKind::ValueMultiplicationResponse)) => {
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn rejected() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("rejected");
+    drop(spawn_bus(&context, &config, &shutdown));
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    // Multiplication service is not running, so BUS rejects request directed to it.
+    let pending_response = client
+        .start_request_to::<_, ValueMultiplicationResponse>(
+            VALUE_MULTIPLICATION_SERVICE_NAME,
+            ValueMultiplicationRequest {
+                value: 1,
+                multiplier: 1,
+            },
+        )
+        .unwrap();
+    let uuid = pending_response.uuid();
+
+    match pending_response.wait(TIMEOUT) {
+        Err(BusClientError::Rejected {
+            uuid: rejected_uuid,
+            reason,
+        }) => {
+            assert_eq!(uuid, rejected_uuid);
+            assert_eq!(
+                format!(
+                    "No available instance of service {VALUE_MULTIPLICATION_SERVICE_NAME}"
+                ),
+                reason
+            );
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(0, client.awaiting_requests_count());
+}
@@ -0,0 +1,59 @@
+//! Fixtures shared by integration tests and benchmarks. Every test binary compiles its own
+//! copy of this module and uses only part of it.
+
+#![allow(dead_code)]
+
+use rust_impl::value_multiplication;
+use rust_impl::Bus;
+use rust_impl::BusConfig;
+use rust_impl::BusService;
+use rust_impl::BusStats;
+use rust_impl::Shutdown;
+use rust_impl::Transport;
+use rust_impl::VALUE_MULTIPLICATION_SERVICE_NAME;
+use std::thread;
+use std::thread::JoinHandle;
+use zmq::Context;
+
+/// Config of BUS with single publisher over inproc endpoints prefixed by `name`.
+pub fn inproc_config(name: &str) -> BusConfig {
+    BusConfig {
+        transport: Transport::Inproc,
+        router_endpoint: Some(format!("inproc://{}-router", name).parse().unwrap()),
+        publisher_endpoints: Some(vec![format!("inproc://{}-publisher", name)
+            .parse()
+            .unwrap()]),
+        ..BusConfig::default()
+    }
+}
+
+/// Binds BUS and runs it on its own thread until shutdown is requested.
+pub fn spawn_bus(
+    context: &Context,
+    config: &BusConfig,
+    shutdown: &Shutdown,
+) -> JoinHandle<BusStats> {
+    let bus = Bus::bind(context, config).unwrap();
+    let bus_shutdown = shutdown.clone();
+    thread::spawn(move || bus.run(&bus_shutdown))
+}
+
+/// Runs instance `multiplier-0` of multiplication service on its own thread until shutdown
+/// is requested.
+pub fn spawn_service(
+    context: &Context,
+    config: &BusConfig,
+    shutdown: &Shutdown,
+) -> JoinHandle<()> {
+    let service_context = context.clone();
+    let service_config = config.clone();
+    let service_shutdown = shutdown.clone();
+    thread::spawn(move || {
+        BusService::new()
+            .named(VALUE_MULTIPLICATION_SERVICE_NAME)
+            .with_instance_id("multiplier-0")
+            .on(value_multiplication)
+            .run(&service_context, &service_config, &service_shutdown)
+            .unwrap();
+    })
+}
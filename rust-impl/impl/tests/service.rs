@@ -0,0 +1,64 @@
+mod common;
+
+use common::inproc_config;
+use common::spawn_bus;
+use rust_impl::BusClient;
+use rust_impl::BusService;
+use rust_impl::Shutdown;
+use std::thread;
+use std::time::Duration;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::ValueMultiplicationRequest;
+use zeromq_messages::messages::ValueMultiplicationResponse;
+use zmq::Context;
+
+const TIMEOUT: Duration = Duration::from_secs(5_u64);
+
+#[test]
+fn request_handled_by_service() {
+    let context = Context::new();
+    let shutdown = Shutdown::new();
+    let config = inproc_config("service");
+    let bus_join_handle = spawn_bus(&context, &config, &shutdown);
+
+    let service_context = context.clone();
+    let service_config = config.clone();
+    let service_shutdown = shutdown.clone();
+    let service_join_handle = thread::spawn(move || {
+        BusService::new()
+            .on::<ValueMultiplicationRequest, _>(|request| ValueMultiplicationResponse {
+                result: request.value * request.multiplier,
+            })
+            .run(&service_context, &service_config, &service_shutdown)
+            .unwrap();
+    });
+
+    let client = BusClient::connect(
+        &context,
+        &config,
+        &[ZeromqMessageKind::ValueMultiplicationResponse],
+    )
+    .unwrap();
+
+    // Give service time to subscribe, otherwise first request waits for resend.
+    thread::sleep(Duration::from_millis(200_u64));
+
+    for value in 0..10 {
+        let response = client
+            .request::<_, ValueMultiplicationResponse>(
+                ValueMultiplicationRequest {
+                    value,
+                    multiplier: 7,
+                },
+                TIMEOUT,
+            )
+            .unwrap();
+
+        assert_eq!(value * 7, response.result);
+    }
+
+    drop(client);
+    shutdown.request();
+    service_join_handle.join().unwrap();
+    let _ = bus_join_handle.join().unwrap();
+}
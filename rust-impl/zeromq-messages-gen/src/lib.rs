@@ -88,6 +88,8 @@ pub fn generate_zeromq_messages_kinds_enum(_input: TokenStream) -> TokenStream {
     assert_eq!(kinds.len(), titles.len());
 
     let mut variants = quote! {};
+    let mut all_variants = quote! {};
+    let mut names = quote! {};
     for current_index in 0..kinds.len() {
         let kind_literal = proc_macro2::Literal::u32_unsuffixed(kinds[current_index]);
 
@@ -95,8 +97,11 @@ pub fn generate_zeromq_messages_kinds_enum(_input: TokenStream) -> TokenStream {
         let camel_case_title_string = uppercase_first(camel_case_title_with_lower_case_first);
         let syn_title: syn::Variant = syn::parse_str(camel_case_title_string.as_str())
             .expect("failed to parse title into field");
+        let name = titles[current_index].as_str();
 
         variants.extend(quote! { #syn_title = #kind_literal, });
+        all_variants.extend(quote! { Self::#syn_title, });
+        names.extend(quote! { Self::#syn_title => #name, });
     }
 
     let output = quote! {
@@ -105,6 +110,19 @@ pub fn generate_zeromq_messages_kinds_enum(_input: TokenStream) -> TokenStream {
         pub enum ZeromqMessageKind {
             #variants
         }
+
+        impl ZeromqMessageKind {
+            /// Every kind of message, one per schema.
+            pub const ALL: &'static [Self] = &[#all_variants];
+
+            /// Name of kind taken from file name of its schema.
+            #[must_use]
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    #names
+                }
+            }
+        }
     };
 
     output.into()
@@ -159,6 +177,12 @@ pub fn generate_zeromq_messages_structs(_input: TokenStream) -> TokenStream {
 
         let path_to_schema =
             proc_macro2::Literal::string(paths_to_files[current_index].as_str());
+        let schema: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(&paths_to_files[current_index])
+                .expect("failed to read schema file"),
+        )
+        .expect("failed to parse schema file");
+        let has_optional_fields = has_optional_properties(&schema);
 
         output.extend(quote! {
             schemafy!(
@@ -168,6 +192,8 @@ pub fn generate_zeromq_messages_structs(_input: TokenStream) -> TokenStream {
 
             #[automatically_derived]
             impl<'de> ZeromqMessageTrait<'de> for #struct_name_ident {
+                const HAS_OPTIONAL_FIELDS: bool = #has_optional_fields;
+
                 fn kind() -> ZeromqMessageKind {
                     ZeromqMessageKind::#struct_name_ident
                 }
@@ -178,6 +204,85 @@ pub fn generate_zeromq_messages_structs(_input: TokenStream) -> TokenStream {
     output.into()
 }
 
+/// Procedural macro for generating enumeration which contains any of messages, one variant
+/// per schema, and dispatching decoding of message payload by message kind.
+#[proc_macro]
+pub fn generate_zeromq_messages_enum(_input: TokenStream) -> TokenStream {
+    let schemas_directory_entries_paths = get_schemas_directory_entries_paths(PATH_TO_SCHEMAS)
+        .expect("failed to get schemas directory entries path");
+    let regex = Regex::new(EXPECTED_SCHEMA_FILE_NAME_REGEX_STR)
+        .expect("failed to initialize expected schema file name regex");
+
+    let mut names: Vec<syn::Ident> = Vec::with_capacity(schemas_directory_entries_paths.len());
+    for path in &schemas_directory_entries_paths {
+        let file_name_string = path
+            .file_name()
+            .expect("failed to get file name OsStr from path")
+            .to_str()
+            .expect("failed to get file name as str from OsStr")
+            .to_string();
+
+        if (!path.is_dir())
+            && file_name_string.ends_with(SCHEMA_EXTENSION)
+            && regex.is_match(file_name_string.as_str())
+        {
+            let name_with_first_symbol_lower_case =
+                file_name_string.split('.').collect::<Vec<&'_ str>>()[1]
+                    .to_string()
+                    .to_camel_case();
+            let name_string = uppercase_first(name_with_first_symbol_lower_case);
+            names.push(
+                syn::parse_str(name_string.as_str())
+                    .expect("failed to parse message name str into ident"),
+            );
+        }
+    }
+
+    let output = quote! {
+        /// Any of messages described by schemas.
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum ZeromqMessage {
+            #( #names(#names), )*
+        }
+
+        impl ZeromqMessage {
+            #[must_use]
+            pub fn kind(&self) -> ZeromqMessageKind {
+                match self {
+                    #( Self::#names(_) => ZeromqMessageKind::#names, )*
+                }
+            }
+
+            /// Decodes payload into message of given kind.
+            pub fn decode_payload(
+                kind: ZeromqMessageKind,
+                format: PayloadFormat,
+                payload_bytes: &[u8],
+            ) -> Result<Self, MessageDecodeError> {
+                match kind {
+                    #(
+                        ZeromqMessageKind::#names => {
+                            decode_message_payload_with_format::<'_, #names>(format, payload_bytes)
+                                .map(Self::#names)
+                        }
+                    )*
+                }
+            }
+        }
+
+        #(
+            #[automatically_derived]
+            impl From<#names> for ZeromqMessage {
+                fn from(message: #names) -> Self {
+                    Self::#names(message)
+                }
+            }
+        )*
+    };
+
+    output.into()
+}
+
 fn get_schemas_directory_entries_paths<P: AsRef<Path>>(path: P) -> io::Result<Vec<PathBuf>> {
     let absolute_path = fs::canonicalize(path.as_ref())?;
     let mut schemas_directory_entries_paths = fs::read_dir(absolute_path)?
@@ -189,6 +294,29 @@ fn get_schemas_directory_entries_paths<P: AsRef<Path>>(path: P) -> io::Result<Ve
     Ok(schemas_directory_entries_paths)
 }
 
+/// Whether any object of schema, including nested ones, has properties which are not
+/// required. Schemafy skips serializing such fields when they are `None`.
+fn has_optional_properties(schema: &serde_json::Value) -> bool {
+    match schema {
+        serde_json::Value::Object(object) => {
+            let is_optional = |name: &String| {
+                !object
+                    .get("required")
+                    .and_then(serde_json::Value::as_array)
+                    .is_some_and(|required| required.iter().any(|value| value == name))
+            };
+            let has_own_optional_properties = object
+                .get("properties")
+                .and_then(serde_json::Value::as_object)
+                .is_some_and(|properties| properties.keys().any(is_optional));
+
+            has_own_optional_properties || object.values().any(has_optional_properties)
+        }
+        serde_json::Value::Array(values) => values.iter().any(has_optional_properties),
+        _ => false,
+    }
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn uppercase_first(string: String) -> String {
     let mut string_chars = string.chars().collect::<Vec<char>>();
@@ -1,4 +1,6 @@
+use crate::format::PayloadFormat;
 use crate::kind::ZeromqMessageKind;
+use crate::messages::ZeromqMessage;
 use crate::template::ZeromqMessageTrait;
 use bytes::Buf;
 use bytes::BufMut;
@@ -14,22 +16,91 @@ use uuid::Uuid;
 
 #[derive(Debug, thiserror::Error)]
 pub enum MessageDecodeError {
+    #[error("Unexpected magic bytes {0:#06x} received, message is not a BUS message")]
+    UnexpectedMagicBytes(u16),
+
+    #[error("Unsupported protocol version {0} received")]
+    UnsupportedProtocolVersion(u8),
+
+    #[error("Invalid header length {0} received")]
+    InvalidHeaderLength(u16),
+
+    #[error("Message is truncated, expected at least {expected} bytes, received {actual}")]
+    Truncated { expected: usize, actual: usize },
+
+    #[error("Message destination is truncated or is not valid utf-8")]
+    InvalidDestination,
+
     #[error("Unexpected message kind received")]
     UnexpectedZeromqMessageKind(#[from] TryFromPrimitiveError<ZeromqMessageKind>),
 
+    #[error("Unexpected payload format received")]
+    UnexpectedPayloadFormat(#[from] TryFromPrimitiveError<PayloadFormat>),
+
     #[error("Failed to parse json into struct")]
     CantParseJson(#[source] serde_json::Error),
+
+    #[cfg(feature = "msgpack")]
+    #[error("Failed to parse message pack into struct")]
+    CantParseMessagePack(#[source] rmp_serde::decode::Error),
+
+    #[cfg(feature = "bincode")]
+    #[error("Failed to parse bincode into struct")]
+    CantParseBincode(#[source] bincode::Error),
+}
+
+impl MessageDecodeError {
+    /// Name of error variant in snake case, which does not depend on error details, e.g.
+    /// to be used as a label of metrics.
+    #[must_use]
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            Self::UnexpectedMagicBytes(_) => "unexpected_magic_bytes",
+            Self::UnsupportedProtocolVersion(_) => "unsupported_protocol_version",
+            Self::InvalidHeaderLength(_) => "invalid_header_length",
+            Self::Truncated { .. } => "truncated",
+            Self::InvalidDestination => "invalid_destination",
+            Self::UnexpectedZeromqMessageKind(_) => "unexpected_zeromq_message_kind",
+            Self::UnexpectedPayloadFormat(_) => "unexpected_payload_format",
+            Self::CantParseJson(_) => "cant_parse_json",
+            #[cfg(feature = "msgpack")]
+            Self::CantParseMessagePack(_) => "cant_parse_message_pack",
+            #[cfg(feature = "bincode")]
+            Self::CantParseBincode(_) => "cant_parse_bincode",
+        }
+    }
 }
 
 impl Clone for MessageDecodeError {
     fn clone(&self) -> Self {
         match self {
+            Self::UnexpectedMagicBytes(magic) => Self::UnexpectedMagicBytes(*magic),
+            Self::UnsupportedProtocolVersion(version) => {
+                Self::UnsupportedProtocolVersion(*version)
+            }
+            Self::InvalidHeaderLength(length) => Self::InvalidHeaderLength(*length),
+            Self::Truncated { expected, actual } => Self::Truncated {
+                expected: *expected,
+                actual: *actual,
+            },
+            Self::InvalidDestination => Self::InvalidDestination,
             Self::UnexpectedZeromqMessageKind(TryFromPrimitiveError { number }) => {
                 Self::UnexpectedZeromqMessageKind(TryFromPrimitiveError { number: *number })
             }
+            Self::UnexpectedPayloadFormat(TryFromPrimitiveError { number }) => {
+                Self::UnexpectedPayloadFormat(TryFromPrimitiveError { number: *number })
+            }
             Self::CantParseJson(error) => {
                 Self::CantParseJson(serde_json::Error::custom(error.to_string()))
             }
+            #[cfg(feature = "msgpack")]
+            Self::CantParseMessagePack(error) => Self::CantParseMessagePack(
+                <rmp_serde::decode::Error as serde::de::Error>::custom(error.to_string()),
+            ),
+            #[cfg(feature = "bincode")]
+            Self::CantParseBincode(error) => {
+                Self::CantParseBincode(Box::new(bincode::ErrorKind::Custom(error.to_string())))
+            }
         }
     }
 }
@@ -38,50 +109,299 @@ impl PartialEq for MessageDecodeError {
     #[allow(clippy::match_wildcard_for_single_variants)]
     fn eq(&self, other: &MessageDecodeError) -> bool {
         match self {
+            Self::UnexpectedMagicBytes(magic) => match other {
+                Self::UnexpectedMagicBytes(other_magic) => magic == other_magic,
+                _ => false,
+            },
+            Self::UnsupportedProtocolVersion(version) => match other {
+                Self::UnsupportedProtocolVersion(other_version) => version == other_version,
+                _ => false,
+            },
+            Self::InvalidHeaderLength(length) => match other {
+                Self::InvalidHeaderLength(other_length) => length == other_length,
+                _ => false,
+            },
+            Self::Truncated { expected, actual } => match other {
+                Self::Truncated {
+                    expected: other_expected,
+                    actual: other_actual,
+                } => expected == other_expected && actual == other_actual,
+                _ => false,
+            },
+            Self::InvalidDestination => matches!(other, Self::InvalidDestination),
             Self::UnexpectedZeromqMessageKind(error) => match other {
                 Self::UnexpectedZeromqMessageKind(other_error) => error == other_error,
                 _ => false,
             },
+            Self::UnexpectedPayloadFormat(error) => match other {
+                Self::UnexpectedPayloadFormat(other_error) => error == other_error,
+                _ => false,
+            },
             Self::CantParseJson(error) => match other {
                 Self::CantParseJson(other_error) => {
                     error.to_string() == other_error.to_string()
                 }
                 _ => false,
             },
+            #[cfg(feature = "msgpack")]
+            Self::CantParseMessagePack(error) => match other {
+                Self::CantParseMessagePack(other_error) => {
+                    error.to_string() == other_error.to_string()
+                }
+                _ => false,
+            },
+            #[cfg(feature = "bincode")]
+            Self::CantParseBincode(error) => match other {
+                Self::CantParseBincode(other_error) => {
+                    error.to_string() == other_error.to_string()
+                }
+                _ => false,
+            },
         }
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum MessageEncodeError {
+    #[error("Message destination is {0} bytes long, maximum is 255 bytes")]
+    DestinationTooLong(usize),
+
+    #[error("Messages of kind {kind:?} have optional fields, which {format:?} can't encode")]
+    UnsupportedFormat {
+        kind: ZeromqMessageKind,
+        format: PayloadFormat,
+    },
+
     #[error("Failed to create json from message payload")]
     CantCreateJsonFromMessagePayload(#[source] serde_json::Error),
+
+    #[cfg(feature = "msgpack")]
+    #[error("Failed to create message pack from message payload")]
+    CantCreateMessagePackFromMessagePayload(#[source] rmp_serde::encode::Error),
+
+    #[cfg(feature = "bincode")]
+    #[error("Failed to create bincode from message payload")]
+    CantCreateBincodeFromMessagePayload(#[source] bincode::Error),
 }
 
 impl Clone for MessageEncodeError {
     fn clone(&self) -> Self {
         match self {
+            Self::DestinationTooLong(length) => Self::DestinationTooLong(*length),
+            Self::UnsupportedFormat { kind, format } => Self::UnsupportedFormat {
+                kind: *kind,
+                format: *format,
+            },
             Self::CantCreateJsonFromMessagePayload(error) => {
                 Self::CantCreateJsonFromMessagePayload(serde_json::Error::custom(
                     error.to_string(),
                 ))
             }
+            #[cfg(feature = "msgpack")]
+            Self::CantCreateMessagePackFromMessagePayload(error) => {
+                Self::CantCreateMessagePackFromMessagePayload(
+                    rmp_serde::encode::Error::custom(error.to_string()),
+                )
+            }
+            #[cfg(feature = "bincode")]
+            Self::CantCreateBincodeFromMessagePayload(error) => {
+                Self::CantCreateBincodeFromMessagePayload(Box::new(
+                    bincode::ErrorKind::Custom(error.to_string()),
+                ))
+            }
         }
     }
 }
 
 impl PartialEq for MessageEncodeError {
+    #[allow(clippy::match_wildcard_for_single_variants)]
     fn eq(&self, other: &MessageEncodeError) -> bool {
         match self {
+            Self::DestinationTooLong(length) => match other {
+                Self::DestinationTooLong(other_length) => length == other_length,
+                _ => false,
+            },
+            Self::UnsupportedFormat { kind, format } => match other {
+                Self::UnsupportedFormat {
+                    kind: other_kind,
+                    format: other_format,
+                } => kind == other_kind && format == other_format,
+                _ => false,
+            },
             Self::CantCreateJsonFromMessagePayload(error) => match other {
                 Self::CantCreateJsonFromMessagePayload(other_error) => {
                     error.to_string() == other_error.to_string()
                 }
+                _ => false,
+            },
+            #[cfg(feature = "msgpack")]
+            Self::CantCreateMessagePackFromMessagePayload(error) => match other {
+                Self::CantCreateMessagePackFromMessagePayload(other_error) => {
+                    error.to_string() == other_error.to_string()
+                }
+                _ => false,
+            },
+            #[cfg(feature = "bincode")]
+            Self::CantCreateBincodeFromMessagePayload(error) => match other {
+                Self::CantCreateBincodeFromMessagePayload(other_error) => {
+                    error.to_string() == other_error.to_string()
+                }
+                _ => false,
             },
         }
     }
 }
 
+//-----------------------------------------------------------------------------------------
+// Header
+//-----------------------------------------------------------------------------------------
+
+/// Header which goes before payload of every message.
+///
+/// Header length is written on the wire, so newer protocol versions can append fields to
+/// the header and older decoders of the same version will skip them. Destination of
+/// directed message is the only appended field for now, it is marked by
+/// `DESTINATION_FLAG`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct MessageHeader {
+    pub version: u8,
+    pub flags: u8,
+    pub kind: ZeromqMessageKind,
+    pub uuid: Uuid,
+    pub format: PayloadFormat,
+}
+
+impl MessageHeader {
+    /// Bytes which every message starts with, "ZM".
+    pub const MAGIC_BYTES: u16 = 0x5a4d;
+    /// Protocol version produced by this implementation.
+    pub const PROTOCOL_VERSION: u8 = 1;
+    /// Protocol versions which this implementation is able to decode.
+    pub const SUPPORTED_PROTOCOL_VERSIONS: &'static [u8] = &[1];
+    /// Length of header encoded by this implementation in bytes.
+    pub const LENGTH: usize = 27;
+    /// Flag which tells that header is followed by destination length and destination.
+    pub const DESTINATION_FLAG: u8 = 0b0000_0001;
+    /// Maximum length of destination in bytes.
+    pub const MAX_DESTINATION_LENGTH: usize = 255;
+
+    #[must_use]
+    pub fn new(kind: ZeromqMessageKind, uuid: Uuid, format: PayloadFormat) -> Self {
+        Self {
+            version: Self::PROTOCOL_VERSION,
+            flags: 0,
+            kind,
+            uuid,
+            format,
+        }
+    }
+
+    #[must_use]
+    pub fn has_destination(&self) -> bool {
+        self.flags & Self::DESTINATION_FLAG != 0
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn encode(&self, output_bytes: &mut Vec<u8>) {
+        self.encode_fields(self.flags, Self::LENGTH as u16, output_bytes);
+    }
+
+    /// Encodes header followed by destination of directed message.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn encode_with_destination(
+        &self,
+        destination: &str,
+        output_bytes: &mut Vec<u8>,
+    ) -> Result<(), MessageEncodeError> {
+        if destination.len() > Self::MAX_DESTINATION_LENGTH {
+            return Err(MessageEncodeError::DestinationTooLong(destination.len()));
+        }
+
+        self.encode_fields(
+            self.flags | Self::DESTINATION_FLAG,
+            (Self::LENGTH + 1 + destination.len()) as u16,
+            output_bytes,
+        );
+        output_bytes.put_u8(destination.len() as u8);
+        output_bytes.put_slice(destination.as_bytes());
+
+        Ok(())
+    }
+
+    fn encode_fields(&self, flags: u8, header_length: u16, output_bytes: &mut Vec<u8>) {
+        output_bytes.put_u16(Self::MAGIC_BYTES);
+        output_bytes.put_u8(self.version);
+        output_bytes.put_u8(flags);
+        output_bytes.put_u16(header_length);
+        output_bytes.put_u32(self.kind as u32);
+        output_bytes.put_u128(self.uuid.as_u128());
+        output_bytes.put_u8(self.format as u8);
+    }
+
+    /// Decodes header and returns it together with payload bytes which go after header.
+    pub fn decode(message_bytes: &[u8]) -> Result<(Self, &[u8]), MessageDecodeError> {
+        Self::decode_with_destination(message_bytes)
+            .map(|(header, _, payload_bytes)| (header, payload_bytes))
+    }
+
+    /// Decodes header together with destination of directed message, if there is any, and
+    /// returns payload bytes which go after header.
+    pub fn decode_with_destination(
+        message_bytes: &[u8],
+    ) -> Result<(Self, Option<&str>, &[u8]), MessageDecodeError> {
+        ensure_length(message_bytes, Self::LENGTH)?;
+
+        let mut message_bytes_slice = message_bytes;
+
+        let magic = message_bytes_slice.get_u16();
+        if magic != Self::MAGIC_BYTES {
+            return Err(MessageDecodeError::UnexpectedMagicBytes(magic));
+        }
+
+        let version = message_bytes_slice.get_u8();
+        if !Self::SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+            return Err(MessageDecodeError::UnsupportedProtocolVersion(version));
+        }
+
+        let flags = message_bytes_slice.get_u8();
+
+        let header_length = message_bytes_slice.get_u16();
+        if usize::from(header_length) < Self::LENGTH {
+            return Err(MessageDecodeError::InvalidHeaderLength(header_length));
+        }
+
+        ensure_length(message_bytes, usize::from(header_length))?;
+
+        let kind = ZeromqMessageKind::try_from(message_bytes_slice.get_u32())
+            .map_err(MessageDecodeError::UnexpectedZeromqMessageKind)?;
+
+        let uuid = Uuid::from_u128(message_bytes_slice.get_u128());
+
+        let format = PayloadFormat::try_from(message_bytes_slice.get_u8())
+            .map_err(MessageDecodeError::UnexpectedPayloadFormat)?;
+
+        let header = Self {
+            version,
+            flags,
+            kind,
+            uuid,
+            format,
+        };
+
+        let (appended_bytes, payload_bytes) =
+            message_bytes_slice.split_at(usize::from(header_length) - Self::LENGTH);
+
+        let destination = if header.has_destination() {
+            Some(decode_destination(appended_bytes)?)
+        } else {
+            None
+        };
+
+        // The rest of appended bytes are fields of newer revisions of the protocol.
+        Ok((header, destination, payload_bytes))
+    }
+}
+
 //-----------------------------------------------------------------------------------------
 // Encode
 //-----------------------------------------------------------------------------------------
@@ -89,19 +409,72 @@ impl PartialEq for MessageEncodeError {
 pub fn encode_message<'de, P: ZeromqMessageTrait<'de>>(
     uuid: Uuid,
     payload: P,
+) -> Result<Vec<u8>, MessageEncodeError> {
+    encode_message_with_format(uuid, payload, PayloadFormat::default())
+}
+
+pub fn encode_message_with_format<'de, P: ZeromqMessageTrait<'de>>(
+    uuid: Uuid,
+    payload: P,
+    format: PayloadFormat,
 ) -> Result<Vec<u8>, MessageEncodeError> {
     let mut output_message_bytes: Vec<u8> = Vec::default();
 
-    output_message_bytes.put_u32(<P as ZeromqMessageTrait<'de>>::kind() as u32);
+    MessageHeader::new(<P as ZeromqMessageTrait<'de>>::kind(), uuid, format)
+        .encode(&mut output_message_bytes);
 
-    output_message_bytes.put_u128(uuid.as_u128());
+    encode_payload(payload, format, output_message_bytes)
+}
 
-    let payload_string = serde_json::to_value(payload)
-        .map_err(MessageEncodeError::CantCreateJsonFromMessagePayload)?
-        .to_string();
+/// Encodes message which BUS delivers to one instance of `destination` service instead of
+/// publishing it.
+pub fn encode_directed_message<'de, P: ZeromqMessageTrait<'de>>(
+    uuid: Uuid,
+    destination: &str,
+    payload: P,
+) -> Result<Vec<u8>, MessageEncodeError> {
+    encode_directed_message_with_format(uuid, destination, payload, PayloadFormat::default())
+}
 
-    for byte in payload_string.as_bytes() {
-        output_message_bytes.put_u8(*byte);
+pub fn encode_directed_message_with_format<'de, P: ZeromqMessageTrait<'de>>(
+    uuid: Uuid,
+    destination: &str,
+    payload: P,
+    format: PayloadFormat,
+) -> Result<Vec<u8>, MessageEncodeError> {
+    let mut output_message_bytes: Vec<u8> = Vec::default();
+
+    MessageHeader::new(<P as ZeromqMessageTrait<'de>>::kind(), uuid, format)
+        .encode_with_destination(destination, &mut output_message_bytes)?;
+
+    encode_payload(payload, format, output_message_bytes)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn encode_payload<'de, P: ZeromqMessageTrait<'de>>(
+    payload: P,
+    format: PayloadFormat,
+    mut output_message_bytes: Vec<u8>,
+) -> Result<Vec<u8>, MessageEncodeError> {
+    match format {
+        PayloadFormat::Json => serde_json::to_writer(&mut output_message_bytes, &payload)
+            .map_err(MessageEncodeError::CantCreateJsonFromMessagePayload)?,
+        #[cfg(feature = "msgpack")]
+        PayloadFormat::MessagePack => {
+            rmp_serde::encode::write_named(&mut output_message_bytes, &payload)
+                .map_err(MessageEncodeError::CantCreateMessagePackFromMessagePayload)?;
+        }
+        // Bincode expects every field on decoding, so skipped ones can't be decoded back.
+        #[cfg(feature = "bincode")]
+        PayloadFormat::Bincode if P::HAS_OPTIONAL_FIELDS => {
+            return Err(MessageEncodeError::UnsupportedFormat {
+                kind: P::kind(),
+                format,
+            });
+        }
+        #[cfg(feature = "bincode")]
+        PayloadFormat::Bincode => bincode::serialize_into(&mut output_message_bytes, &payload)
+            .map_err(MessageEncodeError::CantCreateBincodeFromMessagePayload)?,
     }
 
     Ok(output_message_bytes)
@@ -111,35 +484,107 @@ pub fn encode_message<'de, P: ZeromqMessageTrait<'de>>(
 // Decode
 //-----------------------------------------------------------------------------------------
 
+/// Decodes message header and returns message kind together with remaining message bytes,
+/// which are uuid, payload format and payload.
 #[allow(clippy::needless_pass_by_value)]
 pub fn decode_message_kind(
     message_bytes: Vec<u8>,
 ) -> Result<(ZeromqMessageKind, Vec<u8>), MessageDecodeError> {
-    let mut message_bytes_slice = message_bytes.as_slice();
+    let (header, payload_bytes) = MessageHeader::decode(message_bytes.as_slice())?;
+
+    let mut remaining_bytes: Vec<u8> =
+        Vec::with_capacity(size_of::<u128>() + size_of::<u8>() + payload_bytes.len());
+    remaining_bytes.put_u128(header.uuid.as_u128());
+    remaining_bytes.put_u8(header.format as u8);
+    remaining_bytes.extend_from_slice(payload_bytes);
 
-    // Grab message kind.
-    let kind = ZeromqMessageKind::try_from(message_bytes_slice.get_u32())
-        .map_err(MessageDecodeError::UnexpectedZeromqMessageKind)?;
+    Ok((header.kind, remaining_bytes))
+}
 
-    Ok((kind, message_bytes_slice.chunk().to_vec()))
+/// Reads message kind without consuming message bytes.
+pub fn peek_message_kind(
+    message_bytes: &[u8],
+) -> Result<ZeromqMessageKind, MessageDecodeError> {
+    MessageHeader::decode(message_bytes).map(|(header, _)| header.kind)
 }
 
-#[must_use]
 #[allow(clippy::needless_pass_by_value)]
-pub fn decode_message_uuid(message_bytes_without_kind: Vec<u8>) -> (Uuid, Vec<u8>) {
+pub fn decode_message_uuid(
+    message_bytes_without_kind: Vec<u8>,
+) -> Result<(Uuid, Vec<u8>), MessageDecodeError> {
+    ensure_length(message_bytes_without_kind.as_slice(), size_of::<u128>())?;
+
     let mut message_bytes_slice_without_kind = message_bytes_without_kind.as_slice();
     let uuid_bytes = message_bytes_slice_without_kind.get_u128();
-    (
+    Ok((
         Uuid::from_u128(uuid_bytes),
         message_bytes_slice_without_kind.chunk().to_vec(),
-    )
+    ))
 }
 
 pub fn decode_message_payload<'de, T: ZeromqMessageTrait<'de>>(
     message_bytes_without_kind_and_uuid: &'de [u8],
 ) -> Result<T, MessageDecodeError> {
-    serde_json::from_slice(message_bytes_without_kind_and_uuid)
-        .map_err(MessageDecodeError::CantParseJson)
+    ensure_length(message_bytes_without_kind_and_uuid, size_of::<u8>())?;
+
+    let mut message_bytes_slice = message_bytes_without_kind_and_uuid;
+
+    // Grab payload format, remaining bytes are the payload itself.
+    let format = PayloadFormat::try_from(message_bytes_slice.get_u8())
+        .map_err(MessageDecodeError::UnexpectedPayloadFormat)?;
+
+    decode_message_payload_with_format(format, message_bytes_slice)
+}
+
+/// Decodes payload bytes which go right after message header.
+pub fn decode_message_payload_with_format<'de, T: ZeromqMessageTrait<'de>>(
+    format: PayloadFormat,
+    payload_bytes: &'de [u8],
+) -> Result<T, MessageDecodeError> {
+    match format {
+        PayloadFormat::Json => {
+            serde_json::from_slice(payload_bytes).map_err(MessageDecodeError::CantParseJson)
+        }
+        #[cfg(feature = "msgpack")]
+        PayloadFormat::MessagePack => rmp_serde::from_slice(payload_bytes)
+            .map_err(MessageDecodeError::CantParseMessagePack),
+        #[cfg(feature = "bincode")]
+        PayloadFormat::Bincode => {
+            bincode::deserialize(payload_bytes).map_err(MessageDecodeError::CantParseBincode)
+        }
+    }
+}
+
+/// Decodes whole message at once and returns its uuid together with typed message.
+pub fn decode_message(
+    message_bytes: &[u8],
+) -> Result<(Uuid, ZeromqMessage), MessageDecodeError> {
+    let (header, payload_bytes) = MessageHeader::decode(message_bytes)?;
+
+    ZeromqMessage::decode_payload(header.kind, header.format, payload_bytes)
+        .map(|message| (header.uuid, message))
+}
+
+fn decode_destination(appended_bytes: &[u8]) -> Result<&str, MessageDecodeError> {
+    let (destination_length, destination_bytes) = appended_bytes
+        .split_first()
+        .ok_or(MessageDecodeError::InvalidDestination)?;
+
+    destination_bytes
+        .get(..usize::from(*destination_length))
+        .and_then(|destination_bytes| std::str::from_utf8(destination_bytes).ok())
+        .ok_or(MessageDecodeError::InvalidDestination)
+}
+
+fn ensure_length(message_bytes: &[u8], expected: usize) -> Result<(), MessageDecodeError> {
+    if message_bytes.len() < expected {
+        return Err(MessageDecodeError::Truncated {
+            expected,
+            actual: message_bytes.len(),
+        });
+    }
+
+    Ok(())
 }
 
 //-----------------------------------------------------------------------------------------
@@ -148,14 +593,28 @@ pub fn decode_message_payload<'de, T: ZeromqMessageTrait<'de>>(
 
 #[cfg(test)]
 mod tests {
+    use crate::codec::decode_message;
     use crate::codec::decode_message_kind;
     use crate::codec::decode_message_payload;
     use crate::codec::decode_message_uuid;
+    use crate::codec::encode_directed_message;
     use crate::codec::encode_message;
+    use crate::codec::encode_message_with_format;
+    use crate::codec::peek_message_kind;
     use crate::codec::MessageDecodeError;
     use crate::codec::MessageEncodeError;
+    use crate::codec::MessageHeader;
+    use crate::format::PayloadFormat;
     use crate::kind::ZeromqMessageKind;
+    use crate::messages::BusRegistry;
+    use crate::messages::BusRegistryItemInstances;
+    use crate::messages::BusRegistryQuery;
+    use crate::messages::BusSubscriptions;
+    use crate::messages::BusSubscriptionsItemPublishers;
+    use crate::messages::BusSubscriptionsQuery;
     use crate::messages::ValueMultiplicationRequest;
+    use crate::messages::ValueMultiplicationResponse;
+    use crate::messages::ZeromqMessage;
     use crate::template::ZeromqMessageTrait;
     use num_enum::TryFromPrimitiveError;
     use std::convert::From;
@@ -188,6 +647,11 @@ mod tests {
             encode_message(uuid, payload.clone()).expect("failed to encode message"),
         );
 
+        assert_eq!(
+            Ok(<ValueMultiplicationRequest as ZeromqMessageTrait>::kind()),
+            peek_message_kind(&encoded_zeromq_message)
+        );
+
         let (decoded_kind, remaining_bytes) =
             decode_message_kind(encoded_zeromq_message.to_vec())
                 .expect("failed to decode message kind");
@@ -196,7 +660,8 @@ mod tests {
             decoded_kind
         );
 
-        let (decoded_uuid, remaining_bytes) = decode_message_uuid(remaining_bytes);
+        let (decoded_uuid, remaining_bytes) =
+            decode_message_uuid(remaining_bytes).expect("failed to decode message uuid");
         assert_eq!(uuid, decoded_uuid);
 
         let decoded_payload = decode_message_payload(remaining_bytes.as_slice())
@@ -204,6 +669,431 @@ mod tests {
         assert_eq!(payload, decoded_payload);
     }
 
+    fn basics_with_format(format: PayloadFormat) {
+        let payload = ValueMultiplicationRequest {
+            value: -7,
+            multiplier: 1_024,
+        };
+        let uuid = Uuid::new_v4();
+
+        let encoded_zeromq_message = Message::from(
+            encode_message_with_format(uuid, payload.clone(), format)
+                .expect("failed to encode message"),
+        );
+
+        let (decoded_kind, remaining_bytes) =
+            decode_message_kind(encoded_zeromq_message.to_vec())
+                .expect("failed to decode message kind");
+        assert_eq!(
+            <ValueMultiplicationRequest as ZeromqMessageTrait>::kind(),
+            decoded_kind
+        );
+
+        let (decoded_uuid, remaining_bytes) =
+            decode_message_uuid(remaining_bytes).expect("failed to decode message uuid");
+        assert_eq!(uuid, decoded_uuid);
+        assert_eq!(Some(&(format as u8)), remaining_bytes.first());
+
+        let decoded_payload = decode_message_payload(remaining_bytes.as_slice())
+            .expect("failed to decode message payload");
+        assert_eq!(payload, decoded_payload);
+    }
+
+    #[test]
+    fn basics_json() {
+        basics_with_format(PayloadFormat::Json);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn basics_message_pack() {
+        basics_with_format(PayloadFormat::MessagePack);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn basics_bincode() {
+        basics_with_format(PayloadFormat::Bincode);
+    }
+
+    fn round_trip_with_format<P>(payload: P, format: PayloadFormat)
+    where
+        P: for<'de> ZeromqMessageTrait<'de> + Into<ZeromqMessage>,
+    {
+        let uuid = Uuid::new_v4();
+        let message_bytes = encode_message_with_format(uuid, payload.clone(), format)
+            .expect("failed to encode message");
+
+        assert_eq!(
+            Ok((uuid, payload.into())),
+            decode_message(message_bytes.as_slice())
+        );
+    }
+
+    fn bus_registry() -> BusRegistry {
+        BusRegistry {
+            instances: vec![BusRegistryItemInstances {
+                name: "multiplier".to_string(),
+                instance_id: "multiplier-1".to_string(),
+                kinds: vec![1, 2],
+            }],
+        }
+    }
+
+    fn bus_subscriptions() -> BusSubscriptions {
+        BusSubscriptions {
+            publishers: vec![BusSubscriptionsItemPublishers {
+                endpoint: "tcp://127.0.0.1:5557".to_string(),
+                kinds: vec![2],
+            }],
+        }
+    }
+
+    fn bus_messages_with_format(format: PayloadFormat) {
+        round_trip_with_format(BusRegistryQuery { kind: Some(1) }, format);
+        round_trip_with_format(BusRegistryQuery { kind: None }, format);
+        round_trip_with_format(bus_registry(), format);
+        round_trip_with_format(BusSubscriptionsQuery {}, format);
+        round_trip_with_format(bus_subscriptions(), format);
+    }
+
+    #[test]
+    fn bus_messages_json() {
+        bus_messages_with_format(PayloadFormat::Json);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn bus_messages_message_pack() {
+        bus_messages_with_format(PayloadFormat::MessagePack);
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bus_messages_bincode() {
+        round_trip_with_format(bus_registry(), PayloadFormat::Bincode);
+        round_trip_with_format(BusSubscriptionsQuery {}, PayloadFormat::Bincode);
+        round_trip_with_format(bus_subscriptions(), PayloadFormat::Bincode);
+
+        for kind in [Some(1), None] {
+            assert_eq!(
+                Err(MessageEncodeError::UnsupportedFormat {
+                    kind: ZeromqMessageKind::BusRegistryQuery,
+                    format: PayloadFormat::Bincode,
+                }),
+                encode_message_with_format(
+                    Uuid::new_v4(),
+                    BusRegistryQuery { kind },
+                    PayloadFormat::Bincode
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn decode_typed_message() {
+        let request = ValueMultiplicationRequest {
+            value: 6,
+            multiplier: 7,
+        };
+        let response = ValueMultiplicationResponse { result: 42 };
+        let uuid = Uuid::new_v4();
+
+        let request_bytes =
+            encode_message(uuid, request.clone()).expect("failed to encode request");
+        let response_bytes =
+            encode_message(uuid, response.clone()).expect("failed to encode response");
+
+        assert_eq!(
+            Ok((
+                uuid,
+                ZeromqMessage::ValueMultiplicationRequest(request.clone())
+            )),
+            decode_message(&request_bytes)
+        );
+        assert_eq!(
+            Ok((uuid, ZeromqMessage::from(response))),
+            decode_message(&response_bytes)
+        );
+        assert_eq!(
+            ZeromqMessageKind::ValueMultiplicationRequest,
+            ZeromqMessage::from(request).kind()
+        );
+    }
+
+    #[test]
+    fn decode_typed_message_with_mismatched_payload() {
+        let mut message_bytes = encode_message(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest {
+                value: 6,
+                multiplier: 7,
+            },
+        )
+        .expect("failed to encode message");
+
+        // Pretend that request payload is a response.
+        message_bytes[9] = ZeromqMessageKind::ValueMultiplicationResponse as u8;
+
+        assert!(matches!(
+            decode_message(&message_bytes),
+            Err(MessageDecodeError::CantParseJson(_))
+        ));
+    }
+
+    #[test]
+    fn unexpected_payload_format() {
+        let payload_bytes = [u8::MAX, b'{', b'}'];
+
+        assert_eq!(
+            Err(MessageDecodeError::UnexpectedPayloadFormat(
+                TryFromPrimitiveError { number: u8::MAX }
+            )),
+            decode_message_payload::<'_, ValueMultiplicationRequest>(&payload_bytes)
+        );
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = MessageHeader::new(
+            ZeromqMessageKind::ValueMultiplicationResponse,
+            Uuid::new_v4(),
+            PayloadFormat::Json,
+        );
+        let mut message_bytes = Vec::default();
+        header.encode(&mut message_bytes);
+        message_bytes.extend_from_slice(b"{}");
+
+        assert_eq!(MessageHeader::LENGTH + 2, message_bytes.len());
+        assert_eq!(
+            Ok((header, &b"{}"[..])),
+            MessageHeader::decode(message_bytes.as_slice())
+        );
+    }
+
+    #[test]
+    fn header_unexpected_magic_bytes() {
+        let mut message_bytes = encode_message(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest {
+                value: 1,
+                multiplier: 2,
+            },
+        )
+        .expect("failed to encode message");
+        message_bytes[0] = b'{';
+
+        assert_eq!(
+            Err(MessageDecodeError::UnexpectedMagicBytes(0x7b4d)),
+            MessageHeader::decode(message_bytes.as_slice())
+        );
+    }
+
+    #[test]
+    fn header_unsupported_protocol_version() {
+        let mut message_bytes = encode_message(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest {
+                value: 1,
+                multiplier: 2,
+            },
+        )
+        .expect("failed to encode message");
+        message_bytes[2] = MessageHeader::PROTOCOL_VERSION + 1;
+
+        assert_eq!(
+            Err(MessageDecodeError::UnsupportedProtocolVersion(
+                MessageHeader::PROTOCOL_VERSION + 1
+            )),
+            decode_message_kind(message_bytes)
+        );
+    }
+
+    #[test]
+    fn header_invalid_length() {
+        let mut message_bytes = encode_message(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest {
+                value: 1,
+                multiplier: 2,
+            },
+        )
+        .expect("failed to encode message");
+        message_bytes[4] = 0;
+        message_bytes[5] = 3;
+
+        assert_eq!(
+            Err(MessageDecodeError::InvalidHeaderLength(3)),
+            MessageHeader::decode(message_bytes.as_slice())
+        );
+    }
+
+    #[test]
+    fn header_appended_fields_are_skipped() {
+        let payload = ValueMultiplicationRequest {
+            value: 3,
+            multiplier: 4,
+        };
+        let uuid = Uuid::new_v4();
+        let message_bytes = encode_message(uuid, payload.clone()).expect("failed to encode");
+
+        // Emulate header produced by newer revision of protocol with one extra field.
+        let mut extended_message_bytes = message_bytes[..MessageHeader::LENGTH].to_vec();
+        extended_message_bytes[5] += 4;
+        extended_message_bytes.extend_from_slice(&[0xff; 4]);
+        extended_message_bytes.extend_from_slice(&message_bytes[MessageHeader::LENGTH..]);
+
+        let (decoded_kind, remaining_bytes) =
+            decode_message_kind(extended_message_bytes).expect("failed to decode kind");
+        assert_eq!(ZeromqMessageKind::ValueMultiplicationRequest, decoded_kind);
+
+        let (decoded_uuid, remaining_bytes) =
+            decode_message_uuid(remaining_bytes).expect("failed to decode message uuid");
+        assert_eq!(uuid, decoded_uuid);
+
+        assert_eq!(
+            Ok(payload),
+            decode_message_payload(remaining_bytes.as_slice())
+        );
+    }
+
+    #[test]
+    fn header_with_destination() {
+        let payload = ValueMultiplicationRequest {
+            value: 3,
+            multiplier: 4,
+        };
+        let uuid = Uuid::new_v4();
+        let message_bytes = encode_directed_message(uuid, "multiplier", payload.clone())
+            .expect("failed to encode");
+
+        let (header, destination, payload_bytes) =
+            MessageHeader::decode_with_destination(message_bytes.as_slice())
+                .expect("failed to decode header");
+        assert_eq!(MessageHeader::DESTINATION_FLAG, header.flags);
+        assert_eq!(uuid, header.uuid);
+        assert_eq!(Some("multiplier"), destination);
+        assert_eq!(
+            &message_bytes[MessageHeader::LENGTH + 1 + "multiplier".len()..],
+            payload_bytes
+        );
+
+        // Decoders which do not care about destination skip it as any appended field.
+        assert_eq!(
+            Ok((uuid, ZeromqMessage::from(payload))),
+            decode_message(message_bytes.as_slice())
+        );
+    }
+
+    #[test]
+    fn header_invalid_destination() {
+        let mut message_bytes =
+            encode_message(Uuid::new_v4(), ValueMultiplicationResponse { result: 1 })
+                .expect("failed to encode");
+        message_bytes[3] = MessageHeader::DESTINATION_FLAG;
+
+        assert_eq!(
+            Err(MessageDecodeError::InvalidDestination),
+            MessageHeader::decode_with_destination(message_bytes.as_slice())
+        );
+    }
+
+    #[test]
+    fn variant_name() {
+        assert_eq!(
+            "invalid_destination",
+            MessageDecodeError::InvalidDestination.variant_name()
+        );
+        assert_eq!(
+            "truncated",
+            MessageDecodeError::Truncated {
+                expected: 1,
+                actual: 0
+            }
+            .variant_name()
+        );
+    }
+
+    #[test]
+    fn destination_too_long() {
+        let destination = "a".repeat(MessageHeader::MAX_DESTINATION_LENGTH + 1);
+
+        assert_eq!(
+            Err(MessageEncodeError::DestinationTooLong(256)),
+            encode_directed_message(
+                Uuid::new_v4(),
+                &destination,
+                ValueMultiplicationResponse { result: 1 }
+            )
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let message_bytes = encode_message(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest {
+                value: 1,
+                multiplier: 2,
+            },
+        )
+        .expect("failed to encode message");
+
+        assert_eq!(
+            Err(MessageDecodeError::Truncated {
+                expected: MessageHeader::LENGTH,
+                actual: 0,
+            }),
+            decode_message_kind(Vec::default())
+        );
+
+        assert_eq!(
+            Err(MessageDecodeError::Truncated {
+                expected: MessageHeader::LENGTH,
+                actual: MessageHeader::LENGTH - 1,
+            }),
+            peek_message_kind(&message_bytes[..MessageHeader::LENGTH - 1])
+        );
+
+        assert_eq!(
+            Err(MessageDecodeError::Truncated {
+                expected: 16,
+                actual: 3,
+            }),
+            decode_message_uuid(vec![1, 2, 3])
+        );
+
+        assert_eq!(
+            Err(MessageDecodeError::Truncated {
+                expected: 1,
+                actual: 0,
+            }),
+            decode_message_payload::<'_, ValueMultiplicationRequest>(&[])
+        );
+    }
+
+    #[test]
+    fn header_longer_than_message() {
+        let mut message_bytes = encode_message(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest {
+                value: 1,
+                multiplier: 2,
+            },
+        )
+        .expect("failed to encode message");
+        message_bytes[4] = 0xff;
+        let actual = message_bytes.len();
+
+        assert_eq!(
+            Err(MessageDecodeError::Truncated {
+                expected: 0xff00 + MessageHeader::LENGTH,
+                actual,
+            }),
+            MessageHeader::decode(message_bytes.as_slice())
+        );
+    }
+
     #[test]
     fn encode_error_eq() {
         assert_eq!(
@@ -253,6 +1143,48 @@ mod tests {
             }),
             MessageDecodeError::CantParseJson(get_json_syntax_error())
         );
+
+        assert_eq!(
+            MessageDecodeError::UnexpectedPayloadFormat(TryFromPrimitiveError { number: 7 }),
+            MessageDecodeError::UnexpectedPayloadFormat(TryFromPrimitiveError { number: 7 })
+        );
+
+        assert_ne!(
+            MessageDecodeError::UnexpectedPayloadFormat(TryFromPrimitiveError { number: 7 }),
+            MessageDecodeError::UnexpectedPayloadFormat(TryFromPrimitiveError { number: 8 })
+        );
+
+        assert_eq!(
+            MessageDecodeError::UnsupportedProtocolVersion(2),
+            MessageDecodeError::UnsupportedProtocolVersion(2)
+        );
+
+        assert_ne!(
+            MessageDecodeError::UnsupportedProtocolVersion(2),
+            MessageDecodeError::UnexpectedMagicBytes(2)
+        );
+
+        assert_eq!(
+            MessageDecodeError::Truncated {
+                expected: 16,
+                actual: 3
+            },
+            MessageDecodeError::Truncated {
+                expected: 16,
+                actual: 3
+            }
+        );
+
+        assert_ne!(
+            MessageDecodeError::Truncated {
+                expected: 16,
+                actual: 3
+            },
+            MessageDecodeError::Truncated {
+                expected: 16,
+                actual: 4
+            }
+        );
     }
 
     #[test]
@@ -269,5 +1201,18 @@ mod tests {
                 number: ZeromqMessageKind::ValueMultiplicationResponse as u32,
             });
         assert_eq!(decode_error_kind, decode_error_kind.clone());
+
+        let decode_error_format =
+            MessageDecodeError::UnexpectedPayloadFormat(TryFromPrimitiveError { number: 7 });
+        assert_eq!(decode_error_format, decode_error_format.clone());
+
+        let decode_error_version = MessageDecodeError::UnsupportedProtocolVersion(2);
+        assert_eq!(decode_error_version, decode_error_version.clone());
+
+        let decode_error_truncated = MessageDecodeError::Truncated {
+            expected: 16,
+            actual: 3,
+        };
+        assert_eq!(decode_error_truncated, decode_error_truncated.clone());
     }
 }
@@ -0,0 +1,14 @@
+use num_enum::TryFromPrimitive;
+
+/// Format in which message payload is serialized. Recorded in every message right after
+/// uuid, so decoders pick the right deserializer automatically.
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, TryFromPrimitive)]
+pub enum PayloadFormat {
+    #[default]
+    Json = 0,
+    #[cfg(feature = "msgpack")]
+    MessagePack = 1,
+    #[cfg(feature = "bincode")]
+    Bincode = 2,
+}
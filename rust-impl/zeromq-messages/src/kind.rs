@@ -1,4 +1,84 @@
 use num_enum::TryFromPrimitive;
+use serde::Deserialize;
+use serde::Deserializer;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 use zeromq_messages_gen::generate_zeromq_messages_kinds_enum;
 
 generate_zeromq_messages_kinds_enum!();
+
+impl ZeromqMessageKind {
+    /// Number of kind as it is written in message header and in integer fields of
+    /// messages which refer to kinds.
+    #[must_use]
+    pub fn number(self) -> i64 {
+        i64::from(self as u32)
+    }
+
+    /// Kind with given number, `None` if there is no such kind.
+    #[must_use]
+    pub fn from_number(number: i64) -> Option<Self> {
+        u32::try_from(number)
+            .ok()
+            .and_then(|number| Self::try_from(number).ok())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
+#[error("Unknown message kind {0}")]
+pub struct ZeromqMessageKindParseError(String);
+
+impl fmt::Display for ZeromqMessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ZeromqMessageKind {
+    type Err = ZeromqMessageKindParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| ZeromqMessageKindParseError(s.to_owned()))
+    }
+}
+
+impl<'de> Deserialize<'de> for ZeromqMessageKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kind::ZeromqMessageKind;
+    use crate::kind::ZeromqMessageKindParseError;
+
+    #[test]
+    fn parse() {
+        for kind in ZeromqMessageKind::ALL {
+            assert_eq!(Ok(*kind), kind.to_string().parse());
+        }
+        assert_eq!(
+            "value-multiplication-request",
+            ZeromqMessageKind::ValueMultiplicationRequest.as_str()
+        );
+        assert_eq!(
+            Some(ZeromqMessageKind::ValueMultiplicationResponse),
+            ZeromqMessageKind::from_number(
+                ZeromqMessageKind::ValueMultiplicationResponse.number()
+            )
+        );
+        assert_eq!(None, ZeromqMessageKind::from_number(-1));
+        assert_eq!(
+            Err(ZeromqMessageKindParseError(String::from("unknown"))),
+            "unknown".parse::<ZeromqMessageKind>()
+        );
+    }
+}
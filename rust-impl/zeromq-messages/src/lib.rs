@@ -23,6 +23,8 @@
 #![allow(clippy::missing_errors_doc)]
 
 pub mod codec;
+pub mod format;
 pub mod kind;
 pub mod messages;
 pub mod template;
+pub mod view;
@@ -1,8 +1,15 @@
+use crate::codec::decode_message_payload_with_format;
+use crate::codec::MessageDecodeError;
+use crate::format::PayloadFormat;
 use crate::kind::ZeromqMessageKind;
 use crate::template::ZeromqMessageTrait;
 use schemafy::schemafy;
 use serde::Deserialize;
 use serde::Serialize;
+use std::convert::From;
+use zeromq_messages_gen::generate_zeromq_messages_enum;
 use zeromq_messages_gen::generate_zeromq_messages_structs;
 
 generate_zeromq_messages_structs!();
+
+generate_zeromq_messages_enum!();
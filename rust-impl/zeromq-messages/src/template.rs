@@ -8,6 +8,10 @@ use std::fmt;
 pub trait ZeromqMessageTrait<'de>:
     Clone + PartialEq + fmt::Debug + Serialize + Deserialize<'de> + Sized
 {
+    /// Whether message has fields which are skipped when they are not set. Such messages
+    /// are not encoded by formats which are not self-describing, e.g. bincode.
+    const HAS_OPTIONAL_FIELDS: bool = false;
+
     fn kind() -> ZeromqMessageKind;
 
     fn serialize(self) -> Value {
@@ -0,0 +1,183 @@
+use crate::codec::decode_message_payload_with_format;
+use crate::codec::MessageDecodeError;
+use crate::codec::MessageHeader;
+use crate::format::PayloadFormat;
+use crate::kind::ZeromqMessageKind;
+use crate::messages::ZeromqMessage;
+use crate::template::ZeromqMessageTrait;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+/// Borrowing view over encoded message. Header is validated once on creation, after that
+/// message fields are available without copying or allocating.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MessageView<'a> {
+    header: MessageHeader,
+    destination: Option<&'a str>,
+    payload_bytes: &'a [u8],
+}
+
+impl<'a> MessageView<'a> {
+    pub fn new(message_bytes: &'a [u8]) -> Result<Self, MessageDecodeError> {
+        let (header, destination, payload_bytes) =
+            MessageHeader::decode_with_destination(message_bytes)?;
+
+        Ok(Self {
+            header,
+            destination,
+            payload_bytes,
+        })
+    }
+
+    #[must_use]
+    pub fn header(&self) -> &MessageHeader {
+        &self.header
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> ZeromqMessageKind {
+        self.header.kind
+    }
+
+    #[must_use]
+    pub fn uuid(&self) -> Uuid {
+        self.header.uuid
+    }
+
+    #[must_use]
+    pub fn format(&self) -> PayloadFormat {
+        self.header.format
+    }
+
+    /// Name of service to which directed message is addressed, `None` for messages which
+    /// are published to all subscribers.
+    #[must_use]
+    pub fn destination(&self) -> Option<&'a str> {
+        self.destination
+    }
+
+    #[must_use]
+    pub fn payload_bytes(&self) -> &'a [u8] {
+        self.payload_bytes
+    }
+
+    /// Decodes payload into struct of expected message.
+    pub fn decode_payload<T: ZeromqMessageTrait<'a>>(&self) -> Result<T, MessageDecodeError> {
+        decode_message_payload_with_format(self.header.format, self.payload_bytes)
+    }
+
+    /// Decodes payload into message of kind written in header.
+    pub fn decode(&self) -> Result<ZeromqMessage, MessageDecodeError> {
+        ZeromqMessage::decode_payload(self.header.kind, self.header.format, self.payload_bytes)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for MessageView<'a> {
+    type Error = MessageDecodeError;
+
+    fn try_from(message_bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::new(message_bytes)
+    }
+}
+
+#[cfg(feature = "zmq")]
+impl<'a> TryFrom<&'a zmq::Message> for MessageView<'a> {
+    type Error = MessageDecodeError;
+
+    fn try_from(message: &'a zmq::Message) -> Result<Self, Self::Error> {
+        Self::new(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::encode_directed_message;
+    use crate::codec::encode_message;
+    use crate::codec::MessageDecodeError;
+    use crate::codec::MessageHeader;
+    use crate::format::PayloadFormat;
+    use crate::kind::ZeromqMessageKind;
+    use crate::messages::ValueMultiplicationRequest;
+    use crate::messages::ValueMultiplicationResponse;
+    use crate::messages::ZeromqMessage;
+    use crate::view::MessageView;
+    #[cfg(feature = "zmq")]
+    use std::convert::TryFrom;
+    use uuid::Uuid;
+    use zmq::Message;
+
+    #[test]
+    fn basics() {
+        let payload = ValueMultiplicationRequest {
+            value: 5,
+            multiplier: 5,
+        };
+        let uuid = Uuid::new_v4();
+        let message_bytes =
+            encode_message(uuid, payload.clone()).expect("failed to encode message");
+
+        let view = MessageView::new(&message_bytes).expect("failed to create message view");
+        assert_eq!(ZeromqMessageKind::ValueMultiplicationRequest, view.kind());
+        assert_eq!(uuid, view.uuid());
+        assert_eq!(None, view.destination());
+        assert_eq!(PayloadFormat::Json, view.format());
+        assert_eq!(
+            &message_bytes[MessageHeader::LENGTH..],
+            view.payload_bytes()
+        );
+        assert_eq!(Ok(payload.clone()), view.decode_payload());
+        assert_eq!(Ok(ZeromqMessage::from(payload)), view.decode());
+        assert!(view
+            .decode_payload::<ValueMultiplicationResponse>()
+            .is_err());
+    }
+
+    #[test]
+    fn directed() {
+        let payload = ValueMultiplicationRequest {
+            value: 2,
+            multiplier: 3,
+        };
+        let message_bytes =
+            encode_directed_message(Uuid::new_v4(), "multiplier", payload.clone())
+                .expect("failed to encode message");
+
+        let view = MessageView::new(&message_bytes).expect("failed to create message view");
+        assert!(view.header().has_destination());
+        assert_eq!(Some("multiplier"), view.destination());
+        assert_eq!(Ok(payload), view.decode_payload());
+    }
+
+    #[test]
+    fn over_zmq_message() {
+        let uuid = Uuid::new_v4();
+        let message = Message::from(
+            encode_message(uuid, ValueMultiplicationResponse { result: 1 })
+                .expect("failed to encode message"),
+        );
+
+        let view = MessageView::new(&message).expect("failed to create message view");
+        assert_eq!(ZeromqMessageKind::ValueMultiplicationResponse, view.kind());
+        assert_eq!(uuid, view.uuid());
+
+        #[cfg(feature = "zmq")]
+        assert_eq!(Ok(view), MessageView::try_from(&message));
+
+        // Payload bytes are borrowed right from zmq message.
+        assert_eq!(
+            message[MessageHeader::LENGTH..].as_ptr(),
+            view.payload_bytes().as_ptr()
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(
+            Err(MessageDecodeError::Truncated {
+                expected: MessageHeader::LENGTH,
+                actual: 3,
+            }),
+            MessageView::new(&[1, 2, 3])
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::select;
+use uuid::Uuid;
+use zeromq_messages::codec::decode_message;
+use zeromq_messages::codec::decode_message_kind;
+use zeromq_messages::codec::decode_message_payload;
+use zeromq_messages::codec::decode_message_uuid;
+use zeromq_messages::codec::encode_directed_message_with_format;
+use zeromq_messages::codec::encode_message_with_format;
+use zeromq_messages::codec::peek_message_kind;
+use zeromq_messages::codec::MessageDecodeError;
+use zeromq_messages::codec::MessageHeader;
+use zeromq_messages::format::PayloadFormat;
+use zeromq_messages::kind::ZeromqMessageKind;
+use zeromq_messages::messages::ValueMultiplicationRequest;
+use zeromq_messages::messages::ValueMultiplicationResponse;
+use zeromq_messages::messages::ZeromqMessage;
+use zeromq_messages::view::MessageView;
+
+/// Formats enabled by features of the crate, JSON is always available.
+fn payload_formats() -> impl Strategy<Value = PayloadFormat> {
+    #[allow(unused_mut)]
+    let mut formats = vec![PayloadFormat::Json];
+    #[cfg(feature = "msgpack")]
+    formats.push(PayloadFormat::MessagePack);
+    #[cfg(feature = "bincode")]
+    formats.push(PayloadFormat::Bincode);
+
+    select(formats)
+}
+
+/// Runs whole decode chain, the same way as services do.
+fn decode_all(message_bytes: Vec<u8>) -> Result<(), MessageDecodeError> {
+    let _ = peek_message_kind(message_bytes.as_slice());
+    let _ = MessageHeader::decode(message_bytes.as_slice());
+    let _ = decode_message(message_bytes.as_slice());
+    if let Ok(message_view) = MessageView::new(message_bytes.as_slice()) {
+        let _ = message_view.decode();
+    }
+
+    let (_, remaining_bytes) = decode_message_kind(message_bytes)?;
+    let (_, remaining_bytes) = decode_message_uuid(remaining_bytes)?;
+    let _ = decode_message_payload::<'_, ValueMultiplicationResponse>(&remaining_bytes);
+    decode_message_payload::<'_, ValueMultiplicationRequest>(&remaining_bytes).map(|_| ())
+}
+
+fn valid_header_bytes() -> Vec<u8> {
+    let mut header_bytes = Vec::default();
+    MessageHeader::new(
+        ZeromqMessageKind::ValueMultiplicationRequest,
+        Uuid::nil(),
+        PayloadFormat::Json,
+    )
+    .encode(&mut header_bytes);
+    header_bytes
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_never_panic(message_bytes in vec(any::<u8>(), 0..256)) {
+        let _ = decode_all(message_bytes.clone());
+        let _ = decode_message_uuid(message_bytes.clone());
+        let _ = decode_message_payload::<'_, ValueMultiplicationRequest>(&message_bytes);
+    }
+
+    #[test]
+    fn arbitrary_bytes_after_valid_prefix_never_panic(
+        prefix_length in 0..=MessageHeader::LENGTH,
+        tail in vec(any::<u8>(), 0..128),
+    ) {
+        let mut message_bytes = valid_header_bytes();
+        message_bytes.truncate(prefix_length);
+        message_bytes.extend_from_slice(&tail);
+
+        let _ = decode_all(message_bytes);
+    }
+
+    #[test]
+    fn truncated_messages_are_rejected(
+        value in any::<i64>(),
+        multiplier in any::<i64>(),
+        format in payload_formats(),
+        cut in 0..MessageHeader::LENGTH,
+    ) {
+        let message_bytes = encode_message_with_format(
+            Uuid::new_v4(),
+            ValueMultiplicationRequest { value, multiplier },
+            format,
+        )
+        .expect("failed to encode message");
+
+        prop_assert_eq!(
+            Err(MessageDecodeError::Truncated {
+                expected: MessageHeader::LENGTH,
+                actual: cut,
+            }),
+            decode_all(message_bytes[..cut].to_vec())
+        );
+
+        // Cutting payload must produce an error too, but never a panic.
+        for payload_cut in MessageHeader::LENGTH..message_bytes.len() {
+            prop_assert!(decode_all(message_bytes[..payload_cut].to_vec()).is_err());
+        }
+    }
+
+    #[test]
+    fn round_trip(
+        value in any::<i64>(),
+        multiplier in any::<i64>(),
+        uuid in any::<u128>(),
+        format in payload_formats(),
+    ) {
+        let payload = ValueMultiplicationRequest { value, multiplier };
+        let message_bytes =
+            encode_message_with_format(Uuid::from_u128(uuid), payload.clone(), format)
+                .expect("failed to encode message");
+        let message_bytes_copy = message_bytes.clone();
+
+        let (_, remaining_bytes) = decode_message_kind(message_bytes)?;
+        let (decoded_uuid, remaining_bytes) = decode_message_uuid(remaining_bytes)?;
+        prop_assert_eq!(Uuid::from_u128(uuid), decoded_uuid);
+        prop_assert_eq!(Ok(payload.clone()), decode_message_payload(&remaining_bytes));
+
+        prop_assert_eq!(
+            Ok((Uuid::from_u128(uuid), ZeromqMessage::from(payload))),
+            decode_message(&message_bytes_copy)
+        );
+    }
+
+    #[test]
+    fn directed_round_trip(
+        value in any::<i64>(),
+        destination in "[a-z0-9-]{0,64}",
+        format in payload_formats(),
+    ) {
+        let payload = ValueMultiplicationResponse { result: value };
+        let message_bytes = encode_directed_message_with_format(
+            Uuid::new_v4(),
+            &destination,
+            payload.clone(),
+            format,
+        )
+        .expect("failed to encode message");
+
+        let message_view = MessageView::new(&message_bytes)?;
+        prop_assert_eq!(Some(destination.as_str()), message_view.destination());
+        prop_assert_eq!(Ok(payload), message_view.decode_payload());
+    }
+}